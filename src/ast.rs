@@ -23,6 +23,8 @@ pub enum LogicProp {
     Binary(BinaryProp),
     Unary(UnaryProp),
     Atomic(AtomicProp),
+    /// Truth constant: `true` or `false`
+    Const(bool),
 }
 
 /// Quantified propositions over points and opens
@@ -36,6 +38,14 @@ pub enum QuantProp {
     AO(String, Box<Prop>),
     /// Existential quantification over opens: ∃X. φ
     EO(String, Box<Prop>),
+    /// Universal quantification over topens: ∀T. φ
+    AT(String, Box<Prop>),
+    /// Existential quantification over topens: ∃T. φ
+    ET(String, Box<Prop>),
+    /// Bounded universal quantification over points: ∀p ∈ X. φ, i.e. ∀p. p ∈ X ⇒ φ
+    APIn(String, OpenExpr, Box<Prop>),
+    /// Bounded existential quantification over points: ∃p ∈ X. φ, i.e. ∃p. p ∈ X ∧ φ
+    EPIn(String, OpenExpr, Box<Prop>),
 }
 
 /// Binary logical operators with standard semantics
@@ -49,6 +59,8 @@ pub enum BinaryProp {
     Implies(Box<Prop>, Box<Prop>),
     /// Material equivalence: φ ↔ ψ
     Iff(Box<Prop>, Box<Prop>),
+    /// Exclusive or: φ ⊕ ψ
+    Xor(Box<Prop>, Box<Prop>),
 }
 
 /// Unary logical operators
@@ -75,6 +87,10 @@ pub enum AtomicProp {
     PointEqual(PointExpr, PointExpr),
     /// Open equality: X = Y
     OpenEqual(OpenExpr, OpenExpr),
+    /// Open inclusion: X ⊆ Y
+    Subset(OpenExpr, OpenExpr),
+    /// Direct topen test: X ≠ ∅ and X is transitive
+    IsTopen(OpenExpr),
 }
 
 /// Point expressions - represent individual elements
@@ -82,6 +98,8 @@ pub enum AtomicProp {
 pub enum PointExpr {
     /// Point variable: p, q, x, etc.
     PointVar(String),
+    /// Point literal: 1, 2, etc. (1-based, checked against n when evaluated)
+    PointLit(usize),
 }
 
 /// Open expressions - represent sets in the semitopology  
@@ -91,8 +109,24 @@ pub enum OpenExpr {
     OpenVar(String),
     /// Community of a point: K(p)
     K(PointExpr),
+    /// Minimal open neighbourhood of a point: MON(p)
+    Mon(PointExpr),
     /// Interior complement: IC(X)
     IC(Box<OpenExpr>),
+    /// Closure: Cl(X)
+    Cl(Box<OpenExpr>),
+    /// Boundary: Bd(X)
+    Bd(Box<OpenExpr>),
+    /// Union: X union Y
+    Union(Box<OpenExpr>, Box<OpenExpr>),
+    /// Intersection: X cap Y (the set, unlike the `inter` predicate)
+    Inter(Box<OpenExpr>, Box<OpenExpr>),
+    /// Set literal: {1, 3} (points checked against n when evaluated)
+    Lit(Vec<usize>),
+    /// All points: UNIV
+    Univ,
+    /// No points: EMPTY
+    Empty,
 }
 
 /// Macro propositions - high-level constructs that expand to complex formulas
@@ -102,12 +136,12 @@ pub enum OpenExpr {
 #[derive(Debug, Clone, PartialEq)]
 pub enum MacroProp {
     // Intersection macros
-    /// Triple open intersection: X ∩ Y ∩ Z
-    TripleOpenInter(OpenExpr, OpenExpr, OpenExpr),
+    /// Chained open intersection of three or more opens: X1 ∩ X2 ∩ ... ∩ Xk
+    NaryOpenInter(Vec<OpenExpr>),
     /// Point intersection: p ∩ q  
     PointInter(PointExpr, PointExpr),
-    /// Triple point intersection: p ∩ q ∩ r
-    TriplePointInter(PointExpr, PointExpr, PointExpr),
+    /// Chained point intersection of three or more points: p1 ∩ p2 ∩ ... ∩ pk
+    NaryPointInter(Vec<PointExpr>),
     
     // Topological predicates for opens
     /// Transitivity: ∀O,P. (O∩T ∧ T∩P) → O∩P
@@ -150,4 +184,23 @@ pub enum MacroProp {
     IndirectlyRegularSpace,
     /// Every point is hypertransitive
     HypertransitiveSpace,
+    /// Use of a user definition: name(p, X, ...)
+    Call(String, Vec<MacroArg>),
+}
+
+/// Arguments to a user definition, a point or an open according to the parameter
+#[derive(Debug, Clone, PartialEq)]
+pub enum MacroArg {
+    Point(PointExpr),
+    Open(OpenExpr),
+}
+
+/// User definition: `def name(p, X) := φ;`
+///
+/// Parameters are points or opens by the case of their first letter, as for variables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Prop,
 }
\ No newline at end of file
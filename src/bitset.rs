@@ -0,0 +1,262 @@
+//! Point sets: fixed-width bitmasks for small n and an arbitrary-width bitset beyond.
+
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/// A subset of the points {1..n}, point i being bit i-1
+///
+/// Sets are ordered like the integers their bits spell out, so a family iterates in
+/// the same order whichever representation holds it.
+pub trait PointSet: Clone + Eq + Ord + Hash + Debug + Send + Sync {
+    /// Largest n this representation can hold
+    const MAX_POINTS: usize;
+
+    /// The empty set
+    fn empty() -> Self;
+    /// All points {1..n}
+    fn full(n: usize) -> Self;
+    /// Whether bit i (i.e. point i+1) is set
+    fn contains(&self, i: usize) -> bool;
+    /// Set bit i (i.e. point i+1)
+    fn insert(&mut self, i: usize);
+    fn is_empty(&self) -> bool;
+    /// The largest point (1-based) in the set, or 0 if it is empty
+    fn max_point(&self) -> usize;
+    fn union(&self, other: &Self) -> Self;
+    fn intersection(&self, other: &Self) -> Self;
+    /// The points of `self` not in `other`
+    fn difference(&self, other: &Self) -> Self;
+
+    fn union_with(&mut self, other: &Self) {
+        *self = self.union(other);
+    }
+
+    fn intersects(&self, other: &Self) -> bool {
+        !self.intersection(other).is_empty()
+    }
+
+    fn is_subset(&self, other: &Self) -> bool {
+        self.difference(other).is_empty()
+    }
+}
+
+macro_rules! impl_point_set_for_uint {
+    ($t:ty) => {
+        impl PointSet for $t {
+            const MAX_POINTS: usize = <$t>::BITS as usize;
+
+            fn empty() -> Self {
+                0
+            }
+
+            fn full(n: usize) -> Self {
+                if n >= Self::MAX_POINTS { <$t>::MAX } else { (1 << n) - 1 }
+            }
+
+            fn contains(&self, i: usize) -> bool {
+                i < Self::MAX_POINTS && (self >> i) & 1 == 1
+            }
+
+            fn insert(&mut self, i: usize) {
+                *self |= 1 << i;
+            }
+
+            fn is_empty(&self) -> bool {
+                *self == 0
+            }
+
+            fn max_point(&self) -> usize {
+                (<$t>::BITS - self.leading_zeros()) as usize
+            }
+
+            fn union(&self, other: &Self) -> Self {
+                self | other
+            }
+
+            fn intersection(&self, other: &Self) -> Self {
+                self & other
+            }
+
+            fn difference(&self, other: &Self) -> Self {
+                self & !other
+            }
+
+            fn union_with(&mut self, other: &Self) {
+                *self |= other;
+            }
+
+            fn intersects(&self, other: &Self) -> bool {
+                self & other != 0
+            }
+
+            fn is_subset(&self, other: &Self) -> bool {
+                self & !other == 0
+            }
+        }
+    };
+}
+
+impl_point_set_for_uint!(u32);
+impl_point_set_for_uint!(u64);
+
+/// An arbitrary-width set of points stored in 64-bit blocks, lowest points first
+///
+/// Trailing zero blocks are never stored, so equal sets have equal representations.
+#[derive(Clone, Default, PartialEq, Eq, Hash, Debug)]
+pub struct BitSet {
+    blocks: Vec<u64>,
+}
+
+impl BitSet {
+    fn trimmed(mut blocks: Vec<u64>) -> Self {
+        while blocks.last() == Some(&0) {
+            blocks.pop();
+        }
+        Self { blocks }
+    }
+}
+
+impl Ord for BitSet {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.blocks.len().cmp(&other.blocks.len())
+            .then_with(|| self.blocks.iter().rev().cmp(other.blocks.iter().rev()))
+    }
+}
+
+impl PartialOrd for BitSet {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PointSet for BitSet {
+    const MAX_POINTS: usize = usize::MAX;
+
+    fn empty() -> Self {
+        Self::default()
+    }
+
+    fn full(n: usize) -> Self {
+        let mut blocks = vec![u64::MAX; n / 64];
+        if !n.is_multiple_of(64) {
+            blocks.push((1 << (n % 64)) - 1);
+        }
+        Self { blocks }
+    }
+
+    fn contains(&self, i: usize) -> bool {
+        self.blocks.get(i / 64).is_some_and(|b| (b >> (i % 64)) & 1 == 1)
+    }
+
+    fn insert(&mut self, i: usize) {
+        if self.blocks.len() <= i / 64 {
+            self.blocks.resize(i / 64 + 1, 0);
+        }
+        self.blocks[i / 64] |= 1 << (i % 64);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    fn max_point(&self) -> usize {
+        match self.blocks.last() {
+            Some(last) => self.blocks.len() * 64 - last.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        let (long, short) = if self.blocks.len() >= other.blocks.len() { (self, other) } else { (other, self) };
+        let mut blocks = long.blocks.clone();
+        for (b, s) in blocks.iter_mut().zip(&short.blocks) {
+            *b |= s;
+        }
+        Self { blocks }
+    }
+
+    fn intersection(&self, other: &Self) -> Self {
+        Self::trimmed(self.blocks.iter().zip(&other.blocks).map(|(a, b)| a & b).collect())
+    }
+
+    fn difference(&self, other: &Self) -> Self {
+        let blocks = self.blocks.iter()
+            .enumerate()
+            .map(|(i, a)| a & !other.blocks.get(i).copied().unwrap_or(0))
+            .collect();
+        Self::trimmed(blocks)
+    }
+
+    fn union_with(&mut self, other: &Self) {
+        if self.blocks.len() < other.blocks.len() {
+            self.blocks.resize(other.blocks.len(), 0);
+        }
+        for (b, o) in self.blocks.iter_mut().zip(&other.blocks) {
+            *b |= o;
+        }
+    }
+
+    fn intersects(&self, other: &Self) -> bool {
+        self.blocks.iter().zip(&other.blocks).any(|(a, b)| a & b != 0)
+    }
+
+    fn is_subset(&self, other: &Self) -> bool {
+        self.blocks.iter()
+            .enumerate()
+            .all(|(i, a)| a & !other.blocks.get(i).copied().unwrap_or(0) == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_points<S: PointSet>(points: &[usize]) -> S {
+        let mut s = S::empty();
+        for &p in points {
+            s.insert(p - 1);
+        }
+        s
+    }
+
+    #[test]
+    fn test_bitset_matches_u64() {
+        let samples: [&[usize]; 5] = [&[], &[1], &[1, 2, 64], &[3, 17, 40], &[2, 3, 64]];
+        for a in samples {
+            for b in samples {
+                let (wa, wb): (BitSet, BitSet) = (from_points(a), from_points(b));
+                let (na, nb): (u64, u64) = (from_points(a), from_points(b));
+                let to_points = |s: &BitSet| (0..64).filter(|&i| s.contains(i)).fold(0u64, |acc, i| acc | 1 << i);
+
+                assert_eq!(to_points(&wa.union(&wb)), na | nb);
+                assert_eq!(to_points(&wa.intersection(&wb)), na & nb);
+                assert_eq!(to_points(&wa.difference(&wb)), na & !nb);
+                assert_eq!(wa.intersects(&wb), na.intersects(&nb));
+                assert_eq!(wa.is_subset(&wb), na.is_subset(&nb));
+                assert_eq!(wa.cmp(&wb), na.cmp(&nb));
+                assert_eq!(wa.max_point(), na.max_point());
+            }
+        }
+    }
+
+    #[test]
+    fn test_bitset_beyond_64_points() {
+        let full = BitSet::full(130);
+        assert_eq!((0..200).filter(|&i| full.contains(i)).count(), 130);
+        assert!(full.contains(129) && !full.contains(130));
+        assert_eq!(full.max_point(), 130);
+
+        let high: BitSet = from_points(&[100]);
+        let low: BitSet = from_points(&[1]);
+        assert!(high > low);
+        assert!(high.is_subset(&full));
+        assert!(!high.intersects(&low));
+
+        // Removing the high point leaves no trailing blocks behind
+        let both = high.union(&low);
+        assert_eq!(both.difference(&high), low);
+        assert!(both.difference(&both).is_empty());
+        assert_eq!(both.difference(&both), BitSet::empty());
+    }
+}
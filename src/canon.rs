@@ -4,30 +4,41 @@ use nauty_Traces_sys::{
     densenauty, 
     optionblk, statsblk, setword, graph, SETWORDSNEEDED,
 };
-use std::collections::{HashMap, HashSet, BTreeSet};
+use crate::bitset::PointSet;
+use dashmap::DashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, BTreeSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::io::{BufRead, Write};
 use std::os::raw::c_int;
 
+/// A subset of the points {1..n} as a bitmask, point i being bit i-1
+pub type Elem = u64;
+
+/// Largest n representable by an `Elem`
+pub const MAX_POINTS: usize = <Elem as PointSet>::MAX_POINTS;
+
 /// A family of subsets represented as a set of bitmasks
-pub type Family = BTreeSet<u32>;
+pub type Family = BTreeSet<Elem>;
+
+/// The bitmask of all points {1..n}
+pub fn full_set(n: usize) -> Elem {
+    Elem::full(n)
+}
 
 /// Converts a bitmask back to a set of 1-based integers
-fn int_to_set(i: u32, n: usize) -> HashSet<usize> {
+fn int_to_set<S: PointSet>(i: &S, n: usize) -> HashSet<usize> {
     let mut s = HashSet::new();
     for j in 0..n {
-        if (i >> j) & 1 == 1 {
+        if i.contains(j) {
             s.insert(j + 1);
         }
     }
     s
 }
 
-/// Creates a human-readable string representation of a family of sets
-pub fn family_to_str(family: &Family, n: usize) -> String {
-    if family.is_empty() {
-        return "{}".to_string();
-    }
-    
-    let mut sorted_ints: Vec<u32> = family.iter().cloned().collect();
+/// The members of a family as sorted lists of points, smallest sets first
+fn sorted_set_lists<S: PointSet>(family: &BTreeSet<S>, n: usize) -> Vec<Vec<usize>> {
+    let mut sorted_ints: Vec<&S> = family.iter().collect();
     sorted_ints.sort();
     
     let mut set_list: Vec<Vec<usize>> = sorted_ints
@@ -40,6 +51,16 @@ pub fn family_to_str(family: &Family, n: usize) -> String {
         .collect();
     
     set_list.sort_by_key(|s| (s.len(), s.clone()));
+    set_list
+}
+
+/// Creates a human-readable string representation of a family of sets
+pub fn family_to_str<S: PointSet>(family: &BTreeSet<S>, n: usize) -> String {
+    if family.is_empty() {
+        return "{}".to_string();
+    }
+    
+    let set_list = sorted_set_lists(family, n);
     
     let set_strings: Vec<String> = set_list
         .iter()
@@ -55,8 +76,164 @@ pub fn family_to_str(family: &Family, n: usize) -> String {
     format!("{{{}}}", set_strings.join(", "))
 }
 
+/// Renders a family as a one-line JSON object, e.g. `{"n": 3, "sets": [[], [1], [1, 2]]}`,
+/// with the sets in the same order as `family_to_str`
+pub fn family_to_json<S: PointSet>(family: &BTreeSet<S>, n: usize) -> String {
+    let sets: Vec<String> = sorted_set_lists(family, n)
+        .iter()
+        .map(|s| format!("[{}]", s.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", ")))
+        .collect();
+    format!("{{\"n\": {}, \"sets\": [{}]}}", n, sets.join(", "))
+}
+
+/// Renders the point-vs-open incidence graph of a family (the one `build_dense_bipartite`
+/// hands to nauty) as Graphviz DOT: points on one rank, opens on another below them, and
+/// an edge from each point to every open containing it
+pub fn family_to_dot<S: PointSet>(family: &BTreeSet<S>, n: usize) -> String {
+    let sets = sorted_set_lists(family, n);
+    let mut dot = String::from("graph family {\n");
+    let points: Vec<String> = (1..=n).map(|p| format!("p{} [label=\"{}\"];", p, p)).collect();
+    dot += &format!("  {{ rank=same; node [shape=circle]; {} }}\n", points.join(" "));
+    let opens: Vec<String> = sets.iter().enumerate()
+        .map(|(k, s)| {
+            let label = s.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", ");
+            format!("o{} [label=\"{{{}}}\"];", k, label)
+        })
+        .collect();
+    dot += &format!("  {{ rank=same; node [shape=box]; {} }}\n", opens.join(" "));
+    for (k, s) in sets.iter().enumerate() {
+        for p in s {
+            dot += &format!("  p{} -- o{};\n", p, k);
+        }
+    }
+    dot + "}\n"
+}
+
+/// Encodes the incidence graph of a family (points 1..=n as vertices 0..n, then the opens
+/// in `family_to_str` order) as a graph6 string, for nauty tools such as `showg`
+pub fn family_to_graph6<S: PointSet>(family: &BTreeSet<S>, n: usize) -> String {
+    let sets = sorted_set_lists(family, n);
+    let v = n + sets.len();
+    let adjacent = |i: usize, j: usize| j >= n && i < n && sets[j - n].contains(&(i + 1));
+
+    // N(v): one byte up to 62 vertices, then 126 and 18 bits, then 126, 126 and 36 bits
+    let mut bytes: Vec<u8> = Vec::new();
+    let size_groups = match v {
+        0..=62 => 1,
+        63..=258047 => { bytes.push(126); 3 }
+        _ => { bytes.extend([126, 126]); 6 }
+    };
+    if size_groups == 1 {
+        bytes.push(v as u8 + 63);
+    } else {
+        bytes.extend((0..size_groups).rev().map(|k| ((v >> (6 * k)) & 63) as u8 + 63));
+    }
+
+    // The upper triangle column by column, six bits to a byte
+    let bits: Vec<bool> = (1..v).flat_map(|j| (0..j).map(move |i| (i, j))).map(|(i, j)| adjacent(i, j)).collect();
+    for chunk in bits.chunks(6) {
+        let value = chunk.iter().enumerate().fold(0u8, |acc, (k, &bit)| acc | (u8::from(bit) << (5 - k)));
+        bytes.push(value + 63);
+    }
+    String::from_utf8(bytes).expect("graph6 bytes are printable ASCII")
+}
+
+/// Decodes a graph6 string written by `family_to_graph6`, given the number of points n
+/// (graph6 does not record which vertices are points); every edge must join one of the
+/// first n vertices to one of the others
+pub fn graph6_to_family(graph6: &str, n: usize) -> Result<Family, String> {
+    let graph6 = graph6.trim();
+    let bytes: Vec<u8> = graph6.bytes().collect();
+    if bytes.iter().any(|&b| !(63..=126).contains(&b)) {
+        return Err(format!("Invalid graph6 character in: {}", graph6));
+    }
+    let value = |group: &[u8]| group.iter().fold(0usize, |acc, &b| (acc << 6) | (b - 63) as usize);
+    let (v, body) = match bytes.as_slice() {
+        [126, 126, rest @ ..] if rest.len() >= 6 => (value(&rest[..6]), &rest[6..]),
+        [126, rest @ ..] if rest.len() >= 3 => (value(&rest[..3]), &rest[3..]),
+        [first, rest @ ..] if *first != 126 => ((first - 63) as usize, rest),
+        _ => return Err(format!("Truncated graph6 size in: {}", graph6)),
+    };
+    if v < n {
+        return Err(format!("graph6 graph has {} vertices, fewer than the {} points", v, n));
+    }
+    if n > MAX_POINTS {
+        return Err(format!("Size n={} exceeds the maximum of {} points", n, MAX_POINTS));
+    }
+    let bit_count = v.checked_mul(v.saturating_sub(1))
+        .ok_or_else(|| format!("graph6 graph has too many vertices ({}): {}", v, graph6))? / 2;
+    if body.len() != bit_count.div_ceil(6) {
+        return Err(format!("graph6 string has the wrong length for {} vertices: {}", v, graph6));
+    }
+
+    let mut sets: Vec<Elem> = vec![0; v - n];
+    let pairs = (1..v).flat_map(|j| (0..j).map(move |i| (i, j)));
+    for (k, (i, j)) in pairs.enumerate() {
+        if (body[k / 6] - 63) >> (5 - k % 6) & 1 == 0 {
+            continue;
+        }
+        if i >= n || j < n {
+            return Err(format!("graph6 edge {}-{} does not join a point to an open", i, j));
+        }
+        sets[j - n] |= 1 << i;
+    }
+    Ok(sets.into_iter().collect())
+}
+
+/// Parses a JSON object as written by `family_to_json` back into a family and its size
+///
+/// Only this shape is accepted: an object with a numeric `n` and a `sets` array of
+/// arrays of points, in either order.
+pub fn parse_family_json<S: PointSet>(line: &str) -> Result<(BTreeSet<S>, usize), String> {
+    let compact: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    let inner = compact.strip_prefix('{').and_then(|rest| rest.strip_suffix('}'))
+        .ok_or_else(|| format!("Family JSON must be an object: {}", line.trim()))?;
+
+    // Split the two members at the comma outside any brackets
+    let mut depth = 0;
+    let split = inner.char_indices().find(|&(_, c)| {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            _ => {}
+        }
+        c == ',' && depth == 0
+    });
+    let (first, second) = match split {
+        Some((i, _)) => (&inner[..i], &inner[i + 1..]),
+        None => return Err(format!("Family JSON needs \"n\" and \"sets\": {}", line.trim())),
+    };
+    let (mut n_value, mut sets_value) = (None, None);
+    for member in [first, second] {
+        if let Some(value) = member.strip_prefix("\"n\":") {
+            n_value = Some(value);
+        } else if let Some(value) = member.strip_prefix("\"sets\":") {
+            sets_value = Some(value);
+        }
+    }
+    let (n_value, sets_value) = n_value.zip(sets_value)
+        .ok_or_else(|| format!("Family JSON needs \"n\" and \"sets\": {}", line.trim()))?;
+
+    let n: usize = n_value.parse().map_err(|_| format!("Invalid size in family JSON: {}", n_value))?;
+    if n > S::MAX_POINTS {
+        return Err(format!("Size n={} exceeds the maximum of {} points", n, S::MAX_POINTS));
+    }
+    let sets = sets_value.strip_prefix('[').and_then(|rest| rest.strip_suffix(']'))
+        .ok_or_else(|| format!("\"sets\" must be an array: {}", sets_value))?;
+
+    let mut family = BTreeSet::new();
+    if !sets.is_empty() {
+        let sets = sets.strip_prefix('[').and_then(|rest| rest.strip_suffix(']'))
+            .ok_or_else(|| format!("Each set must be an array: {}", sets))?;
+        for set in sets.split("],[") {
+            family.insert(parse_single_set(&format!("{{{}}}", set), n)?);
+        }
+    }
+    Ok((family, n))
+}
+
 /// Parses a family string like "{{1, 2}, {1, 3}, {2, 3}, {1, 2, 3}}" into a Family
-pub fn parse_family_str(family_str: &str, n: usize) -> Result<Family, String> {
+pub fn parse_family_str<S: PointSet>(family_str: &str, n: usize) -> Result<BTreeSet<S>, String> {
     let mut family = BTreeSet::new();
     
     // Remove outer braces and whitespace
@@ -71,17 +248,19 @@ pub fn parse_family_str(family_str: &str, n: usize) -> Result<Family, String> {
     }
     
     // Parse individual sets
-    let mut chars = inner.chars().peekable();
     let mut current_set = String::new();
     let mut brace_count = 0;
     
-    while let Some(ch) = chars.next() {
+    for ch in inner.chars() {
         match ch {
             '{' => {
                 brace_count += 1;
                 current_set.push(ch);
             }
             '}' => {
+                if brace_count == 0 {
+                    return Err(format!("Unmatched '}}' in family: {}", trimmed));
+                }
                 brace_count -= 1;
                 current_set.push(ch);
                 if brace_count == 0 {
@@ -89,26 +268,212 @@ pub fn parse_family_str(family_str: &str, n: usize) -> Result<Family, String> {
                     let set_mask = parse_single_set(&current_set, n)?;
                     family.insert(set_mask);
                     current_set.clear();
-                    
-                    // Skip comma and whitespace
-                    while chars.peek() == Some(&',') || chars.peek() == Some(&' ') {
-                        chars.next();
-                    }
                 }
             }
             _ => {
                 if brace_count > 0 {
                     current_set.push(ch);
+                } else if ch != ',' && !ch.is_whitespace() {
+                    // Only separators may appear between sets
+                    return Err(format!("Unexpected '{}' between sets in family: {}", ch, trimmed));
                 }
             }
         }
     }
+    if brace_count > 0 {
+        return Err(format!("Unclosed '{{' in family: {}", trimmed));
+    }
     
     Ok(family)
 }
 
+/// Parses one line of family input: an optional leading `n=<k>` token followed by a
+/// family in brace notation, e.g. `n=5 {{1, 2}, {3}}`.
+///
+/// The size is taken from the `n=<k>` token if present, then from `default_n`, and is
+/// otherwise inferred from the largest element.
+pub fn parse_family_line<S: PointSet>(line: &str, default_n: Option<usize>) -> Result<(BTreeSet<S>, usize), String> {
+    let trimmed = line.trim();
+    let (line_n, family_str) = match trimmed.strip_prefix("n=") {
+        Some(rest) => {
+            let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            let n: usize = rest[..end].parse()
+                .map_err(|_| format!("Invalid size prefix in: {}", trimmed))?;
+            (Some(n), &rest[end..])
+        }
+        None => (None, trimmed),
+    };
+
+    let n = match line_n.or(default_n) {
+        Some(n) => n,
+        None => infer_size_from_family(&parse_family_str::<S>(family_str, S::MAX_POINTS)?),
+    };
+    if n == 0 {
+        return Err("Could not determine size n. Please specify it (e.g. with n=<k>) or ensure family contains at least one non-empty set.".to_string());
+    }
+    if n > S::MAX_POINTS {
+        return Err(format!("Size n={} exceeds the maximum of {} points", n, S::MAX_POINTS));
+    }
+
+    let family = parse_family_str(family_str, n)?;
+    Ok((family, n))
+}
+
+/// Loads a file of families (one per line, optionally `n=<k>`-prefixed) as the set of
+/// their canonical forms
+pub fn load_canonical_families(path: &str) -> Result<HashSet<Family>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path, e))?;
+    let mut families = HashSet::new();
+    for (line_no, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (family, n) = parse_family_line(line, None)
+            .map_err(|e| format!("{}:{}: {}", path, line_no + 1, e))?;
+        families.insert(canonicalize_once(&family, n));
+    }
+    Ok(families)
+}
+
+/// Writes the canonical form of each family read from `input` (one per line, optionally
+/// `n=<k>`-prefixed, blank lines skipped) to `output`, one per line, closing each under
+/// unions first if `close` is set; n is taken from the prefix, then `size`, then inferred.
+/// Returns the number of families written.
+pub fn canonicalize_lines(input: impl BufRead, mut output: impl Write, size: Option<usize>, close: bool) -> Result<usize, String> {
+    let mut count = 0;
+    for (line_no, line) in input.lines().enumerate() {
+        let line = line.map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (mut family, n) = parse_family_line::<Elem>(&line, size)
+            .map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+        if close {
+            family = union_closure(&family, n);
+        }
+        writeln!(output, "{}", family_to_str(&canonicalize_once(&family, n), n))
+            .map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Serializes a family as its sets' sorted point lists, in `family_to_str` order, e.g.
+/// `[[], [1], [1, 2]]`; for use with `#[serde(serialize_with = "serialize_family")]`,
+/// since `Family` is an alias of a foreign type
+#[cfg(feature = "serde")]
+pub fn serialize_family<Ser: serde::Serializer>(family: &Family, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+    serde::Serialize::serialize(&sorted_set_lists(family, MAX_POINTS), serializer)
+}
+
+/// Deserializes a family written by `serialize_family`
+#[cfg(feature = "serde")]
+pub fn deserialize_family<'de, De: serde::Deserializer<'de>>(deserializer: De) -> Result<Family, De::Error> {
+    let sets: Vec<Vec<usize>> = serde::Deserialize::deserialize(deserializer)?;
+    sets.iter()
+        .map(|points| points.iter().try_fold(0 as Elem, |mask, &p| match p {
+            1..=MAX_POINTS => Ok(mask | (1 << (p - 1))),
+            _ => Err(serde::de::Error::custom(format!("point {} is out of range 1..={}", p, MAX_POINTS))),
+        }))
+        .collect()
+}
+
+/// `serialize_family` and `deserialize_family` for an optional family, as a module for
+/// `#[serde(with = "crate::canon::optional_family")]`
+#[cfg(feature = "serde")]
+pub mod optional_family {
+    use super::Family;
+
+    pub fn serialize<Ser: serde::Serializer>(family: &Option<Family>, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        struct Sets<'a>(&'a Family);
+        impl serde::Serialize for Sets<'_> {
+            fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+                super::serialize_family(self.0, serializer)
+            }
+        }
+        serde::Serialize::serialize(&family.as_ref().map(Sets), serializer)
+    }
+
+    pub fn deserialize<'de, De: serde::Deserializer<'de>>(deserializer: De) -> Result<Option<Family>, De::Error> {
+        struct Sets(Family);
+        impl<'de> serde::Deserialize<'de> for Sets {
+            fn deserialize<De: serde::Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+                super::deserialize_family(deserializer).map(Sets)
+            }
+        }
+        let sets: Option<Sets> = serde::Deserialize::deserialize(deserializer)?;
+        Ok(sets.map(|Sets(family)| family))
+    }
+}
+
+/// Line formats that `convert_lines` reads and writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FamilyFormat {
+    /// Brace notation, optionally `n=<k>`-prefixed, as produced by `family_to_str`
+    Braces,
+    /// One object per line, as produced by `family_to_json`
+    Json,
+    /// The incidence graph in graph6, as produced by `family_to_graph6`
+    Graph6,
+}
+
+impl std::str::FromStr for FamilyFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "braces" => Ok(FamilyFormat::Braces),
+            "json" => Ok(FamilyFormat::Json),
+            "graph6" => Ok(FamilyFormat::Graph6),
+            _ => Err(format!("Unknown family format '{}' (expected 'braces', 'json' or 'graph6')", s)),
+        }
+    }
+}
+
+impl FamilyFormat {
+    /// Parse one line; n comes from the line itself where the format carries it, then
+    /// from `size`, and for braces is otherwise inferred
+    pub fn parse(self, line: &str, size: Option<usize>) -> Result<(Family, usize), String> {
+        match self {
+            FamilyFormat::Braces => parse_family_line(line, size),
+            FamilyFormat::Json => parse_family_json(line),
+            FamilyFormat::Graph6 => {
+                let n = size.ok_or("Reading graph6 needs the number of points n")?;
+                graph6_to_family(line, n).map(|family| (family, n))
+            }
+        }
+    }
+
+    pub fn render(self, family: &Family, n: usize) -> String {
+        match self {
+            FamilyFormat::Braces => family_to_str(family, n),
+            FamilyFormat::Json => family_to_json(family, n),
+            FamilyFormat::Graph6 => family_to_graph6(family, n),
+        }
+    }
+}
+
+/// Rewrites each family read from `input` (one per line, blank lines skipped) from one
+/// format into another, returning the number of families written
+pub fn convert_lines(input: impl BufRead, mut output: impl Write, from: FamilyFormat, to: FamilyFormat, size: Option<usize>) -> Result<usize, String> {
+    let mut count = 0;
+    for (line_no, line) in input.lines().enumerate() {
+        let line = line.map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (family, n) = from.parse(&line, size)
+            .map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+        writeln!(output, "{}", to.render(&family, n))
+            .map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
 /// Parses a single set string like "{1, 2, 3}" into a bitmask
-fn parse_single_set(set_str: &str, n: usize) -> Result<u32, String> {
+pub fn parse_single_set<S: PointSet>(set_str: &str, n: usize) -> Result<S, String> {
     let trimmed = set_str.trim();
     if !trimmed.starts_with('{') || !trimmed.ends_with('}') {
         return Err(format!("Set must be enclosed in braces: {}", set_str));
@@ -116,25 +481,41 @@ fn parse_single_set(set_str: &str, n: usize) -> Result<u32, String> {
     
     let inner = &trimmed[1..trimmed.len()-1].trim();
     if inner.is_empty() {
-        return Ok(0); // Empty set
+        return Ok(S::empty()); // Empty set
     }
     
-    let mut mask = 0u32;
-    for element_str in inner.split(',') {
+    let parse_element = |element_str: &str| -> Result<usize, String> {
         let element: usize = element_str.trim().parse()
             .map_err(|_| format!("Invalid element: {}", element_str))?;
-        if element == 0 || element > n {
+        if element == 0 || element > n || element > S::MAX_POINTS {
             return Err(format!("Element {} is out of range for n={}", element, n));
         }
-        let bit_pos = element - 1;
-        mask |= 1u32 << bit_pos;
+        Ok(element)
+    };
+
+    let mut mask = S::empty();
+    for element_str in inner.split(',') {
+        // A token `a-b` stands for the points a to b inclusive
+        let (first, last) = match element_str.split_once('-') {
+            Some((a, b)) => (parse_element(a)?, parse_element(b)?),
+            None => {
+                let element = parse_element(element_str)?;
+                (element, element)
+            }
+        };
+        if first > last {
+            return Err(format!("Empty range: {}", element_str.trim()));
+        }
+        for element in first..=last {
+            mask.insert(element - 1);
+        }
     }
     
     Ok(mask)
 }
 
 /// Builds a bipartite graph for nauty with element vertices and set vertices
-fn build_dense_bipartite(sets: &[u32], n: usize) -> (Vec<setword>, usize) {
+fn build_dense_bipartite(sets: &[Elem], n: usize) -> (Vec<setword>, usize) {
     let v = n + sets.len();
     let m = SETWORDSNEEDED(v);
 
@@ -159,8 +540,9 @@ fn build_dense_bipartite(sets: &[u32], n: usize) -> (Vec<setword>, usize) {
     (g, m)
 }
 
-/// Computes canonical permutation using nauty
-fn canon_permutation(sets: &[u32], n: usize) -> Vec<usize> {
+/// Computes canonical permutation using nauty, along with the order of the automorphism
+/// group of the coloured graph (i.e. of the family)
+fn canon_permutation(sets: &[Elem], n: usize) -> (Vec<usize>, f64) {
     let (mut g, m) = build_dense_bipartite(sets, n);
     let v = n + sets.len();
 
@@ -209,23 +591,15 @@ fn canon_permutation(sets: &[u32], n: usize) -> Vec<usize> {
         );
     }
 
-    lab.iter().map(|&x| x as usize).collect()
+    let group_order = stats.grpsize1 * 10f64.powi(stats.grpsize2);
+    (lab.iter().map(|&x| x as usize).collect(), group_order)
 }
 
-/// Canonicalizes a family using nauty with caching
-pub fn canonicalize(family: &Family, n: usize, cache: &mut HashMap<Family, Family>, max_cache_size: usize) -> Family {
-    if family.is_empty() {
-        return BTreeSet::new();
-    }
-    
-    if max_cache_size > 0 {
-        if let Some(cached) = cache.get(family) {
-            return cached.clone();
-        }
-    }
-
-    let sets: Vec<u32> = family.iter().cloned().collect();
-    let canonical_labeling = canon_permutation(&sets, n);
+/// Where each point of a nonempty family goes in its canonical form: bit i moves to bit
+/// `canonical_positions(family, n).0[i]`; also returns the automorphism group order
+fn canonical_positions(family: &Family, n: usize) -> (Vec<usize>, f64) {
+    let sets: Vec<Elem> = family.iter().cloned().collect();
+    let (canonical_labeling, group_order) = canon_permutation(&sets, n);
     
     // Python code does: element_permutation = canonical_labeling[:n]
     // This should be a permutation of [0, 1, ..., n-1]
@@ -239,22 +613,156 @@ pub fn canonicalize(family: &Family, n: usize, cache: &mut HashMap<Family, Famil
         panic!("Invalid element permutation: {:?}", element_permutation);
     }
     
-    let mut canonical_family = BTreeSet::new();
-    for &s_int in family {
-        let mut new_s_int = 0u32;
-        for i in 0..n {
-            if (s_int >> i) & 1 == 1 {
-                let canonical_pos = element_permutation.iter().position(|&x| x == i).unwrap();
-                new_s_int |= 1 << canonical_pos;
+    let positions = (0..n).map(|i| element_permutation.iter().position(|&x| x == i).unwrap()).collect();
+    (positions, group_order)
+}
+
+/// Moves bit i of every member of a family to bit `positions[i]`
+fn relabel(family: &Family, positions: &[usize]) -> Family {
+    family.iter().map(|&s_int| {
+        positions.iter().enumerate()
+            .filter(|&(i, _)| (s_int >> i) & 1 == 1)
+            .fold(0, |acc: Elem, (_, &pos)| acc | (1 << pos))
+    }).collect()
+}
+
+/// Canonical forms of recently seen families, evicting the least recently used one
+/// when full (a capacity of 0 disables caching)
+#[derive(Debug, Default)]
+pub struct CanonCache {
+    capacity: usize,
+    /// Canonical form of each cached family and the tick it was last used at
+    entries: HashMap<Family, (Family, u64)>,
+    /// Cached families by the tick they were last used at, oldest first
+    recency: BTreeMap<u64, Family>,
+    tick: u64,
+    /// Lookups answered from the cache
+    pub hits: usize,
+    /// Lookups that had to run nauty
+    pub misses: usize,
+}
+
+impl CanonCache {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, ..Self::default() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The cached canonical form of a family, marking it as the most recently used
+    fn get(&mut self, family: &Family) -> Option<Family> {
+        self.tick += 1;
+        let (canonical, last_used) = self.entries.get_mut(family)?;
+        let key = self.recency.remove(last_used).expect("every entry has a recency tick");
+        *last_used = self.tick;
+        self.recency.insert(self.tick, key);
+        Some(canonical.clone())
+    }
+
+    fn insert(&mut self, family: Family, canonical: Family) {
+        if self.len() >= self.capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
             }
         }
-        canonical_family.insert(new_s_int);
+        self.tick += 1;
+        self.recency.insert(self.tick, family.clone());
+        self.entries.insert(family, (canonical, self.tick));
+    }
+}
+
+/// Canonical forms shared by the threads of a parallel search
+///
+/// The map is a sharded `DashMap`, so lookups from different threads rarely contend.
+/// There is no eviction: once `capacity` forms are cached, further ones are computed
+/// but not kept. A capacity of 0 disables caching.
+#[derive(Debug, Default)]
+pub struct SharedCanonCache {
+    capacity: usize,
+    entries: DashMap<Family, Family>,
+    /// Number of entries, kept apart since `DashMap::len` locks every shard
+    len: AtomicUsize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl SharedCanonCache {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, ..Self::default() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Lookups answered from the cache
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Lookups that had to run nauty
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+/// Canonical form of a nonempty family, computed with nauty
+fn canonical_form(family: &Family, n: usize) -> Family {
+    let (canonical_positions, _) = canonical_positions(family, n);
+    relabel(family, &canonical_positions)
+}
+
+/// As `canonicalize`, through a cache that any number of threads can share
+pub fn canonicalize_shared(family: &Family, n: usize, cache: &SharedCanonCache) -> Family {
+    if family.is_empty() {
+        return BTreeSet::new();
+    }
+    if cache.capacity == 0 {
+        return canonical_form(family, n);
+    }
+    if let Some(cached) = cache.entries.get(family) {
+        cache.hits.fetch_add(1, Ordering::Relaxed);
+        return cached.clone();
+    }
+    cache.misses.fetch_add(1, Ordering::Relaxed);
+
+    // Two threads may both compute a missing form; they agree, so either insert will do,
+    // but only the first one takes up a slot
+    let canonical_family = canonical_form(family, n);
+    let reserved = cache.len.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |len| (len < cache.capacity).then_some(len + 1));
+    if reserved.is_ok() && cache.entries.insert(family.clone(), canonical_family.clone()).is_some() {
+        cache.len.fetch_sub(1, Ordering::Relaxed);
+    }
+    canonical_family
+}
+
+/// Canonicalizes a family using nauty with caching
+pub fn canonicalize(family: &Family, n: usize, cache: &mut CanonCache) -> Family {
+    if family.is_empty() {
+        return BTreeSet::new();
     }
     
-    if max_cache_size > 0 {
-        if cache.len() >= max_cache_size {
-            cache.clear();
+    if cache.capacity > 0 {
+        if let Some(cached) = cache.get(family) {
+            cache.hits += 1;
+            return cached;
         }
+        cache.misses += 1;
+    }
+
+    let canonical_family = canonical_form(family, n);
+    
+    if cache.capacity > 0 {
         cache.insert(family.clone(), canonical_family.clone());
     }
     canonical_family
@@ -262,36 +770,549 @@ pub fn canonicalize(family: &Family, n: usize, cache: &mut HashMap<Family, Famil
 
 /// Canonicalizes a family without caching (for one-off canonicalization)
 pub fn canonicalize_once(family: &Family, n: usize) -> Family {
-    let mut dummy_cache = HashMap::new();
-    canonicalize(family, n, &mut dummy_cache, 0)
+    canonicalize(family, n, &mut CanonCache::new(0))
 }
 
-/// Removes the lexicographically largest set and canonicalizes
-pub fn canonical_delete(family: &Family, n: usize, cache: &mut HashMap<Family, Family>, max_cache_size: usize) -> Family {
+/// Reference canonical form computed without nauty: the lexicographically smallest
+/// relabelling of the family over all n! permutations of the points
+///
+/// Only practical for small n. It picks a different representative than nauty, so it
+/// is compared with `canonicalize` by which families the two identify, not by value.
+pub fn canonicalize_bruteforce(family: &Family, n: usize) -> Family {
+    // Heap's algorithm, visiting each permutation of the positions once
+    let mut positions: Vec<usize> = (0..n).collect();
+    let mut counters = vec![0; n];
+    let mut best = relabel(family, &positions);
+    let mut i = 1;
+    while i < n {
+        if counters[i] < i {
+            let j = if i % 2 == 0 { 0 } else { counters[i] };
+            positions.swap(j, i);
+            best = best.min(relabel(family, &positions));
+            counters[i] += 1;
+            i = 1;
+        } else {
+            counters[i] = 0;
+            i += 1;
+        }
+    }
+    best
+}
+
+/// Canonicalizes a family without caching, also returning the order of its automorphism
+/// group: the number of permutations of the n points that map the family to itself.
+/// The order is exact up to 2^53 and approximate beyond.
+pub fn canonicalize_with_autsize(family: &Family, n: usize) -> (Family, f64) {
     if family.is_empty() {
-        return BTreeSet::new();
+        return (BTreeSet::new(), (1..=n).map(|k| k as f64).product());
     }
-    
-    let mut temp_list: Vec<u32> = family.iter().cloned().collect();
-    temp_list.sort();
-    
-    if temp_list.len() <= 1 {
-        return BTreeSet::new();
+    let (positions, group_order) = canonical_positions(family, n);
+    (relabel(family, &positions), group_order)
+}
+
+/// If two families on n points are the same up to relabelling the points, returns a
+/// relabelling taking `a` to `b`: bit i of a member of `a` becomes bit `perm[i]`
+pub fn isomorphism(a: &Family, b: &Family, n: usize) -> Option<Vec<usize>> {
+    if a.len() != b.len() {
+        return None;
     }
-    
-    let reduced_family: BTreeSet<u32> = temp_list[1..].iter().cloned().collect();
-    canonicalize(&reduced_family, n, cache, max_cache_size)
+    if a.is_empty() {
+        return Some((0..n).collect());
+    }
+    let (to_canonical_a, _) = canonical_positions(a, n);
+    let (to_canonical_b, _) = canonical_positions(b, n);
+    if relabel(a, &to_canonical_a) != relabel(b, &to_canonical_b) {
+        return None;
+    }
+    // Through the shared canonical form: a -> canonical -> b
+    let mut from_canonical_b = vec![0; n];
+    for (i, &pos) in to_canonical_b.iter().enumerate() {
+        from_canonical_b[pos] = i;
+    }
+    Some(to_canonical_a.iter().map(|&pos| from_canonical_b[pos]).collect())
+}
+
+/// Removes the lexicographically largest set and canonicalizes
+pub fn canonical_delete(family: &Family, n: usize, cache: &mut CanonCache) -> Family {
+    canonicalize(&delete_first(family), n, cache)
+}
+
+/// As `canonical_delete`, through a cache that any number of threads can share
+pub fn canonical_delete_shared(family: &Family, n: usize, cache: &SharedCanonCache) -> Family {
+    canonicalize_shared(&delete_first(family), n, cache)
+}
+
+/// The family without its first set in the order of `Family`
+fn delete_first(family: &Family) -> Family {
+    family.iter().skip(1).cloned().collect()
+}
+
+/// Forms the disjoint union of two semitopologies on n1 and n2 points
+///
+/// Points of `b` are shifted up to n1+1..=n1+n2. Both inputs are treated as containing
+/// the empty set, so the opens are exactly {A ∪ B' : A ∈ a ∪ {∅}, B ∈ b ∪ {∅}} where B'
+/// is B shifted; in particular each side's opens appear on their own, as does ∅.
+pub fn disjoint_union(a: &Family, n1: usize, b: &Family, n2: usize) -> Result<(Family, usize), String> {
+    let n = n1 + n2;
+    if n > MAX_POINTS {
+        return Err(format!("Disjoint union has {} points, but at most {} are supported", n, MAX_POINTS));
+    }
+
+    let left: Vec<Elem> = a.iter().cloned().chain(std::iter::once(0)).collect();
+    let right: Vec<Elem> = b.iter().map(|&m| m << n1).chain(std::iter::once(0)).collect();
+
+    let mut family = BTreeSet::new();
+    for &l in &left {
+        for &r in &right {
+            family.insert(l | r);
+        }
+    }
+    Ok((family, n))
+}
+
+/// Checks whether the empty set is a member of the family
+///
+/// `Search` and `Find` always add ∅ to the families they emit or check, `Combine` and
+/// `union_closure` add it, while `parse_family_str` and hence `Check` and `Canon` take
+/// the family exactly as written.
+pub fn has_empty<S: PointSet>(family: &BTreeSet<S>) -> bool {
+    family.contains(&S::empty())
+}
+
+/// Checks that the union of any two members is again a member
+pub fn is_union_closed<S: PointSet>(family: &BTreeSet<S>) -> bool {
+    family.iter().all(|a| family.iter().all(|b| family.contains(&a.union(b))))
+}
+
+/// As `is_union_closed`, naming the first two members whose union is missing
+pub fn check_union_closed<S: PointSet>(family: &BTreeSet<S>, n: usize) -> Result<(), String> {
+    let set_str = |set: &S| {
+        let mut points: Vec<usize> = int_to_set(set, n).into_iter().collect();
+        points.sort();
+        format!("{{{}}}", points.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", "))
+    };
+    for a in family {
+        for b in family.range(a..) {
+            let union = a.union(b);
+            if !family.contains(&union) {
+                return Err(format!(
+                    "{} is not closed under unions: {} ∪ {} = {} is missing",
+                    family_to_str(family, n), set_str(a), set_str(b), set_str(&union)
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Closes a generating family under unions, adding ∅ as the empty union
+pub fn union_closure<S: PointSet>(family: &BTreeSet<S>, n: usize) -> BTreeSet<S> {
+    debug_assert!(family.iter().all(|m| m.is_subset(&S::full(n))));
+    let mut closed = BTreeSet::new();
+    closed.insert(S::empty());
+    for generator in family {
+        let unions: Vec<S> = closed.iter().map(|m| m.union(generator)).collect();
+        closed.extend(unions);
+    }
+    closed
 }
 
 /// Infers the size n from a family by finding the maximum element
-pub fn infer_size_from_family(family: &Family) -> usize {
-    let mut max_element = 0;
-    for &mask in family {
-        for i in 0..32 { // u32 has 32 bits
-            if (mask >> i) & 1 == 1 {
-                max_element = max_element.max(i + 1);
+///
+/// Returns 0 only when no set in the family contains a point, i.e. for `{}` and `{{}}`,
+/// whose size must then be given explicitly.
+pub fn infer_size_from_family<S: PointSet>(family: &BTreeSet<S>) -> usize {
+    family.iter()
+        .map(|mask| mask.max_point())
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_family_line_size_prefix() {
+        // The prefix overrides inference, which would give n=3 here
+        let (family, n): (Family, _) = parse_family_line("n=5 {{1, 2}, {3}}", None).unwrap();
+        assert_eq!(n, 5);
+        assert_eq!(family, [0b011, 0b100].into_iter().collect());
+
+        // ... and also a caller-supplied default
+        let (_, n) = parse_family_line::<Elem>("n=4{{1}}", Some(2)).unwrap();
+        assert_eq!(n, 4);
+    }
+
+    #[test]
+    fn test_parse_family_line_fallbacks() {
+        let (_, n) = parse_family_line::<Elem>("  {{1, 2}, {3}}  ", None).unwrap();
+        assert_eq!(n, 3);
+        let (_, n) = parse_family_line::<Elem>("{{1, 2}, {3}}", Some(6)).unwrap();
+        assert_eq!(n, 6);
+    }
+
+    #[test]
+    fn test_parse_family_line_errors() {
+        assert!(parse_family_line::<Elem>("n=2 {{1, 2}, {3}}", None).is_err()); // 3 out of range
+        assert!(parse_family_line::<Elem>("n= {{1}}", None).is_err());
+        assert!(parse_family_line::<Elem>("n=x {{1}}", None).is_err());
+        assert!(parse_family_line::<Elem>("{{}}", None).is_err()); // size cannot be inferred
+    }
+
+    #[test]
+    fn test_parse_single_set_ranges() {
+        assert_eq!(parse_single_set::<Elem>("{1-3}", 3), parse_single_set::<Elem>("{1,2,3}", 3));
+        assert_eq!(parse_single_set::<Elem>("{1-4, 7}", 7).unwrap(), 0b1001111);
+        assert_eq!(parse_single_set::<Elem>("{ 2 - 2 }", 3).unwrap(), 0b10);
+        for bad in ["{3-1}", "{1-4}", "{0-2}", "{1-}", "{-2}", "{1-2-3}"] {
+            assert!(parse_single_set::<Elem>(bad, 3).is_err(), "{} should not parse", bad);
+        }
+        let listed = format!("{{{}}}", (1..=100).map(|p| p.to_string()).collect::<Vec<_>>().join(", "));
+        assert_eq!(
+            parse_single_set::<crate::bitset::BitSet>("{1-100}", 100),
+            parse_single_set::<crate::bitset::BitSet>(&listed, 100)
+        );
+    }
+
+    #[test]
+    fn test_parse_family_str_rejects_malformed() {
+        for bad in ["{{1,}2}", "{{1,2} foo {3}}", "{{1}, {2}} x}", "{{1}, {2}", "{{1}}, {2}}", "{{1}, 3}"] {
+            assert!(parse_family_str::<Elem>(bad, 3).is_err(), "{} should not parse", bad);
+        }
+        let family = parse_family_str::<Elem>(" {\t{1, 2},\n{3} ,{}} ", 3).unwrap();
+        assert_eq!(family, Family::from([0, 0b011, 0b100]));
+    }
+
+    #[test]
+    fn test_disjoint_union_opens() {
+        // {{}, {1}, {1,2}} + {{}, {1}} on 2 + 1 points
+        let a: Family = [0b00, 0b01, 0b11].into_iter().collect();
+        let b: Family = [0b0, 0b1].into_iter().collect();
+        let (family, n) = disjoint_union(&a, 2, &b, 1).unwrap();
+        assert_eq!(n, 3);
+        assert_eq!(family_to_str(&family, n), "{{}, {1}, {3}, {1, 2}, {1, 3}, {1, 2, 3}}");
+        // Every pair (A, B) contributes a distinct open
+        assert_eq!(family.len(), a.len() * b.len());
+    }
+
+    #[test]
+    fn test_disjoint_union_without_empty_set() {
+        // The empty set is added on both sides, so separate opens still appear
+        let a: Family = [0b1].into_iter().collect();
+        let b: Family = [0b1].into_iter().collect();
+        let (family, _) = disjoint_union(&a, 1, &b, 1).unwrap();
+        assert_eq!(family, [0b00, 0b01, 0b10, 0b11].into_iter().collect());
+    }
+
+    #[test]
+    fn test_disjoint_union_union_closed() {
+        let a: Family = [0b000, 0b011, 0b101, 0b111].into_iter().collect();
+        let b: Family = [0b00, 0b01, 0b11].into_iter().collect();
+        let (family, _) = disjoint_union(&a, 3, &b, 2).unwrap();
+        for &x in &family {
+            for &y in &family {
+                assert!(family.contains(&(x | y)));
+            }
+        }
+        assert!(disjoint_union(&a, 62, &b, 3).is_err());
+    }
+
+    #[test]
+    fn test_union_closure() {
+        let (family, n): (Family, _) = parse_family_line("{{1}, {2}, {3}}", None).unwrap();
+        let closed = union_closure(&family, n);
+        assert_eq!(closed.len(), 8);
+        assert_eq!(family_to_str(&closed, n), "{{}, {1}, {2}, {3}, {1, 2}, {1, 3}, {2, 3}, {1, 2, 3}}");
+
+        // Closing is idempotent, and already-closed families are unchanged
+        assert_eq!(union_closure(&closed, n), closed);
+        let (family, n): (Family, _) = parse_family_line("{{}, {1, 2}, {1, 3}, {1, 2, 3}}", None).unwrap();
+        assert_eq!(union_closure(&family, n), family);
+
+        assert_eq!(union_closure(&Family::new(), 3), [0].into_iter().collect());
+    }
+
+    #[test]
+    fn test_has_empty() {
+        // Parsing keeps the family exactly as written
+        let (family, _): (Family, _) = parse_family_line("{{1, 2}, {1}}", None).unwrap();
+        assert!(!has_empty(&family));
+        let (family, _): (Family, _) = parse_family_line("{{}, {1, 2}, {1}}", None).unwrap();
+        assert!(has_empty(&family));
+
+        // ... while closing and combining add it
+        let (family, n) = parse_family_line("{{1, 2}, {1}}", None).unwrap();
+        assert!(has_empty(&union_closure(&family, n)));
+        assert!(has_empty(&disjoint_union(&family, n, &family, n).unwrap().0));
+    }
+
+    #[test]
+    fn test_family_json_round_trip() {
+        let (family, n): (Family, _) = parse_family_line("{{}, {1}, {1, 2}, {1, 3}, {1, 2, 3}}", None).unwrap();
+        let json = family_to_json(&family, n);
+        assert_eq!(json, "{\"n\": 3, \"sets\": [[], [1], [1, 2], [1, 3], [1, 2, 3]]}");
+        assert_eq!(parse_family_json::<Elem>(&json).unwrap(), (family, n));
+
+        // Member order and whitespace are free; the empty family has no sets
+        let (empty, n) = parse_family_json::<Elem>(r#"{ "sets": [], "n": 2 }"#).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(n, 2);
+
+        assert!(parse_family_json::<Elem>(r#"{"n": 2, "sets": [[3]]}"#).is_err());
+        assert!(parse_family_json::<Elem>(r#"{"n": 2}"#).is_err());
+        assert!(parse_family_json::<Elem>("{{1}, {1, 2}}").is_err());
+    }
+
+    #[test]
+    fn test_canonicalize_lines() {
+        // {1} and {3} are relabellings of each other; {1, 2} is not
+        let input = "{{1}, {1, 2, 3}}\n\n{{3}, {1, 2, 3}}\nn=3 {{1, 2}, {1, 2, 3}}\n";
+        let mut output = Vec::new();
+        assert_eq!(canonicalize_lines(input.as_bytes(), &mut output, None, false), Ok(3));
+        let lines: Vec<&str> = std::str::from_utf8(&output).unwrap().lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], lines[1]);
+        assert_ne!(lines[0], lines[2]);
+
+        let mut output = Vec::new();
+        assert_eq!(canonicalize_lines("{{1}}\n".as_bytes(), &mut output, Some(2), true), Ok(1));
+        let mut closed = Vec::new();
+        canonicalize_lines("{{}, {1}}\n".as_bytes(), &mut closed, Some(2), false).unwrap();
+        assert_eq!(output, closed);
+
+        let err = canonicalize_lines("{{1}}\n{{1}\n".as_bytes(), Vec::new(), None, false).unwrap_err();
+        assert!(err.starts_with("line 2: "), "{}", err);
+    }
+
+    #[test]
+    fn test_isomorphism() {
+        // {1} <= {1, 2} and {3} <= {2, 3}: relabel 1 -> 3, 2 -> 2, 3 -> 1
+        let a: Family = [0b001, 0b011, 0b111].into_iter().collect();
+        let b: Family = [0b100, 0b110, 0b111].into_iter().collect();
+        let perm = isomorphism(&a, &b, 3).unwrap();
+        assert_eq!(relabel(&a, &perm), b);
+        assert_eq!(perm, vec![2, 1, 0]);
+        assert_eq!(isomorphism(&a, &a, 3).map(|p| relabel(&a, &p)), Some(a.clone()));
+
+        // Same number of opens, but {1} and {2, 3} are not nested like {1} and {1, 2}
+        let c: Family = [0b001, 0b110, 0b111].into_iter().collect();
+        assert_eq!(isomorphism(&a, &c, 3), None);
+        assert_eq!(isomorphism(&a, &[0b111].into_iter().collect(), 3), None);
+    }
+
+    #[test]
+    fn test_canonicalize_with_autsize() {
+        // Sierpinski space {{}, {1}, {1, 2}}: only the identity fixes it
+        let sierpinski: Family = [0b00, 0b01, 0b11].into_iter().collect();
+        let (canonical, order) = canonicalize_with_autsize(&sierpinski, 2);
+        assert_eq!(canonical, canonicalize_once(&sierpinski, 2));
+        assert_eq!(order, 1.0);
+
+        // Discrete and indiscrete spaces on 3 points: every permutation
+        let discrete: Family = (0..8).collect();
+        assert_eq!(canonicalize_with_autsize(&discrete, 3).1, 6.0);
+        let indiscrete: Family = [0b000, 0b111].into_iter().collect();
+        assert_eq!(canonicalize_with_autsize(&indiscrete, 3).1, 6.0);
+
+        // {1} <= {1, 2}, {3} <= {2, 3}: swapping 1 and 3
+        let v: Family = [0b001, 0b100, 0b011, 0b110, 0b111].into_iter().collect();
+        assert_eq!(canonicalize_with_autsize(&v, 3).1, 2.0);
+    }
+
+    #[test]
+    fn test_family_to_dot() {
+        let family: Family = [0b000, 0b001, 0b011, 0b110, 0b111].into_iter().collect();
+        let dot = family_to_dot(&family, 3);
+        assert!(dot.starts_with("graph family {\n") && dot.ends_with("}\n"));
+        let memberships: u32 = family.iter().map(|m| m.count_ones()).sum();
+        assert_eq!(dot.matches(" -- ").count(), memberships as usize);
+        assert!(dot.contains("p3 [label=\"3\"];"));
+        assert!(dot.contains("o0 [label=\"{}\"];"));
+        assert!(dot.contains("p2 -- o3;"));
+    }
+
+    #[test]
+    fn test_graph6_round_trip() {
+        let families: Vec<(Family, usize)> = vec![
+            ([0b0].into_iter().collect(), 1),
+            ([0b00, 0b01, 0b11].into_iter().collect(), 2),
+            ([0b000, 0b001, 0b011, 0b110, 0b111].into_iter().collect(), 3),
+            ((0..16).collect(), 4),
+            ((0..64).collect(), 6),
+        ];
+        for (family, n) in families {
+            let graph6 = family_to_graph6(&family, n);
+            assert_eq!(graph6_to_family(&graph6, n), Ok(family.clone()), "{}", graph6);
+        }
+
+        // A single point in the single open {1}: vertices 0 and 1 joined
+        let single: Family = [0b1].into_iter().collect();
+        assert_eq!(family_to_graph6(&single, 1), "A_");
+        // Points 1, 2 then opens {1}, {2}, {1, 2}, as networkx also encodes it
+        let two: Family = [0b01, 0b10, 0b11].into_iter().collect();
+        assert_eq!(family_to_graph6(&two, 2), "DQo");
+        // 70 vertices takes the long size prefix
+        let discrete: Family = (0..64).collect();
+        assert!(family_to_graph6(&discrete, 6).starts_with("~?@E"));
+
+        assert!(graph6_to_family("A_", 2).is_err());
+        assert!(graph6_to_family("Bw", 1).unwrap_err().contains("does not join"));
+        // The largest 36-bit size must not overflow the bit count
+        assert!(graph6_to_family("~~~~~~~~", 3).unwrap_err().contains("too many vertices"));
+
+        let mut output = Vec::new();
+        let input = "{{}, {1}, {1, 2}}\n\n{{}, {2}, {1, 2}}\n";
+        assert_eq!(convert_lines(input.as_bytes(), &mut output, FamilyFormat::Braces, FamilyFormat::Graph6, None), Ok(2));
+        let mut back = Vec::new();
+        convert_lines(output.as_slice(), &mut back, FamilyFormat::Graph6, FamilyFormat::Braces, Some(2)).unwrap();
+        assert_eq!(String::from_utf8(back).unwrap(), "{{}, {1}, {1, 2}}\n{{}, {2}, {1, 2}}\n");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_family_serde_round_trip() {
+        let family: Family = [0b000, 0b001, 0b011, 0b110, 0b111].into_iter().collect();
+        let mut json = Vec::new();
+        serialize_family(&family, &mut serde_json::Serializer::new(&mut json)).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert_eq!(json, "[[],[1],[1,2],[2,3],[1,2,3]]");
+        let back = deserialize_family(&mut serde_json::Deserializer::from_str(&json)).unwrap();
+        assert_eq!(back, family);
+
+        assert!(deserialize_family(&mut serde_json::Deserializer::from_str("[[0]]")).is_err());
+        assert!(deserialize_family(&mut serde_json::Deserializer::from_str("[[65]]")).is_err());
+    }
+
+    #[test]
+    fn test_canon_cache_evicts_least_recently_used() {
+        // Four hot families each come back after three cold ones used only once
+        let full = full_set(4);
+        let hot: Vec<Family> = (1..=4).map(|s| Family::from([s, full])).collect();
+        let cold = (5..full).flat_map(|a| (a + 1..full).map(move |b| Family::from([a, b, full])));
+        let workload: Vec<Family> = cold.enumerate()
+            .flat_map(|(i, c)| [hot[i % hot.len()].clone(), c])
+            .collect();
+
+        let mut cache = CanonCache::new(8);
+        for family in &workload {
+            assert_eq!(canonicalize(family, 4, &mut cache), canonicalize_once(family, 4));
+        }
+        assert!(cache.len() <= 8);
+        assert_eq!(cache.hits + cache.misses, workload.len());
+
+        // The same workload against a cache that is cleared whenever it fills up
+        let mut cleared: HashSet<&Family> = HashSet::new();
+        let mut clear_on_full_hits = 0;
+        for family in &workload {
+            if cleared.contains(family) {
+                clear_on_full_hits += 1;
+            } else {
+                if cleared.len() >= 8 {
+                    cleared.clear();
+                }
+                cleared.insert(family);
+            }
+        }
+        assert!(cache.hits as f64 > 1.5 * clear_on_full_hits as f64,
+                "LRU hits {} vs clear-on-full hits {}", cache.hits, clear_on_full_hits);
+
+        // A capacity of 0 caches nothing
+        let mut disabled = CanonCache::new(0);
+        canonicalize(&hot[0], 4, &mut disabled);
+        canonicalize(&hot[0], 4, &mut disabled);
+        assert_eq!((disabled.len(), disabled.hits, disabled.misses), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_shared_canon_cache_across_threads() {
+        use rayon::prelude::*;
+
+        // Every family on 3 points with two nonempty members besides the full set, each
+        // canonicalized from many threads at once
+        let full = full_set(3);
+        let families: Vec<Family> = (1..full)
+            .flat_map(|a| (a + 1..full).map(move |b| Family::from([a, b, full])))
+            .collect();
+        let expected: Vec<Family> = families.iter().map(|f| canonicalize_once(f, 3)).collect();
+
+        let cache = SharedCanonCache::new(1000);
+        let rounds = 50;
+        let results: Vec<(usize, Family)> = (0..rounds * families.len())
+            .into_par_iter()
+            .map(|i| (i % families.len(), canonicalize_shared(&families[i % families.len()], 3, &cache)))
+            .collect();
+        for (i, canonical) in results {
+            assert_eq!(canonical, expected[i]);
+        }
+        assert_eq!(cache.len(), families.len());
+        assert_eq!(cache.hits() + cache.misses(), rounds * families.len());
+        // Threads racing on a missing form may each compute it, so misses can exceed the count
+        assert!(cache.hits() > cache.misses());
+
+        // Once full, forms are still computed correctly but no longer kept
+        let small = SharedCanonCache::new(2);
+        families.par_iter().for_each(|f| assert_eq!(canonicalize_shared(f, 3, &small), canonicalize_once(f, 3)));
+        assert_eq!(small.len(), 2);
+    }
+
+    #[test]
+    fn test_canonicalize_bruteforce_agrees_with_nauty() {
+        for n in 1..=5 {
+            let mut families = crate::search::random_families(n, 25, n as u64).unwrap();
+            // Relabelled copies, which both canonicalizers must identify with the originals
+            let reversed: Vec<usize> = (0..n).rev().collect();
+            let rotated: Vec<usize> = (0..n).map(|i| (i + 1) % n).collect();
+            let copies: Vec<Family> = families.iter()
+                .flat_map(|f| [relabel(f, &reversed), relabel(f, &rotated)])
+                .collect();
+            families.extend(copies);
+
+            let nauty: Vec<Family> = families.iter().map(|f| canonicalize_once(f, n)).collect();
+            let brute: Vec<Family> = families.iter().map(|f| canonicalize_bruteforce(f, n)).collect();
+            for i in 0..families.len() {
+                assert_eq!(canonicalize_bruteforce(&nauty[i], n), brute[i], "n={} {}", n, family_to_str(&families[i], n));
+                for j in 0..families.len() {
+                    assert_eq!(nauty[i] == nauty[j], brute[i] == brute[j], "n={} {} vs {}",
+                               n, family_to_str(&families[i], n), family_to_str(&families[j], n));
+                }
             }
         }
+
+        // The smallest relabelling of {{1}, {1, 2}} puts the singleton on the lowest bit
+        let family: Family = [0b10, 0b11].into_iter().collect();
+        assert_eq!(canonicalize_bruteforce(&family, 2), [0b01, 0b11].into_iter().collect());
     }
-    max_element
-}
\ No newline at end of file
+
+    #[test]
+    fn test_check_union_closed() {
+        let (family, n) = parse_family_line::<Elem>("{{1}, {2}}", None).unwrap();
+        assert!(!is_union_closed(&family));
+        assert_eq!(
+            check_union_closed(&family, n),
+            Err("{{1}, {2}} is not closed under unions: {1} ∪ {2} = {1, 2} is missing".to_string())
+        );
+        let closed = union_closure(&family, n);
+        assert!(is_union_closed(&closed));
+        assert_eq!(check_union_closed(&closed, n), Ok(()));
+        assert!(is_union_closed(&Family::new()));
+    }
+
+    #[test]
+    fn test_empty_family_needs_explicit_size() {
+        assert_eq!(infer_size_from_family(&Family::new()), 0);
+        assert_eq!(infer_size_from_family(&Family::from([0])), 0);
+        assert_eq!(infer_size_from_family(&Family::from([0, 0b100])), 3);
+
+        // With the size given, the empty family parses and canonicalizes to itself
+        for line in ["{}", "n=3 {}"] {
+            let (family, n) = parse_family_line::<Elem>(line, Some(3)).unwrap();
+            assert_eq!((family.len(), n), (0, 3));
+            assert_eq!(family_to_str(&canonicalize_once(&family, n), n), "{}");
+        }
+        let (family, n) = parse_family_line::<Elem>("{{}}", Some(3)).unwrap();
+        assert_eq!(family_to_str(&canonicalize_once(&family, n), n), "{{}}");
+
+        assert!(parse_family_line::<Elem>("{}", None).is_err());
+    }
+}
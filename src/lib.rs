@@ -0,0 +1,35 @@
+//! Library interface of the semiframes and semitopologies checker: canonical forms of
+//! families of open sets, the formula parser and model checker, and the orderly search.
+//!
+//! A family is a set of bitmasks, point `i` being bit `i - 1`:
+//!
+//! ```
+//! use semiframes::{ModelChecker, family_to_str, parse_family_str, parse_formula};
+//!
+//! // The Sierpinski space on two points
+//! let family = parse_family_str("{{}, {1}, {1, 2}}", 2).unwrap();
+//! assert_eq!(family_to_str(&family, 2), "{{}, {1}, {1, 2}}");
+//!
+//! // Some open separates one point from another
+//! let formula = parse_formula("EO X. EP x. EP y. (x in X) && !(y in X)").unwrap();
+//! let mut checker = ModelChecker::new(2, family);
+//! assert!(checker.check(&formula).satisfied);
+//!
+//! // ... which the indiscrete space has not
+//! let indiscrete = parse_family_str("{{}, {1, 2}}", 2).unwrap();
+//! assert!(!ModelChecker::new(2, indiscrete).check(&formula).satisfied);
+//! ```
+
+pub mod search;
+pub mod canon;
+pub mod model_checker;
+pub mod parser;
+pub mod tokens;
+pub mod ast;
+pub mod macro_expander;
+pub mod bitset;
+
+pub use canon::{Elem, Family, canonicalize_once, family_to_str, parse_family_str};
+pub use model_checker::{Formula, ModelChecker, ModelCheckResult, formula_to_str};
+pub use parser::{ParsedFormula, parse_closed_formula, parse_formula, parse_formula_with_warnings};
+pub use search::{Config, gen_fam};
@@ -9,16 +9,45 @@
 //! 2. **Macro identification**: Replace MacroProp nodes with their definitions
 //! 3. **Variable renaming**: Generate fresh variables to avoid capture
 //! 4. **Type conversion**: Convert from parser AST to model checker AST
+//!
+//! User definitions (`def name(p) := ...;`) are expanded at each use: the body is
+//! expanded, its bound variables renamed apart and its parameters replaced by the
+//! arguments.
 
 use crate::ast::*;
 use crate::model_checker::{Formula, Atom, OpenExpr as ModelOpenExpr};
+use std::collections::HashMap;
 
 /// Macro expander with fresh variable generation
 ///
 /// Maintains a counter to ensure all generated variables are unique,
 /// preventing accidental variable capture during macro expansion.
+#[derive(Default)]
 pub struct MacroExpander {
     fresh_var_counter: usize,
+    /// User definitions by name
+    definitions: HashMap<String, Definition>,
+    /// Definitions being expanded, innermost last, to detect recursion
+    expanding: Vec<String>,
+}
+
+/// What the free variables of a definition body stand for at a use: parameters map
+/// to the arguments, and variables bound in the body to fresh names
+#[derive(Clone, Default)]
+struct Substitution {
+    points: HashMap<String, String>,
+    opens: HashMap<String, ModelOpenExpr>,
+}
+
+/// Left-nested conjunction of a nonempty sequence of formulas
+fn conjunction(formulas: impl IntoIterator<Item = Formula>) -> Formula {
+    formulas.into_iter()
+        .reduce(|acc, f| Formula::And(Box::new(acc), Box::new(f)))
+        .expect("conjunction of no formulas")
+}
+
+fn is_open_var(var: &str) -> bool {
+    var.starts_with(|c: char| c.is_ascii_uppercase())
 }
 
 impl MacroExpander {
@@ -26,9 +55,28 @@ impl MacroExpander {
     pub fn new() -> Self {
         Self {
             fresh_var_counter: 0,
+            definitions: HashMap::new(),
+            expanding: Vec::new(),
         }
     }
 
+    /// Create a macro expander that also expands uses of the given definitions
+    pub fn with_definitions(definitions: Vec<Definition>) -> Result<Self, String> {
+        let mut expander = Self::new();
+        for definition in definitions {
+            for (i, param) in definition.params.iter().enumerate() {
+                if definition.params[..i].contains(param) {
+                    return Err(format!("Definition '{}' has two parameters named '{}'", definition.name, param));
+                }
+            }
+            if expander.definitions.contains_key(&definition.name) {
+                return Err(format!("Definition '{}' is given twice", definition.name));
+            }
+            expander.definitions.insert(definition.name.clone(), definition);
+        }
+        Ok(expander)
+    }
+
     /// Generate a fresh variable name with the given base
     /// 
     /// Each call produces a unique variable like "O_0", "O_1", "p_0", etc.
@@ -52,6 +100,8 @@ impl MacroExpander {
             LogicProp::Binary(binary) => self.expand_binary_prop(binary),
             LogicProp::Unary(unary) => self.expand_unary_prop(unary),
             LogicProp::Atomic(atomic) => self.expand_atomic_prop(atomic),
+            LogicProp::Const(true) => Ok(Formula::True),
+            LogicProp::Const(false) => Ok(Formula::False),
         }
     }
 
@@ -73,6 +123,24 @@ impl MacroExpander {
                 let inner = self.expand(*prop)?;
                 Ok(Formula::ExistsOpens(var, Box::new(inner)))
             }
+            QuantProp::AT(var, prop) => {
+                let inner = self.expand(*prop)?;
+                Ok(Formula::ForAllTopens(var, Box::new(inner)))
+            }
+            QuantProp::ET(var, prop) => {
+                let inner = self.expand(*prop)?;
+                Ok(Formula::ExistsTopens(var, Box::new(inner)))
+            }
+            QuantProp::APIn(var, open, prop) => {
+                let bound = Formula::Atom(Atom::PointInOpen(var.clone(), self.convert_open_expr_to_model(open)?));
+                let inner = self.expand(*prop)?;
+                Ok(Formula::ForAllPoints(var, Box::new(Formula::Implies(Box::new(bound), Box::new(inner)))))
+            }
+            QuantProp::EPIn(var, open, prop) => {
+                let bound = Formula::Atom(Atom::PointInOpen(var.clone(), self.convert_open_expr_to_model(open)?));
+                let inner = self.expand(*prop)?;
+                Ok(Formula::ExistsPoints(var, Box::new(Formula::And(Box::new(bound), Box::new(inner)))))
+            }
         }
     }
 
@@ -98,6 +166,11 @@ impl MacroExpander {
                 let right_expanded = self.expand(*right)?;
                 Ok(Formula::Iff(Box::new(left_expanded), Box::new(right_expanded)))
             }
+            BinaryProp::Xor(left, right) => {
+                let left_expanded = self.expand(*left)?;
+                let right_expanded = self.expand(*right)?;
+                Ok(Formula::Xor(Box::new(left_expanded), Box::new(right_expanded)))
+            }
         }
     }
 
@@ -146,12 +219,23 @@ impl MacroExpander {
                 let model_open2 = self.convert_open_expr_to_model(open_expr2)?;
                 Ok(Formula::Atom(Atom::OpenEqual(model_open1, model_open2)))
             }
+            AtomicProp::Subset(open_expr1, open_expr2) => {
+                let model_open1 = self.convert_open_expr_to_model(open_expr1)?;
+                let model_open2 = self.convert_open_expr_to_model(open_expr2)?;
+                Ok(Formula::Atom(Atom::Subset(model_open1, model_open2)))
+            }
+            AtomicProp::IsTopen(open_expr) => {
+                let model_open = self.convert_open_expr_to_model(open_expr)?;
+                Ok(Formula::Atom(Atom::IsTopen(model_open)))
+            }
         }
     }
 
     fn extract_point_id(&self, point_expr: PointExpr) -> Result<String, String> {
         match point_expr {
             PointExpr::PointVar(var) => Ok(var),
+            // Variables start with a letter, so a numeric name always denotes the literal
+            PointExpr::PointLit(point) => Ok(point.to_string()),
         }
     }
 
@@ -164,10 +248,35 @@ impl MacroExpander {
                 let point_var = self.extract_point_id(point_expr)?;
                 Ok(ModelOpenExpr::Community(point_var))
             }
+            OpenExpr::Mon(point_expr) => {
+                let point_var = self.extract_point_id(point_expr)?;
+                Ok(ModelOpenExpr::MinimalNeighbourhood(point_var))
+            }
             OpenExpr::IC(inner_expr) => {
                 let inner_model = self.convert_open_expr_to_model(*inner_expr)?;
                 Ok(ModelOpenExpr::InteriorComplement(Box::new(inner_model)))
             }
+            OpenExpr::Cl(inner_expr) => {
+                let inner_model = self.convert_open_expr_to_model(*inner_expr)?;
+                Ok(ModelOpenExpr::Closure(Box::new(inner_model)))
+            }
+            OpenExpr::Bd(inner_expr) => {
+                let inner_model = self.convert_open_expr_to_model(*inner_expr)?;
+                Ok(ModelOpenExpr::Boundary(Box::new(inner_model)))
+            }
+            OpenExpr::Union(left, right) => {
+                let left_model = self.convert_open_expr_to_model(*left)?;
+                let right_model = self.convert_open_expr_to_model(*right)?;
+                Ok(ModelOpenExpr::Union(Box::new(left_model), Box::new(right_model)))
+            }
+            OpenExpr::Inter(left, right) => {
+                let left_model = self.convert_open_expr_to_model(*left)?;
+                let right_model = self.convert_open_expr_to_model(*right)?;
+                Ok(ModelOpenExpr::Inter(Box::new(left_model), Box::new(right_model)))
+            }
+            OpenExpr::Lit(points) => Ok(ModelOpenExpr::Lit(points)),
+            OpenExpr::Univ => Ok(ModelOpenExpr::Univ),
+            OpenExpr::Empty => Ok(ModelOpenExpr::Empty),
         }
     }
 
@@ -175,17 +284,153 @@ impl MacroExpander {
         ModelOpenExpr::Var(var)
     }
 
+    /// Expand a use of a user definition
+    fn expand_call(&mut self, name: String, args: Vec<MacroArg>) -> Result<Formula, String> {
+        let definition = self.definitions.get(&name).cloned()
+            .ok_or_else(|| format!("Unknown definition '{}'", name))?;
+        if self.expanding.contains(&name) {
+            return Err(format!("Definition '{}' is recursive ({} -> {})", name, self.expanding.join(" -> "), name));
+        }
+        if args.len() != definition.params.len() {
+            return Err(format!(
+                "Definition '{}' takes {} argument(s) but is given {}",
+                name, definition.params.len(), args.len()
+            ));
+        }
+
+        let mut subst = Substitution::default();
+        for (i, (param, arg)) in definition.params.iter().zip(args).enumerate() {
+            match (is_open_var(param), arg) {
+                (false, MacroArg::Point(point)) => {
+                    subst.points.insert(param.clone(), self.extract_point_id(point)?);
+                }
+                (true, MacroArg::Open(open)) => {
+                    subst.opens.insert(param.clone(), self.convert_open_expr_to_model(open)?);
+                }
+                (open_param, _) => {
+                    return Err(format!(
+                        "Argument {} of '{}' should be {} (parameter '{}')",
+                        i + 1, name, if open_param { "an open" } else { "a point" }, param
+                    ));
+                }
+            }
+        }
+
+        self.expanding.push(name.clone());
+        let body = self.expand(definition.body);
+        self.expanding.pop();
+        self.instantiate(body?, &subst, &name)
+    }
+
+    /// Rename the bound variables of a definition body apart and substitute its parameters
+    fn instantiate(&mut self, formula: Formula, subst: &Substitution, name: &str) -> Result<Formula, String> {
+        Ok(match formula {
+            Formula::True => Formula::True,
+            Formula::False => Formula::False,
+            Formula::Atom(atom) => Formula::Atom(self.instantiate_atom(atom, subst, name)?),
+            Formula::Not(f) => Formula::Not(self.instantiate_boxed(*f, subst, name)?),
+            Formula::And(f1, f2) => Formula::And(self.instantiate_boxed(*f1, subst, name)?, self.instantiate_boxed(*f2, subst, name)?),
+            Formula::Or(f1, f2) => Formula::Or(self.instantiate_boxed(*f1, subst, name)?, self.instantiate_boxed(*f2, subst, name)?),
+            Formula::Implies(f1, f2) => Formula::Implies(self.instantiate_boxed(*f1, subst, name)?, self.instantiate_boxed(*f2, subst, name)?),
+            Formula::Iff(f1, f2) => Formula::Iff(self.instantiate_boxed(*f1, subst, name)?, self.instantiate_boxed(*f2, subst, name)?),
+            Formula::Xor(f1, f2) => Formula::Xor(self.instantiate_boxed(*f1, subst, name)?, self.instantiate_boxed(*f2, subst, name)?),
+            Formula::ForAllPoints(var, f) => {
+                let (var, inner) = self.bind_point(var, subst);
+                Formula::ForAllPoints(var, self.instantiate_boxed(*f, &inner, name)?)
+            }
+            Formula::ExistsPoints(var, f) => {
+                let (var, inner) = self.bind_point(var, subst);
+                Formula::ExistsPoints(var, self.instantiate_boxed(*f, &inner, name)?)
+            }
+            Formula::ForAllOpens(var, f) => {
+                let (var, inner) = self.bind_open(var, subst);
+                Formula::ForAllOpens(var, self.instantiate_boxed(*f, &inner, name)?)
+            }
+            Formula::ExistsOpens(var, f) => {
+                let (var, inner) = self.bind_open(var, subst);
+                Formula::ExistsOpens(var, self.instantiate_boxed(*f, &inner, name)?)
+            }
+            Formula::ForAllTopens(var, f) => {
+                let (var, inner) = self.bind_open(var, subst);
+                Formula::ForAllTopens(var, self.instantiate_boxed(*f, &inner, name)?)
+            }
+            Formula::ExistsTopens(var, f) => {
+                let (var, inner) = self.bind_open(var, subst);
+                Formula::ExistsTopens(var, self.instantiate_boxed(*f, &inner, name)?)
+            }
+        })
+    }
+
+    fn instantiate_boxed(&mut self, formula: Formula, subst: &Substitution, name: &str) -> Result<Box<Formula>, String> {
+        self.instantiate(formula, subst, name).map(Box::new)
+    }
+
+    fn bind_point(&mut self, var: String, subst: &Substitution) -> (String, Substitution) {
+        let fresh = self.fresh_var(&var);
+        let mut inner = subst.clone();
+        inner.points.insert(var, fresh.clone());
+        (fresh, inner)
+    }
+
+    fn bind_open(&mut self, var: String, subst: &Substitution) -> (String, Substitution) {
+        let fresh = self.fresh_var(&var);
+        let mut inner = subst.clone();
+        inner.opens.insert(var.clone(), ModelOpenExpr::Var(fresh.clone()));
+        (fresh, inner)
+    }
+
+    fn instantiate_atom(&self, atom: Atom, subst: &Substitution, name: &str) -> Result<Atom, String> {
+        let point = |p: String| Self::instantiate_point(p, subst, name);
+        let open = |o: ModelOpenExpr| Self::instantiate_open(o, subst, name);
+        Ok(match atom {
+            Atom::PointInOpen(p, o) => Atom::PointInOpen(point(p)?, open(o)?),
+            Atom::OpenIntersection(o1, o2) => Atom::OpenIntersection(open(o1)?, open(o2)?),
+            Atom::OpenNonempty(o) => Atom::OpenNonempty(open(o)?),
+            Atom::PointNotEqual(p1, p2) => Atom::PointNotEqual(point(p1)?, point(p2)?),
+            Atom::OpenNotEqual(o1, o2) => Atom::OpenNotEqual(open(o1)?, open(o2)?),
+            Atom::PointEqual(p1, p2) => Atom::PointEqual(point(p1)?, point(p2)?),
+            Atom::OpenEqual(o1, o2) => Atom::OpenEqual(open(o1)?, open(o2)?),
+            Atom::Subset(o1, o2) => Atom::Subset(open(o1)?, open(o2)?),
+            Atom::IsTopen(o) => Atom::IsTopen(open(o)?),
+        })
+    }
+
+    fn instantiate_point(var: String, subst: &Substitution, name: &str) -> Result<String, String> {
+        if var.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok(var);
+        }
+        subst.points.get(&var).cloned()
+            .ok_or_else(|| format!("Definition '{}' uses '{}', which is neither a parameter nor bound in it", name, var))
+    }
+
+    fn instantiate_open(open: ModelOpenExpr, subst: &Substitution, name: &str) -> Result<ModelOpenExpr, String> {
+        let inner = |o: Box<ModelOpenExpr>| Self::instantiate_open(*o, subst, name).map(Box::new);
+        Ok(match open {
+            ModelOpenExpr::Var(var) => subst.opens.get(&var).cloned()
+                .ok_or_else(|| format!("Definition '{}' uses '{}', which is neither a parameter nor bound in it", name, var))?,
+            ModelOpenExpr::Community(p) => ModelOpenExpr::Community(Self::instantiate_point(p, subst, name)?),
+            ModelOpenExpr::MinimalNeighbourhood(p) => {
+                ModelOpenExpr::MinimalNeighbourhood(Self::instantiate_point(p, subst, name)?)
+            }
+            ModelOpenExpr::InteriorComplement(o) => ModelOpenExpr::InteriorComplement(inner(o)?),
+            ModelOpenExpr::Closure(o) => ModelOpenExpr::Closure(inner(o)?),
+            ModelOpenExpr::Boundary(o) => ModelOpenExpr::Boundary(inner(o)?),
+            ModelOpenExpr::Union(o1, o2) => ModelOpenExpr::Union(inner(o1)?, inner(o2)?),
+            ModelOpenExpr::Inter(o1, o2) => ModelOpenExpr::Inter(inner(o1)?, inner(o2)?),
+            ModelOpenExpr::Lit(_) | ModelOpenExpr::Univ | ModelOpenExpr::Empty => open,
+        })
+    }
+
     fn expand_macro_prop(&mut self, macro_prop: MacroProp) -> Result<Formula, String> {
         match macro_prop {
-            MacroProp::TripleOpenInter(o, p, q) => {
-                // O inter P inter Q = (O inter P) && (P inter Q)
-                let o_model = self.convert_open_expr_to_model(o)?;
-                let p_model = self.convert_open_expr_to_model(p)?;
-                let q_model = self.convert_open_expr_to_model(q)?;
-                
-                let inter1 = Formula::Atom(Atom::OpenIntersection(o_model, p_model.clone()));
-                let inter2 = Formula::Atom(Atom::OpenIntersection(p_model, q_model));
-                Ok(Formula::And(Box::new(inter1), Box::new(inter2)))
+            MacroProp::NaryOpenInter(opens) => {
+                // O1 inter O2 inter ... inter Ok = (O1 inter O2) && (O2 inter O3) && ... && (Ok-1 inter Ok)
+                let models = opens.into_iter()
+                    .map(|o| self.convert_open_expr_to_model(o))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let inters = models.windows(2)
+                    .map(|pair| Formula::Atom(Atom::OpenIntersection(pair[0].clone(), pair[1].clone())));
+                Ok(conjunction(inters))
             }
             
             MacroProp::PointInter(p, q) => {
@@ -208,11 +453,12 @@ impl MacroExpander {
                 Ok(Formula::ForAllOpens(o_var, Box::new(inner_forall)))
             }
             
-            MacroProp::TriplePointInter(p, q, r) => {
-                // p inter q inter r = (p inter q) && (q inter r)
-                let p_inter_q = self.expand_macro_prop(MacroProp::PointInter(p, q.clone()))?;
-                let q_inter_r = self.expand_macro_prop(MacroProp::PointInter(q, r))?;
-                Ok(Formula::And(Box::new(p_inter_q), Box::new(q_inter_r)))
+            MacroProp::NaryPointInter(points) => {
+                // p1 inter p2 inter ... inter pk = (p1 inter p2) && (p2 inter p3) && ... && (pk-1 inter pk)
+                let inters = points.windows(2)
+                    .map(|pair| self.expand_macro_prop(MacroProp::PointInter(pair[0].clone(), pair[1].clone())))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(conjunction(inters))
             }
             
             MacroProp::Transitive(t_expr) => {
@@ -401,6 +647,8 @@ impl MacroExpander {
                 let hypertransitive_p = self.expand_macro_prop(MacroProp::Hypertransitive(PointExpr::PointVar(p_var.clone())))?;
                 Ok(Formula::ForAllPoints(p_var, Box::new(hypertransitive_p)))
             }
+
+            MacroProp::Call(name, args) => self.expand_call(name, args),
         }
     }
 }
\ No newline at end of file
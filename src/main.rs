@@ -1,19 +1,16 @@
-//! Main entry point for the semiframes and semitopologies checker.
-
-mod search;
-mod canon;
-mod model_checker;
-mod parser;
-mod tokens;
-mod ast;
-mod macro_expander;
+//! Command-line entry point for the semiframes and semitopologies checker, a thin
+//! binary over the `semiframes` library.
 
 use clap::{Parser, Subcommand};
-use search::{Config, gen_fam};
-use canon::{Family, parse_family_str, canonicalize_once, family_to_str, infer_size_from_family};
-use model_checker::{ModelChecker, Witness};
-use parser::parse_formula;
-use std::time::Instant;
+use semiframes::search::{self, Config, OutputFormat, SearchStrategy, SizeRun, count_fam, gen_fam, parse_property, random_families, summary_json};
+use semiframes::bitset::{BitSet, PointSet};
+use semiframes::canon::{Elem, Family, FamilyFormat, MAX_POINTS, parse_family_str, parse_family_line, canonicalize_lines, canonicalize_once, convert_lines, canonicalize_with_autsize, isomorphism, disjoint_union, family_to_dot, family_to_str, load_canonical_families, infer_size_from_family, union_closure, has_empty, check_union_closed};
+use semiframes::model_checker::{Assignment, Formula, check_bound, filter_family_lines, formula_to_str, MAX_POWERSET_POINTS, ModelChecker, OpenDomain, OpenOrigin, Witness};
+use semiframes::parser::{ParsedFormula, parse_closed_formula, parse_formula_with_warnings};
+use semiframes::status;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufRead, BufReader};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[derive(Parser)]
 #[command(name = "semiframes")]
@@ -48,6 +45,10 @@ enum Commands {
         #[arg(long)]
         semiframes: bool,
 
+        /// Only emit topologies (families also closed under intersection)
+        #[arg(long)]
+        topologies: bool,
+
         /// Starting family as semitopology (e.g., "{{1}, {1,2}, {1,2,3}}")
         #[arg(long)]
         starting_family: Option<String>,
@@ -63,24 +64,275 @@ enum Commands {
         /// Number of threads to use (1 for sequential, >1 for parallel)
         #[arg(short = 't', long = "threads", default_value = "1")]
         threads: usize,
+
+        /// Tally found families by the cardinality of their largest open
+        #[arg(long)]
+        largest_open_histogram: bool,
+
+        /// Tally found families by their number of opens
+        #[arg(long)]
+        histogram: bool,
+
+        /// Sort the output file once the search completes
+        #[arg(long)]
+        sort_output: bool,
+
+        /// File of known families (one per line); only families not in it are emitted
+        #[arg(long)]
+        exclude: Option<String>,
+
+        /// Write per-size counts and timings as JSON to this file once all sizes complete
+        #[arg(long)]
+        json_summary: Option<String>,
+
+        /// Save the pending search stack to this file after every batch (use {n} for size)
+        #[arg(long)]
+        checkpoint: Option<String>,
+
+        /// Continue from a checkpoint file saved by an interrupted run (use {n} for size)
+        #[arg(long)]
+        resume: Option<String>,
+
+        /// Keep at most this many pending families in memory, spilling older ones to disk
+        #[arg(long)]
+        max_stack: Option<usize>,
+
+        /// How to write families: "text" (brace notation), "json" (one object per line) or "csv"
+        #[arg(long, default_value = "text")]
+        format: OutputFormat,
+
+        /// Comma-separated space predicates to tabulate with --format csv (e.g. "regular_space,hypertransitive_space")
+        #[arg(long)]
+        columns: Option<String>,
+
+        /// Traversal order: "dfs" (depth first) or "bfs" (one number of opens at a time)
+        #[arg(long, default_value = "dfs")]
+        strategy: SearchStrategy,
+
+        /// Write each family at most once up to isomorphism, reporting how many repeats were skipped
+        #[arg(long)]
+        dedup: bool,
+
+        /// Only emit families satisfying this space predicate (e.g. "regular_space")
+        #[arg(long)]
+        property: Option<String>,
+
+        /// Stop each size after this many seconds, keeping its partial results, and go on to the next
+        #[arg(long)]
+        per_size_timeout: Option<u64>,
+
+        /// Write a uniform random sample of this many families, drawn from the whole search
+        #[arg(long, conflicts_with_all = ["limit", "checkpoint", "resume", "max_stack"])]
+        sample: Option<usize>,
+
+        /// Seed for --sample (taken from the clock and reported if not provided)
+        #[arg(long, requires = "sample")]
+        seed: Option<u64>,
+
+        /// Leave the empty set out of the families written and of the formula check
+        #[arg(long)]
+        no_empty: bool,
+    },
+    /// Count semiframes or semitopologies without writing them
+    Count {
+        /// Size to count for (single number or range like "3-5")
+        #[arg(short = 's', long, default_value = "1-6")]
+        size: String,
+
+        /// Maximum cache size (0 to disable caching)
+        #[arg(short = 'c', long, default_value = "10000")]
+        cache_size: usize,
+
+        /// Stop counting at this many families (0 for unlimited)
+        #[arg(short = 'l', long, default_value = "0")]
+        limit: usize,
+
+        /// Count semiframes instead of semitopologies
+        #[arg(long)]
+        semiframes: bool,
+
+        /// Only count topologies (families also closed under intersection)
+        #[arg(long)]
+        topologies: bool,
+
+        /// Starting family as semitopology (e.g., "{{1}, {1,2}, {1,2,3}}")
+        #[arg(long)]
+        starting_family: Option<String>,
+
+        /// Log interval for progress reporting
+        #[arg(long, default_value = "10000")]
+        log_interval: usize,
+
+        /// Number of threads to use (1 for sequential, >1 for parallel)
+        #[arg(short = 't', long = "threads", default_value = "1")]
+        threads: usize,
+
+        /// File of known families (one per line); only families not in it are counted
+        #[arg(long)]
+        exclude: Option<String>,
+
+        /// Write per-size counts and timings as JSON to this file once all sizes complete
+        #[arg(long)]
+        json_summary: Option<String>,
+
+        /// Traversal order: "dfs" (depth first) or "bfs" (one number of opens at a time)
+        #[arg(long, default_value = "dfs")]
+        strategy: SearchStrategy,
     },
     /// Canonicalize a given semitopology
     Canon {
         /// The semitopology to canonicalize (e.g., "{{1, 2}, {1, 3}, {2, 3}, {1, 2, 3}}")
-        #[arg(short = 'f', long)]
-        family: String,
+        #[arg(short = 'f', long, required_unless_present = "input")]
+        family: Option<String>,
+
+        /// File of families, one per line ("-" for stdin); prints each canonical form on its own line
+        #[arg(long, conflicts_with = "family")]
+        input: Option<String>,
 
         /// Size n for the semitopology (auto-inferred if not provided)
         #[arg(short = 'n', long)]
         size: Option<usize>,
+
+        /// Treat the family as generators and close it under unions first
+        #[arg(long)]
+        close: bool,
+
+        /// Print only the point-vs-open incidence graph of the family, in Graphviz DOT
+        #[arg(long, conflicts_with = "input")]
+        dot: bool,
+    },
+    /// Convert a file of families, one per line, between formats
+    Convert {
+        /// File to read ("-" for stdin)
+        #[arg(short = 'i', long, default_value = "-")]
+        input: String,
+
+        /// Format of the input lines: "braces", "json" or "graph6"
+        #[arg(long, default_value = "braces")]
+        from: FamilyFormat,
+
+        /// Format to write: "braces", "json" or "graph6"
+        #[arg(long)]
+        to: FamilyFormat,
+
+        /// Number of points (needed to read graph6; inferred from braces if not provided)
+        #[arg(short = 'n', long)]
+        size: Option<usize>,
+    },
+    /// Form the disjoint union of two semitopologies
+    Combine {
+        /// The first semitopology (e.g., "{{1}, {1, 2}}")
+        #[arg(short = 'a', long)]
+        first: String,
+
+        /// The second semitopology, whose points are numbered after the first's
+        #[arg(short = 'b', long)]
+        second: String,
+
+        /// Size of the first semitopology (auto-inferred if not provided)
+        #[arg(long)]
+        first_size: Option<usize>,
+
+        /// Size of the second semitopology (auto-inferred if not provided)
+        #[arg(long)]
+        second_size: Option<usize>,
+    },
+    /// Check whether two families are the same up to relabelling their points
+    Iso {
+        /// The first family (e.g., "{{1}, {1, 2}, {1, 2, 3}}")
+        #[arg(short = 'a', long)]
+        family_a: String,
+
+        /// The second family (e.g., "{{3}, {2, 3}, {1, 2, 3}}")
+        #[arg(short = 'b', long)]
+        family_b: String,
+
+        /// Size n for both families (auto-inferred as the larger of the two if not provided)
+        #[arg(short = 'n', long)]
+        size: Option<usize>,
+    },
+    /// Print random semitopologies, canonicalized, one per line
+    Random {
+        /// Number of points
+        #[arg(short = 'n', long)]
+        size: usize,
+
+        /// How many semitopologies to generate
+        #[arg(short = 'c', long, default_value = "1")]
+        count: usize,
+
+        /// Seed for the generator (the same seed always gives the same output); taken
+        /// from the clock and reported on stderr if not provided
+        #[arg(long)]
+        seed: Option<u64>,
     },
     /// Check if a semitopology satisfies a given formula
     Check {
         /// The formula to check (e.g., "AO X. EO Y. AP x. (x in X) || (X inter Y) => !(x in Y)")
-        #[arg(short = 'f', long)]
-        formula: String,
+        #[arg(short = 'f', long, required_unless_present = "formulas")]
+        formula: Option<String>,
+
+        /// File of formulas, one per line (blank lines and lines starting with # are skipped),
+        /// each checked against the semitopology with a pass/fail line
+        #[arg(long, conflicts_with_all = ["formula", "threads", "all", "stats", "timeout", "explain"])]
+        formulas: Option<String>,
         
         /// The semitopology to check against (e.g., "{{1, 2}, {1, 3}, {2, 3}, {1, 2, 3}}")
+        #[arg(short = 's', long, required_unless_present = "semitopologies")]
+        semitopology: Option<String>,
+
+        /// File of families, one per line ("-" for stdin, e.g. the output of search),
+        /// printing those that satisfy the formula
+        #[arg(long, conflicts_with_all = ["semitopology", "formulas", "assign", "close", "threads", "require_empty", "all", "stats", "timeout"])]
+        semitopologies: Option<String>,
+
+        /// Size n for the semitopology (auto-inferred if not provided)
+        #[arg(short = 'n', long)]
+        size: Option<usize>,
+
+        /// Pre-assign free variables (e.g., "X={1,2},p=3")
+        #[arg(long)]
+        assign: Option<String>,
+
+        /// Treat the semitopology as generators and close it under unions first
+        #[arg(long)]
+        close: bool,
+
+        /// Number of threads for the top-level quantifier (1 for sequential, >1 for parallel)
+        #[arg(short = 't', long = "threads", default_value = "1")]
+        threads: usize,
+
+        /// Fail if the semitopology (after --close, if given) does not contain the empty set
+        #[arg(long)]
+        require_empty: bool,
+
+        /// What open quantifiers range over: "family" or "powerset" (all subsets of the points)
+        #[arg(long, default_value = "family")]
+        opens: OpenDomain,
+
+        /// Print every binding of the leading existential quantifiers that satisfies the formula
+        #[arg(long, conflicts_with = "threads")]
+        all: bool,
+
+        /// Stop after this many models (with --all)
+        #[arg(long, requires = "all")]
+        max_models: Option<usize>,
+
+        /// Report how many atoms, quantifier bindings and cache lookups the check took
+        #[arg(long, conflicts_with_all = ["threads", "all"])]
+        stats: bool,
+
+        /// Give up after this many seconds
+        #[arg(long, conflicts_with_all = ["threads", "all", "stats"])]
+        timeout: Option<u64>,
+
+        /// Print the formula with all macros expanded before checking it
+        #[arg(long)]
+        explain: bool,
+    },
+    /// List the space predicates a semitopology satisfies and where each point predicate holds
+    Describe {
+        /// The semitopology to describe (e.g., "{{}, {1}, {1, 2}}")
         #[arg(short = 's', long)]
         semitopology: String,
 
@@ -91,8 +343,13 @@ enum Commands {
     /// Find semitopologies that satisfy a given formula
     Find {
         /// The formula to satisfy (e.g., "AO X. EO Y. AP x. (x in X) || (X inter Y) => !(x in Y)")
-        #[arg(short = 'f', long)]
-        formula: String,
+        #[arg(short = 'f', long, required_unless_present = "formulas")]
+        formula: Option<String>,
+
+        /// File of formulas, one per line (blank lines and lines starting with # are skipped);
+        /// requires an --output pattern containing {formula}
+        #[arg(long, conflicts_with_all = ["formula", "brute_verify"])]
+        formulas: Option<String>,
         
         /// Size to search for (single number or range like "3-5")
         #[arg(short = 's', long, default_value = "1-6")]
@@ -133,6 +390,50 @@ enum Commands {
         /// Suppress printing of found semitopologies (only show count)
         #[arg(short = 'q', long)]
         quiet: bool,
+
+        /// Cross-check the search against a brute-force enumeration (n <= 4 only)
+        #[arg(long)]
+        brute_verify: bool,
+
+        /// Only report minimal satisfying families (removing any open breaks the formula)
+        #[arg(long)]
+        minimal: bool,
+
+        /// Sort the output file once the search completes (requires --output)
+        #[arg(long)]
+        sort_output: bool,
+
+        /// What open quantifiers range over: "family" or "powerset" (all subsets of the points)
+        #[arg(long, default_value = "family")]
+        opens: OpenDomain,
+
+        /// How to write families: "text" (brace notation), "json" (one object per line) or "csv"
+        #[arg(long, default_value = "text")]
+        format: OutputFormat,
+
+        /// Comma-separated space predicates to tabulate with --format csv (e.g. "regular_space,hypertransitive_space")
+        #[arg(long)]
+        columns: Option<String>,
+
+        /// Stop each size after this many seconds, keeping its partial results, and go on to the next
+        #[arg(long, conflicts_with_all = ["formulas", "brute_verify"])]
+        per_size_timeout: Option<u64>,
+
+        /// Print the formula with all macros expanded before searching
+        #[arg(long, conflicts_with = "formulas")]
+        explain: bool,
+
+        /// Stop at the first satisfying family, trying the sizes in order, and print it
+        #[arg(long, conflicts_with_all = ["formulas", "limit", "output", "brute_verify", "sort_output", "quiet", "format", "columns"])]
+        first: bool,
+
+        /// Look for families violating the formula instead of satisfying it (counterexamples)
+        #[arg(long, conflicts_with = "formulas")]
+        negate: bool,
+
+        /// Only count the satisfying families (all of them, ignoring --limit), without printing or writing any
+        #[arg(long, conflicts_with_all = ["formulas", "limit", "output", "brute_verify", "sort_output", "quiet", "format", "columns", "per_size_timeout", "first"])]
+        count_only: bool,
     },
 }
 
@@ -147,9 +448,15 @@ fn parse_size_range(size_str: &str) -> Result<Vec<usize>, String> {
         if start > end {
             return Err(format!("Start {} is greater than end {}", start, end));
         }
+        if end > MAX_POINTS {
+            return Err(format!("Size n={} exceeds the maximum of {} points", end, MAX_POINTS));
+        }
         Ok((start..=end).collect())
     } else {
         let single: usize = size_str.parse().map_err(|_| format!("Invalid number: {}", size_str))?;
+        if single > MAX_POINTS {
+            return Err(format!("Size n={} exceeds the maximum of {} points", single, MAX_POINTS));
+        }
         Ok(vec![single])
     }
 }
@@ -158,6 +465,9 @@ fn parse_starting_family(family_str: &str, n: usize) -> Result<Family, String> {
     // Use the same parsing logic as the canon command
     let family = parse_family_str(family_str, n)
         .map_err(|e| format!("Invalid starting family format: {}", e))?;
+    // The search only ever adds sets that keep the family union-closed
+    check_union_closed(&family, n)
+        .map_err(|e| format!("Invalid starting family: {}", e))?;
     
     // Canonicalize the starting family
     let canonical_family = canonicalize_once(&family, n);
@@ -165,14 +475,44 @@ fn parse_starting_family(family_str: &str, n: usize) -> Result<Family, String> {
     Ok(canonical_family)
 }
 
+/// Parse a family given to `check` or `canon`. A family with no point in any of its
+/// sets (`{}` or `{{}}`) leaves nothing to infer n from, so it needs `--size`
+fn parse_family_arg<S: PointSet>(family_str: &str, size: Option<usize>) -> Result<(BTreeSet<S>, usize), String> {
+    if size.is_none() && !family_str.trim_start().starts_with("n=") {
+        if let Ok(family) = parse_family_str::<S>(family_str, S::MAX_POINTS) {
+            if infer_size_from_family(&family) == 0 {
+                return Err(format!("{} has no points to infer the size from; pass it with --size", family_str.trim()));
+            }
+        }
+    }
+    parse_family_line(family_str, size)
+}
+
+/// Warn on stderr when a family given to `check` or `canon` is not a semitopology
+fn warn_if_not_union_closed<S: PointSet>(family: &BTreeSet<S>, n: usize) {
+    if let Err(e) = check_union_closed(family, n) {
+        eprintln!("Warning: {} (pass --close to add the missing unions)", e);
+    }
+}
+
+/// Reject powerset quantification on sizes whose powerset is too large to enumerate
+fn check_open_domain(opens: OpenDomain, n: usize) -> Result<(), String> {
+    if opens == OpenDomain::Powerset && n > MAX_POWERSET_POINTS {
+        return Err(format!("--opens powerset supports n <= {} (got n={})", MAX_POWERSET_POINTS, n));
+    }
+    Ok(())
+}
+
+/// Build the search configuration shared by search, count and find; commands that write
+/// families set `output_pattern` themselves
 fn parse_search_args(
     size: String,
     limit: usize,
-    output: String,
     semiframes: bool,
     starting_family: Option<String>,
     log_interval: usize,
     threads: usize,
+    cache_size: usize,
 ) -> Result<Config, String> {
     let sizes = parse_size_range(&size)?;
     
@@ -189,126 +529,666 @@ fn parse_search_args(
     Ok(Config {
         sizes,
         limit,
-        output_pattern: output,
+        output_pattern: String::new(),
         search_semiframes: semiframes,
+        topologies: false,
         starting_family,
         log_interval,
         num_threads: threads,
+        cache_size,
+        largest_open_histogram: false,
+        histogram: false,
+        minimal: false,
+        sort_output: false,
+        exclude: None,
+        open_domain: OpenDomain::Family,
+        checkpoint: None,
+        resume: None,
+        batch_size: 100000,
+        max_stack: None,
+        format: OutputFormat::Text,
+        strategy: SearchStrategy::Dfs,
+        dedup: false,
+        property: None,
+        per_size_timeout: None,
+        sample: None,
+        include_empty: true,
     })
 }
 
+/// Print the warnings about a parsed formula to stderr, prefixed by `location`, and keep the formula
+fn warn_about(parsed: ParsedFormula, location: &str) -> Formula {
+    for warning in &parsed.warnings {
+        eprintln!("{}Warning: {}", location, warning);
+    }
+    parsed.formula
+}
+
+/// Attach the formulas named by `--columns` to a CSV output format
+fn with_columns(format: OutputFormat, columns: Option<String>) -> Result<OutputFormat, String> {
+    match (format, columns) {
+        (OutputFormat::Csv(_), Some(columns)) => columns.split(',')
+            .map(|name| {
+                let name = name.trim();
+                parse_closed_formula(name)
+                    .map(|parsed| (name.to_string(), parsed.formula))
+                    .map_err(|e| format!("Error parsing column {}: {}", name, e))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(OutputFormat::Csv),
+        (_, Some(_)) => Err("--columns requires --format csv".to_string()),
+        (format, None) => Ok(format),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn handle_search_command(
     size: String,
     limit: usize,
     output: String,
     semiframes: bool,
+    topologies: bool,
     starting_family: Option<String>,
     log_interval: usize,
     threads: usize,
+    cache_size: usize,
+    largest_open_histogram: bool,
+    histogram: bool,
+    sort_output: bool,
+    exclude: Option<String>,
+    json_summary: Option<String>,
+    batch_size: usize,
+    checkpoint: Option<String>,
+    resume: Option<String>,
+    max_stack: Option<usize>,
+    format: OutputFormat,
+    strategy: SearchStrategy,
+    dedup: bool,
+    property: Option<String>,
+    per_size_timeout: Option<Duration>,
+    sample: Option<(usize, u64)>,
+    include_empty: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let config = parse_search_args(
-        size, limit, output, semiframes,
-        starting_family, log_interval, threads
+    let mut config = parse_search_args(
+        size, limit, semiframes,
+        starting_family, log_interval, threads, cache_size
     ).map_err(|e| format!("Error parsing arguments: {}", e))?;
+    config.output_pattern = output;
+    config.topologies = topologies;
+    config.largest_open_histogram = largest_open_histogram;
+    config.histogram = histogram;
+    config.sort_output = sort_output;
+    config.batch_size = batch_size;
+    config.checkpoint = checkpoint;
+    config.resume = resume;
+    config.max_stack = max_stack;
+    config.format = format;
+    config.strategy = strategy;
+    config.dedup = dedup;
+    config.property = property.as_deref().map(parse_property).transpose()?;
+    config.per_size_timeout = per_size_timeout;
+    config.sample = sample;
+    config.include_empty = include_empty;
+    
+    // With "-o -" families go to stdout and everything else to stderr
+    let streaming = config.output_pattern == "-";
+    if streaming && config.sort_output {
+        return Err("--sort-output requires an output file; stdout results are streamed as they are found".into());
+    }
+    if let Some(ref path) = exclude {
+        let known = load_canonical_families(path)
+            .map_err(|e| format!("Error loading excluded families: {}", e))?;
+        status!(streaming, "Excluding {} known families from {}", known.len(), path);
+        config.exclude = Some(known);
+    }
     
     let total_start_time = Instant::now();
+    let mut runs = Vec::new();
     
     for n_val in &config.sizes {
         let start_time = Instant::now();
-        let (count, filename) = gen_fam(&config, *n_val)?;
+        let (count, filename, stats) = gen_fam(&config, *n_val)?;
         let end_time = Instant::now();
+        runs.push(SizeRun { n: *n_val, found: count, secs: (end_time - start_time).as_secs_f64() });
         
-        println!("\nResults for n={}:", n_val);
-        let search_type = if config.search_semiframes { "semiframes" } else { "semitopologies" };
-        println!("Total {} found: {}", search_type, count);
-        println!("Results saved in: {}", filename);
-        println!("Time taken: {:.3} seconds", (end_time - start_time).as_secs_f64());
-        println!("{}", "-".repeat(50));
+        status!(streaming, "\nResults for n={}:", n_val);
+        let search_type = config.search_type();
+        status!(streaming, "Total {} found: {}", search_type, count);
+        if !streaming {
+            println!("Results saved in: {}", filename);
+        }
+        if let Some(hist) = stats.largest_open {
+            status!(streaming, "Families by cardinality of largest open:");
+            for (card, families) in hist {
+                status!(streaming, "  largest open={}: {} families", card, families);
+            }
+        }
+        if let Some(hist) = stats.opens {
+            status!(streaming, "Families by number of opens:");
+            for (opens, families) in hist {
+                status!(streaming, "  opens={}: {} families", opens, families);
+            }
+        }
+        status!(streaming, "Time taken: {:.3} seconds", (end_time - start_time).as_secs_f64());
+        status!(streaming, "{}", "-".repeat(50));
     }
     
     let total_end_time = Instant::now();
-    println!("Total execution time: {:.3} seconds", (total_end_time - total_start_time).as_secs_f64());
+    let total_secs = (total_end_time - total_start_time).as_secs_f64();
+    status!(streaming, "Total execution time: {:.3} seconds", total_secs);
+    
+    if let Some(path) = json_summary {
+        std::fs::write(&path, summary_json(&runs, total_secs) + "\n")
+            .map_err(|e| format!("Error writing JSON summary to {}: {}", path, e))?;
+        status!(streaming, "Summary written to: {}", path);
+    }
     
     Ok(())
 }
 
-fn handle_canon_command(family_str: String, size: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
-    // First, try to infer size from the family if not provided
-    let temp_family = parse_family_str(&family_str, 32) // Use max possible size for parsing
-        .map_err(|e| format!("Error parsing family: {}", e))?;
+#[allow(clippy::too_many_arguments)]
+fn handle_count_command(
+    size: String,
+    limit: usize,
+    semiframes: bool,
+    topologies: bool,
+    starting_family: Option<String>,
+    log_interval: usize,
+    threads: usize,
+    cache_size: usize,
+    exclude: Option<String>,
+    json_summary: Option<String>,
+    strategy: SearchStrategy,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = parse_search_args(
+        size, limit, semiframes,
+        starting_family, log_interval, threads, cache_size
+    ).map_err(|e| format!("Error parsing arguments: {}", e))?;
+    config.topologies = topologies;
+    config.strategy = strategy;
+    if let Some(ref path) = exclude {
+        let known = load_canonical_families(path)
+            .map_err(|e| format!("Error loading excluded families: {}", e))?;
+        println!("Excluding {} known families from {}", known.len(), path);
+        config.exclude = Some(known);
+    }
     
-    let n = size.unwrap_or_else(|| infer_size_from_family(&temp_family));
+    let total_start_time = Instant::now();
+    let mut runs = Vec::new();
     
-    if n == 0 {
-        return Err("Could not determine size n. Please specify with --size or ensure family contains at least one non-empty set.".into());
+    for n_val in &config.sizes {
+        let start_time = Instant::now();
+        let count = count_fam(&config, *n_val)?;
+        let secs = start_time.elapsed().as_secs_f64();
+        runs.push(SizeRun { n: *n_val, found: count, secs });
+        // Padded to overwrite the progress line, if one was printed
+        let line = format!("n={}: {} {} ({:.3} seconds)", n_val, count, config.search_type(), secs);
+        println!("\r{:<72}", line);
     }
     
-    // Parse the family properly with the correct size
-    let family = parse_family_str(&family_str, n)
+    let total_secs = total_start_time.elapsed().as_secs_f64();
+    println!("Total execution time: {:.3} seconds", total_secs);
+    
+    if let Some(path) = json_summary {
+        std::fs::write(&path, summary_json(&runs, total_secs) + "\n")
+            .map_err(|e| format!("Error writing JSON summary to {}: {}", path, e))?;
+        println!("Summary written to: {}", path);
+    }
+    
+    Ok(())
+}
+
+/// Print the canonical form of every family in a file (or stdin for "-"), and nothing else
+fn handle_canon_input(input: &str, size: Option<usize>, close: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let reader: Box<dyn BufRead> = if input == "-" {
+        Box::new(std::io::stdin().lock())
+    } else {
+        let file = std::fs::File::open(input).map_err(|e| format!("Could not read {}: {}", input, e))?;
+        Box::new(BufReader::new(file))
+    };
+    canonicalize_lines(reader, std::io::stdout().lock(), size, close)
+        .map_err(|e| format!("{}: {}", input, e))?;
+    Ok(())
+}
+
+/// Print every family in a file (or stdin for "-") in another format, one per line
+fn handle_convert_command(input: &str, from: FamilyFormat, to: FamilyFormat, size: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+    let reader: Box<dyn BufRead> = if input == "-" {
+        Box::new(std::io::stdin().lock())
+    } else {
+        let file = std::fs::File::open(input).map_err(|e| format!("Could not read {}: {}", input, e))?;
+        Box::new(BufReader::new(file))
+    };
+    convert_lines(reader, std::io::stdout().lock(), from, to, size)
+        .map_err(|e| format!("{}: {}", input, e))?;
+    Ok(())
+}
+
+fn handle_canon_command(family_str: String, size: Option<usize>, close: bool, dot: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut family, n) = parse_family_arg(&family_str, size)
         .map_err(|e| format!("Error parsing family: {}", e))?;
+    if !close {
+        warn_if_not_union_closed(&family, n);
+    }
+    
+    if dot {
+        if close {
+            family = union_closure(&family, n);
+        }
+        print!("{}", family_to_dot(&family, n));
+        return Ok(());
+    }
     
     println!("Input family (n={}): {}", n, family_to_str(&family, n));
     
-    let canonical_family = canonicalize_once(&family, n);
+    if close {
+        family = union_closure(&family, n);
+        println!("Closed family: {}", family_to_str(&family, n));
+    }
+    
+    let (canonical_family, group_order) = canonicalize_with_autsize(&family, n);
     
     println!("Canonical form: {}", family_to_str(&canonical_family, n));
+    // Exact below 2^53; larger orders (from many interchangeable points) in scientific notation
+    if group_order < 9.0e15 {
+        println!("Automorphism group order: {}", group_order);
+    } else {
+        println!("Automorphism group order: {:e}", group_order);
+    }
+    let checker = ModelChecker::new(n, family);
+    println!("Connected components: {}", checker.num_components());
+    let irreducibles: Family = checker.join_irreducibles().into_iter().collect();
+    println!("Join-irreducible opens: {}", family_to_str(&irreducibles, n));
+    
+    Ok(())
+}
+
+fn handle_iso_command(family_a: String, family_b: String, size: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+    let (a, na) = parse_family_line(&family_a, size)
+        .map_err(|e| format!("Error parsing first family: {}", e))?;
+    let (b, nb) = parse_family_line(&family_b, size)
+        .map_err(|e| format!("Error parsing second family: {}", e))?;
+    let n = na.max(nb);
+    
+    println!("First (n={}): {}", n, family_to_str(&a, n));
+    println!("Second (n={}): {}", n, family_to_str(&b, n));
+    
+    match isomorphism(&a, &b, n) {
+        Some(perm) => {
+            println!("Isomorphic: yes");
+            let mapping: Vec<String> = perm.iter().enumerate()
+                .map(|(i, &j)| format!("{} -> {}", i + 1, j + 1))
+                .collect();
+            println!("Point mapping (first -> second): {}", mapping.join(", "));
+        }
+        None => {
+            println!("Isomorphic: no");
+            println!("Canonical forms: {} and {}",
+                     family_to_str(&canonicalize_once(&a, n), n),
+                     family_to_str(&canonicalize_once(&b, n), n));
+        }
+    }
+    
+    Ok(())
+}
+
+/// The given seed, or one taken from the clock and reported on stderr so the run can be repeated
+fn seed_or_clock(seed: Option<u64>) -> u64 {
+    seed.unwrap_or_else(|| {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        eprintln!("Seed: {}", seed);
+        seed
+    })
+}
+
+fn handle_random_command(n: usize, count: usize, seed: Option<u64>) -> Result<(), Box<dyn std::error::Error>> {
+    for family in random_families(n, count, seed_or_clock(seed))? {
+        println!("{}", family_to_str(&family, n));
+    }
+    Ok(())
+}
+
+fn handle_combine_command(
+    first: String,
+    second: String,
+    first_size: Option<usize>,
+    second_size: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (a, n1) = parse_family_line(&first, first_size)
+        .map_err(|e| format!("Error parsing first family: {}", e))?;
+    let (b, n2) = parse_family_line(&second, second_size)
+        .map_err(|e| format!("Error parsing second family: {}", e))?;
+    
+    println!("First (n={}): {}", n1, family_to_str(&a, n1));
+    println!("Second (n={}): {}", n2, family_to_str(&b, n2));
+    
+    let (combined, n) = disjoint_union(&a, n1, &b, n2)?;
+    
+    println!("Disjoint union (n={}): {}", n, family_to_str(&combined, n));
+    println!("Canonical form: {}", family_to_str(&canonicalize_once(&combined, n), n));
+    
+    Ok(())
+}
+
+fn handle_describe_command(semitopology_str: String, size: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut checker = ModelChecker::<Elem>::parse_family(&semitopology_str, size)
+        .map_err(|e| format!("Error parsing semitopology: {}", e))?;
+    let n = checker.n();
+    println!("Semitopology (n={}): {}", n, family_to_str(checker.family(), n));
+    
+    let description = checker.describe();
+    println!("\nSpace predicates:");
+    for (name, holds) in &description.space {
+        println!("  {:<26}{}", name, if *holds { "yes" } else { "no" });
+    }
+    println!("\nPoint predicates (points where each holds):");
+    for (name, points) in &description.points {
+        let points: Vec<String> = points.iter().map(|p| p.to_string()).collect();
+        println!("  {:<26}{{{}}}", name, points.join(", "));
+    }
+    let topens: Family = description.topens.into_iter().collect();
+    println!("\nTopens: {}", family_to_str(&topens, n));
     
     Ok(())
 }
 
-fn handle_check_command(formula_str: String, semitopology_str: String, size: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+#[allow(clippy::too_many_arguments)]
+fn handle_check_command(
+    formula_str: String,
+    semitopology_str: String,
+    size: Option<usize>,
+    assign: Option<String>,
+    close: bool,
+    threads: usize,
+    require_empty: bool,
+    opens: OpenDomain,
+    all: Option<Option<usize>>,
+    stats: bool,
+    timeout: Option<Duration>,
+    explain: bool,
+) -> Result<bool, Box<dyn std::error::Error>> {
     // Parse the formula
-    let formula = parse_formula(&formula_str)
+    let formula = parse_formula_with_warnings(&formula_str)
+        .map(|parsed| warn_about(parsed, ""))
         .map_err(|e| format!("Error parsing formula: {}", e))?;
+    if explain {
+        println!("Expanded: {}", formula_to_str(&formula));
+    }
     
-    // First, try to infer size from the family if not provided
-    let temp_family = parse_family_str(&semitopology_str, 32) // Use max possible size for parsing
+    // Families too wide for a bitmask are checked with the arbitrary-width BitSet
+    let (_, n) = parse_family_arg::<BitSet>(&semitopology_str, size)
         .map_err(|e| format!("Error parsing semitopology: {}", e))?;
+    check_open_domain(opens, n)?;
+    if n > MAX_POINTS {
+        run_check::<BitSet>(formula, formula_str, semitopology_str, size, assign, close, threads, require_empty, opens, all, stats, timeout)
+    } else {
+        run_check::<Elem>(formula, formula_str, semitopology_str, size, assign, close, threads, require_empty, opens, all, stats, timeout)
+    }
+}
+
+/// Parse the semitopology and assignment given to `check`, printing them, closing the
+/// family under unions if asked and enforcing --require-empty
+fn load_checker<S: PointSet>(
+    semitopology_str: &str,
+    size: Option<usize>,
+    assign: Option<String>,
+    close: bool,
+    require_empty: bool,
+    opens: OpenDomain,
+) -> Result<(ModelChecker<S>, Assignment<S>), Box<dyn std::error::Error>> {
+    let mut checker = ModelChecker::<S>::parse_family(semitopology_str, size)
+        .map_err(|e| format!("Error parsing semitopology: {}", e))?;
+    let n = checker.n();
+    
+    println!("Semitopology (n={}): {}", n, family_to_str(checker.family(), n));
+    if !close {
+        warn_if_not_union_closed(checker.family(), n);
+    }
     
-    let n = size.unwrap_or_else(|| infer_size_from_family(&temp_family));
+    if close {
+        checker = ModelChecker::from_family(n, union_closure(checker.family(), n));
+        println!("Closed family: {}", family_to_str(checker.family(), n));
+    }
+    let checker = checker.with_open_domain(opens);
     
-    if n == 0 {
-        return Err("Could not determine size n. Please specify with --size or ensure family contains at least one non-empty set.".into());
+    if require_empty && !has_empty(checker.family()) {
+        return Err("Semitopology does not contain the empty set {} (required by --require-empty)".into());
+    }
+    
+    let assignment = match assign {
+        Some(ref assign_str) => {
+            println!("Assignment: {}", assign_str);
+            Assignment::parse(assign_str, n)
+                .map_err(|e| format!("Error parsing assignment: {}", e))?
+        }
+        None => Assignment::new(),
+    };
+    Ok((checker, assignment))
+}
+
+/// Print the lines of `input` (a file, or `-` for stdin) whose family satisfies the
+/// formula, with a count on stderr so the output can be piped on
+fn handle_check_semitopologies_command(formula_str: String, input: &str, size: Option<usize>, opens: OpenDomain, explain: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let formula = parse_closed_formula(&formula_str)
+        .map(|parsed| warn_about(parsed, ""))
+        .map_err(|e| format!("Error parsing formula: {}", e))?;
+    if explain {
+        eprintln!("Expanded: {}", formula_to_str(&formula));
     }
     
-    // Parse the family properly with the correct size
-    let family = parse_family_str(&semitopology_str, n)
+    let text = if input == "-" {
+        std::io::read_to_string(std::io::stdin())
+    } else {
+        std::fs::read_to_string(input)
+    }.map_err(|e| format!("Error reading {}: {}", input, e))?;
+    let total = text.lines().filter(|line| !line.trim().is_empty()).count();
+    let matching = filter_family_lines(&text, size, &formula, opens)
+        .map_err(|e| format!("{}:{}", input, e.strip_prefix("line ").unwrap_or(&e)))?;
+    
+    for line in &matching {
+        println!("{}", line);
+    }
+    eprintln!("{} of {} families satisfy the formula", matching.len(), total);
+    Ok(!matching.is_empty())
+}
+
+fn handle_check_formulas_command(
+    formulas_path: String,
+    semitopology_str: String,
+    size: Option<usize>,
+    assign: Option<String>,
+    close: bool,
+    require_empty: bool,
+    opens: OpenDomain,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(&formulas_path)
+        .map_err(|e| format!("Error reading {}: {}", formulas_path, e))?;
+    let (_, n) = parse_family_arg::<BitSet>(&semitopology_str, size)
         .map_err(|e| format!("Error parsing semitopology: {}", e))?;
+    check_open_domain(opens, n)?;
+    if n > MAX_POINTS {
+        run_check_formulas::<BitSet>(&formulas_path, &text, &semitopology_str, size, assign, close, require_empty, opens)
+    } else {
+        run_check_formulas::<Elem>(&formulas_path, &text, &semitopology_str, size, assign, close, require_empty, opens)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_check_formulas<S: PointSet>(
+    formulas_path: &str,
+    text: &str,
+    semitopology_str: &str,
+    size: Option<usize>,
+    assign: Option<String>,
+    close: bool,
+    require_empty: bool,
+    opens: OpenDomain,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let (mut checker, assignment) = load_checker::<S>(semitopology_str, size, assign, close, require_empty, opens)?;
+    let results = checker.check_lines(text, &assignment)
+        .map_err(|e| format!("{}:{}", formulas_path, e.strip_prefix("line ").unwrap_or(&e)))?;
+    if results.is_empty() {
+        return Err(format!("No formulas found in {}", formulas_path).into());
+    }
     
+    for (formula, satisfied) in &results {
+        println!("{} {}", if *satisfied { "✓ PASS" } else { "✗ FAIL" }, formula);
+    }
+    let passed = results.iter().filter(|(_, satisfied)| *satisfied).count();
+    println!("Passed: {} of {}", passed, results.len());
+    Ok(passed == results.len())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_check<S: PointSet>(
+    formula: Formula,
+    formula_str: String,
+    semitopology_str: String,
+    size: Option<usize>,
+    assign: Option<String>,
+    close: bool,
+    threads: usize,
+    require_empty: bool,
+    opens: OpenDomain,
+    all: Option<Option<usize>>,
+    stats: bool,
+    timeout: Option<Duration>,
+) -> Result<bool, Box<dyn std::error::Error>> {
     println!("Formula: {}", formula_str);
-    println!("Semitopology (n={}): {}", n, family_to_str(&family, n));
+    let (mut checker, assignment) = load_checker::<S>(&semitopology_str, size, assign, close, require_empty, opens)?;
+    let n = checker.n();
+    check_bound(&formula, &assignment)
+        .map_err(|e| format!("Error in formula: {} (bind it with a quantifier or --assign)", e))?;
+    
+    if let Some(max_models) = all {
+        let models = checker.all_models(&formula, &assignment, max_models);
+        for (i, model) in models.iter().enumerate() {
+            let mut bindings: HashMap<String, Witness<S>> = model.points.iter()
+                .map(|(var, &p)| (var.clone(), Witness::Point(p)))
+                .collect();
+            for (var, open) in &model.opens {
+                let origin = if checker.family().contains(open) { OpenOrigin::Family } else { OpenOrigin::Subset };
+                bindings.insert(var.clone(), Witness::Open(open.clone(), origin));
+            }
+            print_bindings(&format!("Model {}", i + 1), &bindings, n);
+        }
+        println!("Models: {}", models.len());
+        return Ok(!models.is_empty());
+    }
+    
+    let mut check_stats = None;
+    let result = if threads > 1 {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|e| format!("Failed to initialize thread pool: {}", e))?;
+        pool.install(|| checker.check_parallel(&formula, &assignment))
+    } else if stats {
+        let (result, s) = checker.check_with_stats(&formula, &assignment);
+        check_stats = Some(s);
+        result
+    } else if let Some(timeout) = timeout {
+        checker.check_with_deadline(&formula, &assignment, timeout)?
+    } else {
+        checker.check_with(&formula, &assignment)
+    };
     
-    // Create model checker and check the formula
-    let mut checker = ModelChecker::new(n, family);
-    let result = checker.check(&formula);
+    for warning in &result.warnings {
+        eprintln!("Warning: {}", warning);
+    }
     
     if result.satisfied {
         println!("Result: ✓ SATISFIED");
-        
-        if !result.witnesses.is_empty() {
-            println!("Witnesses:");
-            for (var, witness) in result.witnesses {
-                match witness {
-                    Witness::Point(p) => println!("  {} = point {}", var, p),
-                    Witness::Open(mask) => {
-                        let mut open_points = Vec::new();
-                        for i in 0..n {
-                            if (mask >> i) & 1 == 1 {
-                                open_points.push(i + 1);
-                            }
-                        }
-                        println!("  {} = {{{}}}", var, open_points.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", "));
+        print_bindings("Witnesses", &result.witnesses, n);
+    } else {
+        println!("Result: ✗ NOT SATISFIED");
+        print_bindings("Counterexamples", &result.counterexamples, n);
+    }
+    
+    if let Some(s) = check_stats {
+        println!("Statistics:");
+        println!("  Atoms evaluated: {}", s.atom_evaluations);
+        println!("  Quantifier bindings: {}", s.quantifier_expansions);
+        println!("  Community cache: {} hits, {} misses", s.community_cache_hits, s.community_cache_misses);
+        println!("  Interior complement cache: {} hits, {} misses", s.ic_cache_hits, s.ic_cache_misses);
+    }
+    
+    Ok(result.satisfied)
+}
+
+/// Print the witnesses or counterexamples of a check result, if there are any
+fn print_bindings<S: PointSet>(title: &str, bindings: &HashMap<String, Witness<S>>, n: usize) {
+    if bindings.is_empty() {
+        return;
+    }
+    println!("{}:", title);
+    for (var, witness) in bindings {
+        match witness {
+            Witness::Point(p) => println!("  {} = point {}", var, p),
+            Witness::Open(mask, origin) => {
+                let mut open_points = Vec::new();
+                for i in 0..n {
+                    if mask.contains(i) {
+                        open_points.push(i + 1);
                     }
                 }
+                let origin_str = match origin {
+                    OpenOrigin::Family => "family member".to_string(),
+                    OpenOrigin::Community(p) => format!("community of point {}", p),
+                    OpenOrigin::InteriorComplement => "interior complement".to_string(),
+                    OpenOrigin::Closure => "closure".to_string(),
+                    OpenOrigin::Boundary => "boundary".to_string(),
+                    OpenOrigin::Subset => "not a family member".to_string(),
+                };
+                println!("  {} = {{{}}} ({})", var, open_points.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", "), origin_str);
             }
         }
-    } else {
-        println!("Result: ✗ NOT SATISFIED");
     }
-    
+}
+
+fn handle_brute_verify(formula: &Formula, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    if config.starting_family.is_some() {
+        return Err("--brute-verify cannot be combined with --starting-family".into());
+    }
+    if let Some(&n) = config.sizes.iter().find(|&&n| n > search::BRUTE_VERIFY_MAX_N) {
+        return Err(format!("--brute-verify only supports n <= {} (got n={})", search::BRUTE_VERIFY_MAX_N, n).into());
+    }
+
+    let search_type = config.search_type();
+    let mut discrepancies = 0;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.num_threads)
+        .build()
+        .map_err(|e| format!("Failed to initialize thread pool: {}", e))?;
+
+    for &n_val in &config.sizes {
+        let (found, explored) = search::collect_fam_with_formula(config, n_val, formula)?;
+        let brute = pool.install(|| search::brute_force_families(n_val, config.search_semiframes, config.topologies, config.minimal, formula, config.open_domain));
+
+        println!("\nVerification for n={}:", n_val);
+        println!("Total {} explored: {}", search_type, explored);
+        println!("Satisfying (search): {}", found.len());
+        println!("Satisfying (brute force): {}", brute.len());
+
+        for fam in brute.difference(&found) {
+            println!("  Missed by search: {}", family_to_str(fam, n_val));
+            discrepancies += 1;
+        }
+        for fam in found.difference(&brute) {
+            println!("  Not found by brute force: {}", family_to_str(fam, n_val));
+            discrepancies += 1;
+        }
+        println!("{}", "-".repeat(50));
+    }
+
+    if discrepancies > 0 {
+        return Err(format!("Brute-force verification found {} discrepancies", discrepancies).into());
+    }
+    println!("Brute-force verification passed.");
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_find_command(
     formula_str: String,
     size: String,
@@ -318,29 +1198,79 @@ fn handle_find_command(
     starting_family: Option<String>,
     log_interval: usize,
     threads: usize,
+    cache_size: usize,
     quiet: bool,
+    brute_verify: bool,
+    minimal: bool,
+    sort_output: bool,
+    opens: OpenDomain,
+    format: OutputFormat,
+    per_size_timeout: Option<Duration>,
+    explain: bool,
+    first: bool,
+    count_only: bool,
+    negate: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Parse the formula first
-    let formula = parse_formula(&formula_str)
+    let formula = parse_closed_formula(&formula_str)
+        .map(|parsed| warn_about(parsed, ""))
         .map_err(|e| format!("Error parsing formula: {}", e))?;
+    let (formula, verdict) = if negate {
+        (Formula::Not(Box::new(formula)), "violating")
+    } else {
+        (formula, "satisfying")
+    };
+    if explain {
+        println!("Expanded: {}", formula_to_str(&formula));
+    }
     
-    println!("Searching for semitopologies satisfying formula: {}", formula_str);
+    if brute_verify {
+        let mut config = parse_search_args(
+            size, limit, semiframes,
+            starting_family, log_interval, threads, cache_size
+        ).map_err(|e| format!("Error parsing arguments: {}", e))?;
+        config.minimal = minimal;
+        config.open_domain = opens;
+        println!("Verifying search for {} families against brute force: {}", verdict, formula_str);
+        return handle_brute_verify(&formula, &config);
+    }
+    
+    println!("Searching for semitopologies {} formula: {}", verdict, formula_str);
     
     // Determine if we should output to file or console
     let output_to_file = output.is_some();
+    if sort_output && !output_to_file {
+        return Err("--sort-output requires --output; console results are streamed as they are found".into());
+    }
     let output_pattern = output.unwrap_or_else(|| "console".to_string());
     
     // Create a modified config that includes the formula
-    let config = parse_search_args(
-        size, limit, output_pattern, semiframes,
-        starting_family, log_interval, threads
+    let mut config = parse_search_args(
+        size, if count_only { 0 } else { limit }, semiframes,
+        starting_family, log_interval, threads, cache_size
     ).map_err(|e| format!("Error parsing arguments: {}", e))?;
+    config.output_pattern = output_pattern;
+    config.minimal = minimal;
+    config.sort_output = sort_output;
+    config.open_domain = opens;
+    config.format = format;
+    config.per_size_timeout = per_size_timeout;
+    for &n_val in &config.sizes {
+        check_open_domain(opens, n_val)?;
+    }
+    
+    if first {
+        return find_first_over_sizes(&config, &formula, verdict);
+    }
     
     let total_start_time = Instant::now();
     
     for n_val in &config.sizes {
         let start_time = Instant::now();
-        let (results, explored, filename) = if output_to_file {
+        let (results, explored, filename) = if count_only {
+            let (results, explored) = search::count_fam_with_formula(&config, *n_val, &formula)?;
+            (results, explored, String::new())
+        } else if output_to_file {
             search::gen_fam_with_formula(&config, *n_val, &formula)?
         } else {
             search::gen_fam_with_formula_console(&config, *n_val, &formula, quiet)?
@@ -348,14 +1278,14 @@ fn handle_find_command(
         let end_time = Instant::now();
         
         println!("\nResults for n={}:", n_val);
-        let search_type = if config.search_semiframes { "semiframes" } else { "semitopologies" };
+        let search_type = config.search_type();
         
         println!("Total {} explored: {}", search_type, explored);
         if output_to_file {
-            println!("Total {} satisfying formula: {}", search_type, results);
+            println!("Total {} {} formula: {}", search_type, verdict, results);
             println!("Results saved in: {}", filename);
         } else {
-            println!("Total {} satisfying formula: {}", search_type, results);
+            println!("Total {} {} formula: {}", search_type, verdict, results);
         }
         
         println!("Time taken: {:.3} seconds", (end_time - start_time).as_secs_f64());
@@ -368,32 +1298,198 @@ fn handle_find_command(
     Ok(())
 }
 
+/// Answer "does an example exist?": print the first satisfying family of the smallest size
+/// that has one, stopping the search as soon as it is found (`verdict` says what it is)
+fn find_first_over_sizes(config: &Config, formula: &Formula, verdict: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let search_type = config.search_type();
+    for &n_val in &config.sizes {
+        let start_time = Instant::now();
+        let (family, explored) = search::find_first(config, n_val, formula)?;
+        let secs = start_time.elapsed().as_secs_f64();
+        match family {
+            Some(family) => {
+                println!("n={}: found after exploring {} {} ({:.3} seconds)", n_val, explored, search_type, secs);
+                println!("{}", family_to_str(&family, n_val));
+                return Ok(());
+            }
+            None => println!("n={}: none among {} {} explored ({:.3} seconds)", n_val, explored, search_type, secs),
+        }
+    }
+    println!("No {} {} the formula", search_type, verdict);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_find_formulas_command(
+    formulas_path: String,
+    size: String,
+    limit: usize,
+    output: Option<String>,
+    semiframes: bool,
+    starting_family: Option<String>,
+    log_interval: usize,
+    threads: usize,
+    cache_size: usize,
+    minimal: bool,
+    opens: OpenDomain,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let output_pattern = match output {
+        Some(pattern) if pattern.contains("{formula}") => pattern,
+        _ => return Err("--formulas requires an --output pattern containing {formula}".into()),
+    };
+    
+    let text = std::fs::read_to_string(&formulas_path)
+        .map_err(|e| format!("Error reading {}: {}", formulas_path, e))?;
+    let mut formulas: Vec<(String, Formula)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let location = format!("{}:{}: ", formulas_path, i + 1);
+        let formula = parse_closed_formula(line)
+            .map(|parsed| warn_about(parsed, &location))
+            .map_err(|e| format!("{}Error parsing formula: {}", location, e))?;
+        let name = search::sanitize_formula_name(line);
+        if formulas.iter().any(|(other, _)| *other == name) {
+            return Err(format!("{}:{}: formula name {} is used more than once", formulas_path, i + 1, name).into());
+        }
+        formulas.push((name, formula));
+    }
+    if formulas.is_empty() {
+        return Err(format!("No formulas found in {}", formulas_path).into());
+    }
+    
+    let mut config = parse_search_args(
+        size, limit, semiframes,
+        starting_family, log_interval, threads, cache_size
+    ).map_err(|e| format!("Error parsing arguments: {}", e))?;
+    config.output_pattern = output_pattern;
+    config.minimal = minimal;
+    config.open_domain = opens;
+    config.format = format;
+    for &n_val in &config.sizes {
+        check_open_domain(opens, n_val)?;
+    }
+    
+    let total_start_time = Instant::now();
+    let mut matrix = Vec::new();
+    
+    for n_val in &config.sizes {
+        let (counts, _) = search::gen_fam_with_formulas(&config, *n_val, &formulas)?;
+        matrix.push(counts);
+        println!("{}", "-".repeat(50));
+    }
+    
+    // One row per formula, one column per size
+    let width = formulas.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max("formula".len());
+    print!("{:<width$}", "formula", width = width);
+    for n_val in &config.sizes {
+        print!(" {:>8}", format!("n={}", n_val));
+    }
+    println!();
+    for (i, (name, _)) in formulas.iter().enumerate() {
+        print!("{:<width$}", name, width = width);
+        for counts in &matrix {
+            print!(" {:>8}", counts[i]);
+        }
+        println!();
+    }
+    
+    let total_end_time = Instant::now();
+    println!("Total execution time: {:.3} seconds", (total_end_time - total_start_time).as_secs_f64());
+    
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
     
     match args.command {
         Commands::Search { 
-            size, limit, output, semiframes, 
-            starting_family, log_interval, threads, ..
+            size, cache_size, limit, output, semiframes, topologies,
+            starting_family, log_interval, threads,
+            largest_open_histogram, histogram, sort_output, exclude, json_summary,
+            batch_size, checkpoint, resume, max_stack, format, columns, strategy, dedup, property, per_size_timeout, sample, seed, no_empty, ..
         } => {
+            let format = with_columns(format, columns)?;
+            let sample = sample.map(|k| (k, seed_or_clock(seed)));
             handle_search_command(
-                size, limit, output, semiframes,
-                starting_family, log_interval, threads
+                size, limit, output, semiframes, topologies,
+                starting_family, log_interval, threads, cache_size,
+                largest_open_histogram, histogram, sort_output, exclude, json_summary,
+                batch_size, checkpoint, resume, max_stack, format, strategy, dedup, property,
+                per_size_timeout.map(Duration::from_secs), sample, !no_empty
             )
         }
-        Commands::Canon { family, size } => {
-            handle_canon_command(family, size)
+        Commands::Count {
+            size, cache_size, limit, semiframes, topologies, starting_family,
+            log_interval, threads, exclude, json_summary, strategy
+        } => {
+            handle_count_command(
+                size, limit, semiframes, topologies, starting_family,
+                log_interval, threads, cache_size, exclude, json_summary, strategy
+            )
+        }
+        Commands::Canon { family, input, size, close, dot } => {
+            match input {
+                Some(input) => handle_canon_input(&input, size, close),
+                None => handle_canon_command(family.unwrap_or_default(), size, close, dot),
+            }
+        }
+        Commands::Convert { input, from, to, size } => {
+            handle_convert_command(&input, from, to, size)
+        }
+        Commands::Combine { first, second, first_size, second_size } => {
+            handle_combine_command(first, second, first_size, second_size)
+        }
+        Commands::Iso { family_a, family_b, size } => {
+            handle_iso_command(family_a, family_b, size)
         }
-        Commands::Check { formula, semitopology, size } => {
-            handle_check_command(formula, semitopology, size)
+        Commands::Random { size, count, seed } => {
+            handle_random_command(size, count, seed)
+        }
+        Commands::Check { formula, formulas, semitopology, semitopologies, size, assign, close, threads, require_empty, opens, all, max_models, stats, timeout, explain } => {
+            let semitopology = semitopology.unwrap_or_default();
+            let all = all.then_some(max_models);
+            let outcome = if let Some(input) = semitopologies {
+                handle_check_semitopologies_command(formula.unwrap_or_default(), &input, size, opens, explain)
+            } else if let Some(formulas_path) = formulas {
+                handle_check_formulas_command(formulas_path, semitopology, size, assign, close, require_empty, opens)
+            } else {
+                handle_check_command(formula.unwrap_or_default(), semitopology, size, assign, close, threads, require_empty, opens, all, stats, timeout.map(Duration::from_secs), explain)
+            };
+            // Exit codes for scripts: 0 satisfied, 1 not satisfied, 2 error (as for bad arguments)
+            match outcome {
+                Ok(true) => Ok(()),
+                Ok(false) => std::process::exit(1),
+                Err(e) => {
+                    eprintln!("Error: {:?}", e);
+                    std::process::exit(2)
+                }
+            }
+        }
+        Commands::Describe { semitopology, size } => {
+            handle_describe_command(semitopology, size)
         }
         Commands::Find { 
-            formula, size, limit, output, semiframes, 
-            starting_family, log_interval, threads, quiet, ..
+            formula, formulas, size, cache_size, limit, output, semiframes, 
+            starting_family, log_interval, threads, quiet,
+            brute_verify, minimal, sort_output, opens, format, columns, per_size_timeout, explain, first, count_only, negate, ..
         } => {
+            let format = with_columns(format, columns)?;
+            if let Some(formulas_path) = formulas {
+                return handle_find_formulas_command(
+                    formulas_path, size, limit, output, semiframes,
+                    starting_family, log_interval, threads, cache_size, minimal, opens, format
+                );
+            }
             handle_find_command(
-                formula, size, limit, output, semiframes,
-                starting_family, log_interval, threads, quiet
+                formula.unwrap_or_default(), size, limit, output, semiframes,
+                starting_family, log_interval, threads, cache_size, quiet,
+                brute_verify, minimal, sort_output, opens, format,
+                per_size_timeout.map(Duration::from_secs), explain, first, count_only, negate
             )
         }
     }
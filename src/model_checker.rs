@@ -1,24 +1,51 @@
 //! Model checker for semitopology propositions.
 
-use crate::canon::Family;
-use std::collections::HashMap;
+use crate::bitset::{BitSet, PointSet};
+use crate::canon::{Elem, Family, MAX_POINTS, has_empty, parse_family_line, parse_single_set};
+use std::collections::BTreeSet;
+use crate::parser::parse_formula;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use rayon::prelude::*;
 
 
 /// Open expressions that can be evaluated to concrete open sets
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OpenExpr {
     /// Simple open variable
     Var(String),
     /// Community of a point (K p)
     Community(String),
+    /// Minimal open neighbourhood of a point (MON p): the intersection of the opens
+    /// containing it, a raw subset of the points (empty if no open contains it)
+    MinimalNeighbourhood(String),
     /// Interior complement of an open expression (IC O)
     InteriorComplement(Box<OpenExpr>),
+    /// Closure of an open expression (Cl O), a raw subset of the points
+    Closure(Box<OpenExpr>),
+    /// Boundary of an open expression (Bd O): its closure minus its interior
+    Boundary(Box<OpenExpr>),
+    /// Union of two open expressions (O union P), a raw subset of the points
+    Union(Box<OpenExpr>, Box<OpenExpr>),
+    /// Intersection of two open expressions (O cap P), a raw subset of the points
+    Inter(Box<OpenExpr>, Box<OpenExpr>),
+    /// A literal set of points ({1, 3}), a raw subset of the points
+    Lit(Vec<usize>),
+    /// All n points (UNIV), whether or not the family contains them
+    Univ,
+    /// The empty set (EMPTY), whether or not the family contains it
+    Empty,
 }
 
 /// Atomic propositions
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Atom {
-    /// Point x is in open expression
+    /// Point x is in open expression (point names that are numbers denote literal points)
     PointInOpen(String, OpenExpr),
     /// Two open expressions intersect
     OpenIntersection(OpenExpr, OpenExpr),
@@ -32,11 +59,20 @@ pub enum Atom {
     PointEqual(String, String),
     /// Two open expressions are equal
     OpenEqual(OpenExpr, OpenExpr),
+    /// The first open expression is contained in the second
+    Subset(OpenExpr, OpenExpr),
+    /// Open expression is nonempty and transitive with respect to the family
+    IsTopen(OpenExpr),
 }
 
 /// Proposition formulas
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Formula {
+    /// The constant true
+    True,
+    /// The constant false
+    False,
     /// Atomic proposition
     Atom(Atom),
     /// Logical negation
@@ -49,6 +85,8 @@ pub enum Formula {
     Implies(Box<Formula>, Box<Formula>),
     /// Material equivalence (if and only if)
     Iff(Box<Formula>, Box<Formula>),
+    /// Exclusive or: exactly one side holds
+    Xor(Box<Formula>, Box<Formula>),
     /// Universal quantification over points
     ForAllPoints(String, Box<Formula>),
     /// Existential quantification over points
@@ -57,18 +95,424 @@ pub enum Formula {
     ForAllOpens(String, Box<Formula>),
     /// Existential quantification over opens
     ExistsOpens(String, Box<Formula>),
+    /// Universal quantification over the topens (nonempty transitive opens) of the family
+    ForAllTopens(String, Box<Formula>),
+    /// Existential quantification over the topens of the family
+    ExistsTopens(String, Box<Formula>),
+}
+
+/// Print a core formula in the surface syntax, so that `parse_formula` reads it back
+///
+/// Only the parentheses the grammar needs are printed: quantifiers extend as far right
+/// as possible, so one that is an operand of a connective is parenthesized.
+pub fn formula_to_str(formula: &Formula) -> String {
+    formula_at(formula, 0)
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&formula_to_str(self))
+    }
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&atom_to_str(self))
+    }
+}
+
+impl fmt::Display for OpenExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&open_expr_at(self, 0))
+    }
+}
+
+/// How tightly a formula binds: quantifiers lowest, then `=>`, `<=>`/`^^`, `||`, `&&`, `!`
+fn formula_precedence(formula: &Formula) -> u8 {
+    match formula {
+        Formula::ForAllPoints(..) | Formula::ExistsPoints(..)
+        | Formula::ForAllOpens(..) | Formula::ExistsOpens(..)
+        | Formula::ForAllTopens(..) | Formula::ExistsTopens(..) => 0,
+        Formula::Implies(..) => 1,
+        Formula::Iff(..) | Formula::Xor(..) => 2,
+        Formula::Or(..) => 3,
+        Formula::And(..) => 4,
+        Formula::Not(_) => 5,
+        Formula::True | Formula::False | Formula::Atom(_) => 6,
+    }
+}
+
+/// Print a formula, parenthesized if it binds less tightly than `min`
+fn formula_at(formula: &Formula, min: u8) -> String {
+    let binary = |f1: &Formula, op: &str, f2: &Formula, left: u8, right: u8| {
+        format!("{} {} {}", formula_at(f1, left), op, formula_at(f2, right))
+    };
+    let quantifier = |q: &str, var: &str, f: &Formula| format!("{} {}. {}", q, var, formula_at(f, 0));
+    let printed = match formula {
+        Formula::True => "true".to_string(),
+        Formula::False => "false".to_string(),
+        Formula::Atom(atom) => atom_to_str(atom),
+        Formula::Not(f) if matches!(**f, Formula::Not(_)) => format!("!{}", formula_at(f, 5)),
+        Formula::Not(f) => format!("!({})", formula_at(f, 0)),
+        // `=>` associates to the right, the other connectives to the left
+        Formula::Implies(f1, f2) => binary(f1, "=>", f2, 2, 1),
+        Formula::Iff(f1, f2) => binary(f1, "<=>", f2, 2, 3),
+        Formula::Xor(f1, f2) => binary(f1, "^^", f2, 2, 3),
+        Formula::Or(f1, f2) => binary(f1, "||", f2, 3, 4),
+        Formula::And(f1, f2) => binary(f1, "&&", f2, 4, 5),
+        Formula::ForAllPoints(var, f) => quantifier("AP", var, f),
+        Formula::ExistsPoints(var, f) => quantifier("EP", var, f),
+        Formula::ForAllOpens(var, f) => quantifier("AO", var, f),
+        Formula::ExistsOpens(var, f) => quantifier("EO", var, f),
+        Formula::ForAllTopens(var, f) => quantifier("AT", var, f),
+        Formula::ExistsTopens(var, f) => quantifier("ET", var, f),
+    };
+    if formula_precedence(formula) < min {
+        format!("({})", printed)
+    } else {
+        printed
+    }
+}
+
+fn atom_to_str(atom: &Atom) -> String {
+    let open = |o: &OpenExpr| open_expr_at(o, 0);
+    match atom {
+        Atom::PointInOpen(x, o) => format!("{} in {}", x, open(o)),
+        Atom::OpenIntersection(o1, o2) => format!("{} inter {}", open(o1), open(o2)),
+        Atom::OpenNonempty(o) => format!("nonempty {}", open(o)),
+        Atom::PointNotEqual(x, y) => format!("{} != {}", x, y),
+        Atom::OpenNotEqual(o1, o2) => format!("{} != {}", open(o1), open(o2)),
+        Atom::PointEqual(x, y) => format!("{} = {}", x, y),
+        Atom::OpenEqual(o1, o2) => format!("{} = {}", open(o1), open(o2)),
+        Atom::Subset(o1, o2) => format!("{} subseteq {}", open(o1), open(o2)),
+        Atom::IsTopen(o) => format!("is_topen {}", open(o)),
+    }
+}
+
+/// Print an open expression, parenthesized if it binds less tightly than `min`
+/// (`union` is 0, `cap` is 1 and everything else is 2; both operators associate to the left)
+fn open_expr_at(expr: &OpenExpr, min: u8) -> String {
+    let (printed, precedence) = match expr {
+        OpenExpr::Var(name) => (name.clone(), 2),
+        OpenExpr::Community(x) => (format!("K {}", x), 2),
+        OpenExpr::MinimalNeighbourhood(x) => (format!("MON {}", x), 2),
+        OpenExpr::InteriorComplement(o) => (format!("IC {}", open_expr_at(o, 2)), 2),
+        OpenExpr::Closure(o) => (format!("Cl {}", open_expr_at(o, 2)), 2),
+        OpenExpr::Boundary(o) => (format!("Bd {}", open_expr_at(o, 2)), 2),
+        OpenExpr::Union(o1, o2) => (format!("{} union {}", open_expr_at(o1, 0), open_expr_at(o2, 1)), 0),
+        OpenExpr::Inter(o1, o2) => (format!("{} cap {}", open_expr_at(o1, 1), open_expr_at(o2, 2)), 1),
+        OpenExpr::Lit(points) => {
+            let points: Vec<String> = points.iter().map(|p| p.to_string()).collect();
+            (format!("{{{}}}", points.join(", ")), 2)
+        }
+        OpenExpr::Univ => ("UNIV".to_string(), 2),
+        OpenExpr::Empty => ("EMPTY".to_string(), 2),
+    };
+    if precedence < min {
+        format!("({})", printed)
+    } else {
+        printed
+    }
+}
+
+/// Negation normal form: an equivalent formula in which `Not` only wraps atoms
+///
+/// Implications and equivalences are expanded into conjunctions and disjunctions, and
+/// negations are pushed inward with De Morgan's laws, swapping `AP`/`EP` and `AO`/`EO`.
+/// Negated atoms are kept as `Not(Atom)` rather than flipped (e.g. `=` to `!=`): atoms
+/// over unbound variables are false, so `!(p = q)` and `p != q` can differ.
+pub fn to_nnf(formula: &Formula) -> Formula {
+    let nnf = |f: &Formula| Box::new(to_nnf(f));
+    let neg = |f: &Formula| Box::new(negated_nnf(f));
+    match formula {
+        Formula::True | Formula::False | Formula::Atom(_) => formula.clone(),
+        Formula::Not(f) => negated_nnf(f),
+        Formula::And(f1, f2) => Formula::And(nnf(f1), nnf(f2)),
+        Formula::Or(f1, f2) => Formula::Or(nnf(f1), nnf(f2)),
+        Formula::Implies(f1, f2) => Formula::Or(neg(f1), nnf(f2)),
+        Formula::Iff(f1, f2) => Formula::Or(
+            Box::new(Formula::And(nnf(f1), nnf(f2))),
+            Box::new(Formula::And(neg(f1), neg(f2))),
+        ),
+        Formula::Xor(f1, f2) => Formula::Or(
+            Box::new(Formula::And(nnf(f1), neg(f2))),
+            Box::new(Formula::And(neg(f1), nnf(f2))),
+        ),
+        Formula::ForAllPoints(var, f) => Formula::ForAllPoints(var.clone(), nnf(f)),
+        Formula::ExistsPoints(var, f) => Formula::ExistsPoints(var.clone(), nnf(f)),
+        Formula::ForAllOpens(var, f) => Formula::ForAllOpens(var.clone(), nnf(f)),
+        Formula::ExistsOpens(var, f) => Formula::ExistsOpens(var.clone(), nnf(f)),
+        Formula::ForAllTopens(var, f) => Formula::ForAllTopens(var.clone(), nnf(f)),
+        Formula::ExistsTopens(var, f) => Formula::ExistsTopens(var.clone(), nnf(f)),
+    }
+}
+
+/// Negation normal form of `!formula`
+fn negated_nnf(formula: &Formula) -> Formula {
+    let nnf = |f: &Formula| Box::new(to_nnf(f));
+    let neg = |f: &Formula| Box::new(negated_nnf(f));
+    match formula {
+        Formula::True => Formula::False,
+        Formula::False => Formula::True,
+        Formula::Atom(_) => Formula::Not(Box::new(formula.clone())),
+        Formula::Not(f) => to_nnf(f),
+        Formula::And(f1, f2) => Formula::Or(neg(f1), neg(f2)),
+        Formula::Or(f1, f2) => Formula::And(neg(f1), neg(f2)),
+        Formula::Implies(f1, f2) => Formula::And(nnf(f1), neg(f2)),
+        Formula::Iff(f1, f2) => Formula::Or(
+            Box::new(Formula::And(nnf(f1), neg(f2))),
+            Box::new(Formula::And(neg(f1), nnf(f2))),
+        ),
+        Formula::Xor(f1, f2) => Formula::Or(
+            Box::new(Formula::And(nnf(f1), nnf(f2))),
+            Box::new(Formula::And(neg(f1), neg(f2))),
+        ),
+        Formula::ForAllPoints(var, f) => Formula::ExistsPoints(var.clone(), neg(f)),
+        Formula::ExistsPoints(var, f) => Formula::ForAllPoints(var.clone(), neg(f)),
+        Formula::ForAllOpens(var, f) => Formula::ExistsOpens(var.clone(), neg(f)),
+        Formula::ExistsOpens(var, f) => Formula::ForAllOpens(var.clone(), neg(f)),
+        Formula::ForAllTopens(var, f) => Formula::ExistsTopens(var.clone(), neg(f)),
+        Formula::ExistsTopens(var, f) => Formula::ForAllTopens(var.clone(), neg(f)),
+    }
+}
+
+/// Fold constants and double negations out of a formula
+///
+/// Quantifiers are only folded when the result does not depend on the domain being
+/// nonempty: `AP x. true` is true and `EP x. false` is false, but `EP x. true` is kept.
+pub fn simplify(formula: &Formula) -> Formula {
+    use Formula::{False, True};
+    match formula {
+        True | False | Formula::Atom(_) => formula.clone(),
+        Formula::Not(f) => match simplify(f) {
+            True => False,
+            False => True,
+            Formula::Not(inner) => *inner,
+            f => Formula::Not(Box::new(f)),
+        },
+        Formula::And(f1, f2) => match (simplify(f1), simplify(f2)) {
+            (False, _) | (_, False) => False,
+            (True, f) | (f, True) => f,
+            (f1, f2) => Formula::And(Box::new(f1), Box::new(f2)),
+        },
+        Formula::Or(f1, f2) => match (simplify(f1), simplify(f2)) {
+            (True, _) | (_, True) => True,
+            (False, f) | (f, False) => f,
+            (f1, f2) => Formula::Or(Box::new(f1), Box::new(f2)),
+        },
+        Formula::Implies(f1, f2) => match (simplify(f1), simplify(f2)) {
+            (False, _) | (_, True) => True,
+            (True, f) => f,
+            (f, False) => simplify(&Formula::Not(Box::new(f))),
+            (f1, f2) => Formula::Implies(Box::new(f1), Box::new(f2)),
+        },
+        Formula::Iff(f1, f2) => match (simplify(f1), simplify(f2)) {
+            (True, f) | (f, True) => f,
+            (False, f) | (f, False) => simplify(&Formula::Not(Box::new(f))),
+            (f1, f2) => Formula::Iff(Box::new(f1), Box::new(f2)),
+        },
+        Formula::Xor(f1, f2) => match (simplify(f1), simplify(f2)) {
+            (False, f) | (f, False) => f,
+            (True, f) | (f, True) => simplify(&Formula::Not(Box::new(f))),
+            (f1, f2) => Formula::Xor(Box::new(f1), Box::new(f2)),
+        },
+        Formula::ForAllPoints(var, f) => match simplify(f) {
+            True => True,
+            f => Formula::ForAllPoints(var.clone(), Box::new(f)),
+        },
+        Formula::ExistsPoints(var, f) => match simplify(f) {
+            False => False,
+            f => Formula::ExistsPoints(var.clone(), Box::new(f)),
+        },
+        Formula::ForAllOpens(var, f) => match simplify(f) {
+            True => True,
+            f => Formula::ForAllOpens(var.clone(), Box::new(f)),
+        },
+        Formula::ExistsOpens(var, f) => match simplify(f) {
+            False => False,
+            f => Formula::ExistsOpens(var.clone(), Box::new(f)),
+        },
+        Formula::ForAllTopens(var, f) => match simplify(f) {
+            True => True,
+            f => Formula::ForAllTopens(var.clone(), Box::new(f)),
+        },
+        Formula::ExistsTopens(var, f) => match simplify(f) {
+            False => False,
+            f => Formula::ExistsTopens(var.clone(), Box::new(f)),
+        },
+    }
+}
+
+/// The point and open variables a formula uses outside any quantifier binding them
+///
+/// Numeric point literals are not variables and never appear.
+pub fn free_vars(formula: &Formula) -> (HashSet<String>, HashSet<String>) {
+    fn point(var: &str, bound: &[&str], points: &mut HashSet<String>) {
+        if !var.starts_with(|c: char| c.is_ascii_digit()) && !bound.contains(&var) {
+            points.insert(var.to_string());
+        }
+    }
+    fn open<'f>(open_expr: &'f OpenExpr, points_bound: &[&str], opens_bound: &[&'f str], free: &mut (HashSet<String>, HashSet<String>)) {
+        match open_expr {
+            OpenExpr::Var(var) => {
+                if !opens_bound.contains(&var.as_str()) {
+                    free.1.insert(var.clone());
+                }
+            }
+            OpenExpr::Community(p) | OpenExpr::MinimalNeighbourhood(p) => point(p, points_bound, &mut free.0),
+            OpenExpr::InteriorComplement(inner) | OpenExpr::Closure(inner) | OpenExpr::Boundary(inner) => {
+                open(inner, points_bound, opens_bound, free)
+            }
+            OpenExpr::Union(left, right) | OpenExpr::Inter(left, right) => {
+                open(left, points_bound, opens_bound, free);
+                open(right, points_bound, opens_bound, free);
+            }
+            OpenExpr::Lit(_) | OpenExpr::Univ | OpenExpr::Empty => {}
+        }
+    }
+    fn walk<'f>(formula: &'f Formula, points_bound: &mut Vec<&'f str>, opens_bound: &mut Vec<&'f str>, free: &mut (HashSet<String>, HashSet<String>)) {
+        match formula {
+            Formula::True | Formula::False => {}
+            Formula::Atom(atom) => match atom {
+                Atom::PointInOpen(p, o) => {
+                    point(p, points_bound, &mut free.0);
+                    open(o, points_bound, opens_bound, free);
+                }
+                Atom::OpenNonempty(o) | Atom::IsTopen(o) => open(o, points_bound, opens_bound, free),
+                Atom::OpenIntersection(o1, o2) | Atom::OpenNotEqual(o1, o2) | Atom::OpenEqual(o1, o2)
+                | Atom::Subset(o1, o2) => {
+                    open(o1, points_bound, opens_bound, free);
+                    open(o2, points_bound, opens_bound, free);
+                }
+                Atom::PointNotEqual(p1, p2) | Atom::PointEqual(p1, p2) => {
+                    point(p1, points_bound, &mut free.0);
+                    point(p2, points_bound, &mut free.0);
+                }
+            },
+            Formula::Not(f) => walk(f, points_bound, opens_bound, free),
+            Formula::And(f1, f2) | Formula::Or(f1, f2) | Formula::Implies(f1, f2) | Formula::Iff(f1, f2)
+            | Formula::Xor(f1, f2) => {
+                walk(f1, points_bound, opens_bound, free);
+                walk(f2, points_bound, opens_bound, free);
+            }
+            Formula::ForAllPoints(var, f) | Formula::ExistsPoints(var, f) => {
+                points_bound.push(var);
+                walk(f, points_bound, opens_bound, free);
+                points_bound.pop();
+            }
+            Formula::ForAllOpens(var, f) | Formula::ExistsOpens(var, f)
+            | Formula::ForAllTopens(var, f) | Formula::ExistsTopens(var, f) => {
+                opens_bound.push(var);
+                walk(f, points_bound, opens_bound, free);
+                opens_bound.pop();
+            }
+        }
+    }
+    let mut free = (HashSet::new(), HashSet::new());
+    walk(formula, &mut Vec::new(), &mut Vec::new(), &mut free);
+    free
+}
+
+/// The lines of `text` whose family satisfies the closed `formula`, as written
+///
+/// Each line is a family in brace notation, optionally `n=<k>`-prefixed (as written by
+/// `search`), and blank lines are skipped. n comes from the prefix, then `size`, and is
+/// otherwise inferred per line. A line that does not parse fails with its line number.
+pub fn filter_family_lines(text: &str, size: Option<usize>, formula: &Formula, open_domain: OpenDomain) -> Result<Vec<String>, String> {
+    fn satisfies<S: PointSet>(line: &str, size: Option<usize>, formula: &Formula, open_domain: OpenDomain) -> Result<bool, String> {
+        let mut checker = ModelChecker::<S>::parse_family(line, size)?.with_open_domain(open_domain);
+        Ok(checker.check(formula).satisfied)
+    }
+
+    let mut matching = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let located = |e: String| format!("line {}: {}", i + 1, e);
+        let (_, n) = parse_family_line::<BitSet>(line, size).map_err(located)?;
+        if open_domain == OpenDomain::Powerset && n > MAX_POWERSET_POINTS {
+            return Err(located(format!("powerset opens support n <= {} (got n={})", MAX_POWERSET_POINTS, n)));
+        }
+        // Families too wide for a bitmask are checked with the arbitrary-width BitSet
+        let satisfied = if n > MAX_POINTS {
+            satisfies::<BitSet>(line, size, formula, open_domain)
+        } else {
+            satisfies::<Elem>(line, size, formula, open_domain)
+        }.map_err(located)?;
+        if satisfied {
+            matching.push(line.to_string());
+        }
+    }
+    Ok(matching)
+}
+
+/// Fail with "unbound variable x" if the formula uses a variable that is neither
+/// quantified nor given by the assignment (the alphabetically first, if several)
+pub fn check_bound<S>(formula: &Formula, assignment: &Assignment<S>) -> Result<(), String> {
+    let (points, opens) = free_vars(formula);
+    let unbound = points.into_iter().filter(|var| !assignment.points.contains_key(var))
+        .chain(opens.into_iter().filter(|var| !assignment.opens.contains_key(var)))
+        .min();
+    match unbound {
+        Some(var) => Err(format!("unbound variable {}", var)),
+        None => Ok(()),
+    }
+}
+
+/// Whether a formula mentions a community anywhere
+fn formula_uses_community(formula: &Formula) -> bool {
+    match formula {
+        Formula::True | Formula::False => false,
+        Formula::Atom(atom) => match atom {
+            Atom::PointInOpen(_, o) | Atom::OpenNonempty(o) | Atom::IsTopen(o) => open_expr_uses_community(o),
+            Atom::OpenIntersection(o1, o2) | Atom::OpenNotEqual(o1, o2) | Atom::OpenEqual(o1, o2)
+            | Atom::Subset(o1, o2) => {
+                open_expr_uses_community(o1) || open_expr_uses_community(o2)
+            }
+            Atom::PointNotEqual(_, _) | Atom::PointEqual(_, _) => false,
+        },
+        Formula::Not(f) => formula_uses_community(f),
+        Formula::And(f1, f2) | Formula::Or(f1, f2) | Formula::Implies(f1, f2) | Formula::Iff(f1, f2)
+        | Formula::Xor(f1, f2) => {
+            formula_uses_community(f1) || formula_uses_community(f2)
+        }
+        Formula::ForAllPoints(_, f) | Formula::ExistsPoints(_, f)
+        | Formula::ForAllOpens(_, f) | Formula::ExistsOpens(_, f)
+        | Formula::ForAllTopens(_, f) | Formula::ExistsTopens(_, f) => formula_uses_community(f),
+    }
+}
+
+fn open_expr_uses_community(open_expr: &OpenExpr) -> bool {
+    match open_expr {
+        OpenExpr::Var(_) | OpenExpr::Lit(_) | OpenExpr::MinimalNeighbourhood(_) | OpenExpr::Univ | OpenExpr::Empty => false,
+        OpenExpr::Community(_) => true,
+        OpenExpr::InteriorComplement(inner) | OpenExpr::Closure(inner) | OpenExpr::Boundary(inner) => {
+            open_expr_uses_community(inner)
+        }
+        OpenExpr::Union(left, right) | OpenExpr::Inter(left, right) => {
+            open_expr_uses_community(left) || open_expr_uses_community(right)
+        }
+    }
 }
 
 /// Assignment of variables to concrete values
 #[derive(Debug, Clone)]
-pub struct Assignment {
+pub struct Assignment<S = Elem> {
     /// Maps point variable names to point indices (1-based)
     pub points: HashMap<String, usize>,
     /// Maps open variable names to subset bitmasks
-    pub opens: HashMap<String, u32>,
+    pub opens: HashMap<String, S>,
 }
 
-impl Assignment {
+impl<S: PointSet> Default for Assignment<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: PointSet> Assignment<S> {
     pub fn new() -> Self {
         Self {
             points: HashMap::new(),
@@ -80,7 +524,7 @@ impl Assignment {
         self.points.insert(var, point);
     }
     
-    pub fn assign_open(&mut self, var: String, open: u32) {
+    pub fn assign_open(&mut self, var: String, open: S) {
         self.opens.insert(var, open);
     }
     
@@ -90,32 +534,139 @@ impl Assignment {
         new_assignment
     }
     
-    pub fn clone_with_open(&self, var: String, open: u32) -> Self {
+    pub fn clone_with_open(&self, var: String, open: S) -> Self {
         let mut new_assignment = self.clone();
         new_assignment.assign_open(var, open);
         new_assignment
     }
+    
+    /// Parse bindings like "X={1,2},p=3" for a semitopology of size n
+    ///
+    /// As in formulas, uppercase names bind opens (to any subset, not necessarily a
+    /// family member) and lowercase names bind points.
+    pub fn parse(input: &str, n: usize) -> Result<Self, String> {
+        let mut assignment = Self::new();
+        
+        // Split on commas outside of set braces
+        let mut bindings = Vec::new();
+        let mut depth = 0;
+        let mut start = 0;
+        for (i, ch) in input.char_indices() {
+            match ch {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                ',' if depth == 0 => {
+                    bindings.push(&input[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        bindings.push(&input[start..]);
+        
+        for binding in bindings {
+            let (var, value) = binding.split_once('=')
+                .ok_or_else(|| format!("Expected var=value in assignment: {}", binding.trim()))?;
+            let (var, value) = (var.trim(), value.trim());
+            match var.chars().next() {
+                Some(c) if c.is_ascii_uppercase() => {
+                    assignment.assign_open(var.to_string(), parse_single_set(value, n)?);
+                }
+                Some(c) if c.is_ascii_lowercase() => {
+                    let point: usize = value.parse()
+                        .map_err(|_| format!("Invalid point for {}: {}", var, value))?;
+                    if point == 0 || point > n {
+                        return Err(format!("Point {} is out of range for n={}", point, n));
+                    }
+                    assignment.assign_point(var.to_string(), point);
+                }
+                _ => return Err(format!("Invalid variable name in assignment: {}", binding.trim())),
+            }
+        }
+        Ok(assignment)
+    }
+}
+
+/// The sets that open quantifiers (AO, EO) range over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum OpenDomain {
+    /// The members of the family
+    #[default]
+    Family,
+    /// All 2^n subsets of the points, open or not
+    Powerset,
+}
+
+/// Largest n for which open quantifiers may range over the powerset
+pub const MAX_POWERSET_POINTS: usize = 24;
+
+impl FromStr for OpenDomain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "family" => Ok(OpenDomain::Family),
+            "powerset" => Ok(OpenDomain::Powerset),
+            _ => Err(format!("Unknown open domain '{}' (expected 'family' or 'powerset')", s)),
+        }
+    }
+}
+
+/// How an open value was obtained
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpenOrigin {
+    /// A member of the family, as bound by an open quantifier
+    Family,
+    /// The community K p of the given point
+    Community(usize),
+    /// An interior complement IC O
+    InteriorComplement,
+    /// A closure Cl O
+    Closure,
+    /// A boundary Bd O
+    Boundary,
+    /// Any other subset of the points, not necessarily a family member
+    Subset,
 }
 
 /// Witness for existential quantification
-#[derive(Debug, Clone)]
-pub enum Witness {
+#[derive(Debug, Clone, PartialEq)]
+pub enum Witness<S = Elem> {
     Point(usize),
-    Open(u32),
+    Open(S, OpenOrigin),
 }
 
 /// Result of model checking with witnesses
+///
+/// Witnesses explain a true result and counterexamples a false one; they are
+/// combined as follows:
+/// - `!A` swaps the witnesses and counterexamples of `A`
+/// - a false `A && B` keeps those of the first false conjunct; a true one merges
+///   the witnesses of both
+/// - a true `A || B` keeps those of the first true disjunct; a false one merges
+///   the counterexamples of both
+/// - `A => B` is treated as `!A || B`
+/// - `A <=> B` records neither
+/// - `EP`/`EO` bind a witness when true, `AP`/`AO` a counterexample when false
 #[derive(Debug, Clone)]
-pub struct ModelCheckResult {
+pub struct ModelCheckResult<S = Elem> {
     pub satisfied: bool,
-    pub witnesses: HashMap<String, Witness>,
+    pub witnesses: HashMap<String, Witness<S>>,
+    /// Bindings of universally quantified variables that falsify the formula
+    pub counterexamples: HashMap<String, Witness<S>>,
+    /// Diagnostics about the model that may make the result misleading
+    pub warnings: Vec<String>,
 }
 
-impl ModelCheckResult {
+impl<S> ModelCheckResult<S> {
     pub fn true_result() -> Self {
         Self {
             satisfied: true,
             witnesses: HashMap::new(),
+            counterexamples: HashMap::new(),
+            warnings: Vec::new(),
         }
     }
     
@@ -123,41 +674,223 @@ impl ModelCheckResult {
         Self {
             satisfied: false,
             witnesses: HashMap::new(),
+            counterexamples: HashMap::new(),
+            warnings: Vec::new(),
         }
     }
     
-    pub fn with_witness(mut self, var: String, witness: Witness) -> Self {
+    pub fn with_witness(mut self, var: String, witness: Witness<S>) -> Self {
         self.witnesses.insert(var, witness);
         self
     }
+    
+    pub fn with_counterexample(mut self, var: String, counterexample: Witness<S>) -> Self {
+        self.counterexamples.insert(var, counterexample);
+        self
+    }
+    
+    /// The result of negating this one
+    fn negated(self) -> Self {
+        Self {
+            satisfied: !self.satisfied,
+            witnesses: self.counterexamples,
+            counterexamples: self.witnesses,
+            warnings: self.warnings,
+        }
+    }
+}
+
+/// The zero-argument space predicates, which hold or fail of a family as a whole
+pub const SPACE_PREDICATES: &[&str] = &[
+    "conflicted_space",
+    "unconflicted_space",
+    "regular_space",
+    "irregular_space",
+    "weakly_regular_space",
+    "quasiregular_space",
+    "indirectly_regular_space",
+    "hypertransitive_space",
+];
+
+/// The one-point predicates, each applied to a point variable as in `regular p`
+pub const POINT_PREDICATES: &[&str] = &[
+    "conflicted",
+    "unconflicted",
+    "regular",
+    "irregular",
+    "weakly_regular",
+    "quasiregular",
+    "indirectly_regular",
+    "hypertransitive",
+];
+
+/// Which predicates a family satisfies, as computed by `ModelChecker::describe`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Description<S = Elem> {
+    /// Each of `SPACE_PREDICATES` with whether it holds
+    pub space: Vec<(&'static str, bool)>,
+    /// Each of `POINT_PREDICATES` with the points (1-based) at which it holds
+    pub points: Vec<(&'static str, Vec<usize>)>,
+    /// The topens of the family
+    pub topens: Vec<S>,
+}
+
+/// Counts of the work done while evaluating formulas
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelCheckStats {
+    /// Atomic propositions evaluated
+    pub atom_evaluations: usize,
+    /// Bindings tried by quantifiers (one per point or open iterated over)
+    pub quantifier_expansions: usize,
+    /// Communities read from the cache
+    pub community_cache_hits: usize,
+    /// Communities computed and added to the cache
+    pub community_cache_misses: usize,
+    /// Interior complements read from the cache
+    pub ic_cache_hits: usize,
+    /// Interior complements computed and added to the cache
+    pub ic_cache_misses: usize,
 }
 
 /// Model checker for propositions against semitopologies
-pub struct ModelChecker {
+///
+/// Opens are `Elem` bitmasks by default; other `PointSet`s such as `BitSet` lift the
+/// limit on n. Clones share the antipode table once it has been built.
+#[derive(Clone)]
+pub struct ModelChecker<S = Elem> {
     n: usize,
-    family: Family,
-    antipode_cache: Option<HashMap<u32, u32>>,
+    family: BTreeSet<S>,
+    antipode_cache: Option<Arc<HashMap<S, S>>>,
+    /// Communities computed so far, keyed by point; valid as long as the family is fixed
+    community_cache: HashMap<usize, S>,
+    /// Interior complements computed so far, keyed by the inner open
+    ic_cache: HashMap<S, S>,
+    /// What open quantifiers range over
+    open_domain: OpenDomain,
+    /// Topens of the family, computed on first use
+    topen_cache: Option<Vec<S>>,
+    /// Work done by evaluations since the checker was built or last reset
+    stats: ModelCheckStats,
+    /// When set, quantifier loops stop once this instant has passed
+    deadline: Option<Instant>,
+    /// Whether the current evaluation was cut short by the deadline
+    timed_out: bool,
 }
 
 impl ModelChecker {
     pub fn new(n: usize, family: Family) -> Self {
+        Self::from_family(n, family)
+    }
+
+    /// Build a checker from a family string like "{{1, 2}, {1, 3}}"
+    ///
+    /// Accepts the same `n=<k>` prefix as `parse_family_line`; otherwise the size is
+    /// `n` if given, or inferred from the largest element.
+    pub fn from_str(family_str: &str, n: Option<usize>) -> Result<Self, String> {
+        Self::parse_family(family_str, n)
+    }
+}
+
+impl<S: PointSet> ModelChecker<S> {
+    /// As `new`, for any representation of the opens
+    pub fn from_family(n: usize, family: BTreeSet<S>) -> Self {
         Self { 
             n, 
             family,
             antipode_cache: None,
+            community_cache: HashMap::new(),
+            ic_cache: HashMap::new(),
+            open_domain: OpenDomain::Family,
+            topen_cache: None,
+            stats: ModelCheckStats::default(),
+            deadline: None,
+            timed_out: false,
+        }
+    }
+
+    /// As `from_str`, for any representation of the opens
+    pub fn parse_family(family_str: &str, n: Option<usize>) -> Result<Self, String> {
+        let (family, n) = parse_family_line(family_str, n)?;
+        Ok(Self::from_family(n, family))
+    }
+
+    /// Let open quantifiers range over `domain` instead of the family
+    ///
+    /// The powerset is enumerated eagerly, so callers keep n <= `MAX_POWERSET_POINTS`.
+    pub fn with_open_domain(mut self, domain: OpenDomain) -> Self {
+        self.open_domain = domain;
+        self
+    }
+
+    /// Size n of the underlying semitopology
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    /// The family of opens being checked against
+    pub fn family(&self) -> &BTreeSet<S> {
+        &self.family
+    }
+
+    /// Number of quasi-components: classes of points that no clopen partition separates
+    ///
+    /// A clopen is a member whose complement (relative to {1..n}) is also a member, with
+    /// the empty set always counted as open.
+    pub fn num_components(&self) -> usize {
+        if self.n == 0 {
+            return 0;
         }
+        let full_set = S::full(self.n);
+        let clopens: Vec<&S> = self.family.iter()
+            .filter(|&o| !o.is_empty() && *o != full_set && self.family.contains(&full_set.difference(o)))
+            .collect();
+
+        // Union-find over points 0..n, merging every pair no clopen separates
+        let mut parent: Vec<usize> = (0..self.n).collect();
+        fn find(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for p in 0..self.n {
+            for q in (p + 1)..self.n {
+                let separated = clopens.iter().any(|c| c.contains(p) != c.contains(q));
+                if !separated {
+                    let (rp, rq) = (find(&mut parent, p), find(&mut parent, q));
+                    parent[rp] = rq;
+                }
+            }
+        }
+        (0..self.n).filter(|&i| find(&mut parent, i) == i).count()
+    }
+    
+    /// Join-irreducible opens: nonempty opens that are not the union of the opens strictly
+    /// contained in them (and so not a union of any proper subfamily of smaller opens)
+    pub fn join_irreducibles(&self) -> Vec<S> {
+        self.family.iter()
+            .filter(|&o| {
+                let mut below = S::empty();
+                for q in self.family.iter().filter(|&q| q != o && q.is_subset(o)) {
+                    below.union_with(q);
+                }
+                !o.is_empty() && below != *o
+            })
+            .cloned()
+            .collect()
     }
     
     /// Build the antipode table: anti[O] = ⋃{P ∈ τ | P ∩ O = ∅}
-    fn build_antipodes(&self) -> HashMap<u32, u32> {
-        let mut anti: HashMap<u32, u32> = HashMap::new();
-        for &o in &self.family {
-            anti.insert(o, 0);                   // allocate entry
+    fn build_antipodes(&self) -> HashMap<S, S> {
+        let mut anti: HashMap<S, S> = HashMap::new();
+        for o in &self.family {
+            anti.insert(o.clone(), S::empty());  // allocate entry
         }
-        for &o in &self.family {
-            for &q in &self.family {
-                if o & q == 0 {                  // disjoint
-                    *anti.get_mut(&o).unwrap() |= q;
+        for o in &self.family {
+            for q in &self.family {
+                if !o.intersects(q) {            // disjoint
+                    anti.get_mut(o).unwrap().union_with(q);
                 }
             }
         }
@@ -165,109 +898,260 @@ impl ModelChecker {
     }
     
     /// Calculate interior complement of open O: largest open disjoint from O
-    fn interior_complement(&self, o: u32) -> u32 {
-        let mut complement = 0u32;
-        for &q in &self.family {
-            if o & q == 0 {  // q is disjoint from o
-                complement |= q;
+    fn interior_complement(&self, o: &S) -> S {
+        let mut complement = S::empty();
+        for q in &self.family {
+            if !o.intersects(q) {  // q is disjoint from o
+                complement.union_with(q);
             }
         }
         complement
     }
     
+    /// The values an open quantifier ranges over, in increasing order
+    fn quantified_opens(&self) -> Vec<S> {
+        match self.open_domain {
+            OpenDomain::Family => self.family.iter().cloned().collect(),
+            OpenDomain::Powerset => (0..1u64 << self.n)
+                .map(|mask| {
+                    let mut open = S::empty();
+                    for i in (0..self.n).filter(|&i| (mask >> i) & 1 == 1) {
+                        open.insert(i);
+                    }
+                    open
+                })
+                .collect(),
+        }
+    }
+
+    /// The topens of the family, in increasing order: nonempty opens T such that any
+    /// two opens meeting T meet each other
+    ///
+    /// An open O meeting T has an open P meeting T but not O exactly when the antipode
+    /// of O (the union of the opens disjoint from O) meets T, so each T is checked in
+    /// one pass over the family. The list is computed once per checker.
+    pub fn topens(&mut self) -> &Vec<S> {
+        if self.topen_cache.is_none() {
+            self.ensure_antipodes();
+            let anti = self.antipodes();
+            let topens = self.family.iter()
+                .filter(|&t| !t.is_empty() && self.family.iter().all(|o| !o.intersects(t) || !anti[o].intersects(t)))
+                .cloned()
+                .collect();
+            self.topen_cache = Some(topens);
+        }
+        self.topen_cache.as_ref().unwrap()
+    }
+
+    /// Origin of a value bound by an open quantifier
+    fn quantified_origin(&self, open: &S) -> OpenOrigin {
+        if self.family.contains(open) { OpenOrigin::Family } else { OpenOrigin::Subset }
+    }
+    
+    /// Interior of a set of points: the largest open contained in it
+    fn interior(&self, o: &S) -> S {
+        let mut interior = S::empty();
+        for q in self.family.iter().filter(|q| q.is_subset(o)) {
+            interior.union_with(q);
+        }
+        interior
+    }
+    
+    /// Interior complement of O, read from or added to `ic_cache`
+    fn cached_interior_complement(&mut self, o: S) -> S {
+        if let Some(complement) = self.ic_cache.get(&o) {
+            self.stats.ic_cache_hits += 1;
+            return complement.clone();
+        }
+        self.stats.ic_cache_misses += 1;
+        let complement = self.interior_complement(&o);
+        self.ic_cache.insert(o, complement.clone());
+        complement
+    }
+    
     /// Calculate community of point p using cached antipode table
     fn community_with_cache(
         &self,
         p: usize,
-        anti: &HashMap<u32, u32>,
-    ) -> u32 {
+        anti: &HashMap<S, S>,
+    ) -> S {
         if p == 0 || p > self.n || self.family.is_empty() {
-            return 0;
+            return S::empty();
         }
 
-        let universe: u32 = if self.n == 32 { u32::MAX } else { (1u32 << self.n) - 1 };
-        let p_bit: u32     = 1u32 << (p - 1);
+        let universe = S::full(self.n);
 
         // 1) gather everything separable from p via the pre-computed table
-        let mut separable: u32 = 0;
-        for &o in &self.family {
-            if o & p_bit != 0 {
-                separable |= anti[&o];           // O ∋ p   ⇒   throw away anti(O)
+        let mut separable = S::empty();
+        for o in &self.family {
+            if o.contains(p - 1) {
+                separable.union_with(&anti[o]);  // O ∋ p   ⇒   throw away anti(O)
             }
         }
 
         // 2) inseparable class
-        let class: u32 = universe & !separable;
+        let class = universe.difference(&separable);
 
         // 3) interior
-        let mut community: u32 = 0;
-        for &o in &self.family {
-            if o & !class == 0 {
-                community |= o;
+        let mut community = S::empty();
+        for o in &self.family {
+            if o.is_subset(&class) {
+                community.union_with(o);
             }
         }
         community
     }
     
-    /// Ensure antipode cache is built and return reference to it
-    fn get_antipode_cache(&mut self) -> &HashMap<u32, u32> {
+    /// Build the antipode table if it has not been built yet
+    fn ensure_antipodes(&mut self) {
         if self.antipode_cache.is_none() {
-            self.antipode_cache = Some(self.build_antipodes());
+            self.antipode_cache = Some(Arc::new(self.build_antipodes()));
         }
-        self.antipode_cache.as_ref().unwrap()
+    }
+
+    /// Borrow the antipode table built by `ensure_antipodes`
+    fn antipodes(&self) -> &HashMap<S, S> {
+        self.antipode_cache.as_deref().expect("antipode table is built before use")
     }
     
     /// Check if a point is in an open (subset)
-    fn point_in_open(&self, point: usize, open: u32) -> bool {
+    fn point_in_open(&self, point: usize, open: &S) -> bool {
         if point == 0 || point > self.n {
             false
         } else {
             let bit_pos = point - 1;
-            (open >> bit_pos) & 1 == 1
+            open.contains(bit_pos)
         }
     }
     
     /// Check if two opens (subsets) intersect
-    fn opens_intersect(&self, open1: u32, open2: u32) -> bool {
-        (open1 & open2) != 0
+    fn opens_intersect(&self, open1: &S, open2: &S) -> bool {
+        open1.intersects(open2)
     }
     
     /// Check if an open (subset) is nonempty
-    fn open_is_nonempty(&self, open: u32) -> bool {
-        open != 0
+    fn open_is_nonempty(&self, open: &S) -> bool {
+        !open.is_empty()
     }
     
+    /// Check if a set is a topen: nonempty, and any two family opens meeting it meet
+    /// each other (equivalently, no family open meeting it has an antipode meeting it)
+    fn is_topen(&mut self, open: &S) -> bool {
+        if open.is_empty() {
+            return false;
+        }
+        self.ensure_antipodes();
+        let anti = self.antipodes();
+        self.family.iter().all(|o| !o.intersects(open) || !anti[o].intersects(open))
+    }
+    
+    /// The point a name denotes: its binding, or for a numeric literal the point itself
+    ///
+    /// Literals outside 1..=n denote nothing, so atoms mentioning them are false, just
+    /// like atoms over unbound variables.
+    fn eval_point(&self, name: &str, assignment: &Assignment<S>) -> Option<usize> {
+        match assignment.points.get(name) {
+            Some(&point) => Some(point),
+            None => name.parse().ok().filter(|point| (1..=self.n).contains(point)),
+        }
+    }
+
     /// Evaluate an open expression to a concrete open set
-    fn eval_open_expr(&mut self, open_expr: &OpenExpr, assignment: &Assignment) -> Option<u32> {
+    fn eval_open_expr(&mut self, open_expr: &OpenExpr, assignment: &Assignment<S>) -> Option<S> {
         match open_expr {
             OpenExpr::Var(var) => {
-                assignment.opens.get(var).copied()
+                assignment.opens.get(var).cloned()
             }
             OpenExpr::Community(point_var) => {
-                if let Some(&point) = assignment.points.get(point_var) {
-                    let anti = self.get_antipode_cache().clone();
-                    Some(self.community_with_cache(point, &anti))
+                if let Some(point) = self.eval_point(point_var, assignment) {
+                    if let Some(community) = self.community_cache.get(&point) {
+                        self.stats.community_cache_hits += 1;
+                        return Some(community.clone());
+                    }
+                    self.stats.community_cache_misses += 1;
+                    self.ensure_antipodes();
+                    let community = self.community_with_cache(point, self.antipodes());
+                    self.community_cache.insert(point, community.clone());
+                    Some(community)
                 } else {
                     None
                 }
             }
+            OpenExpr::MinimalNeighbourhood(point_var) => {
+                let point = self.eval_point(point_var, assignment)?;
+                let mut containing = self.family.iter().filter(|open| open.contains(point - 1));
+                match containing.next() {
+                    Some(first) => Some(containing.fold(first.clone(), |acc, open| acc.intersection(open))),
+                    None => Some(S::empty()),
+                }
+            }
             OpenExpr::InteriorComplement(inner_expr) => {
-                if let Some(inner_open) = self.eval_open_expr(inner_expr, assignment) {
-                    Some(self.interior_complement(inner_open))
-                } else {
-                    None
+                let inner_open = self.eval_open_expr(inner_expr, assignment)?;
+                Some(self.cached_interior_complement(inner_open))
+            }
+            OpenExpr::Closure(inner_expr) => {
+                // The complement of the interior of the complement, i.e. of IC O
+                let inner_open = self.eval_open_expr(inner_expr, assignment)?;
+                Some(S::full(self.n).difference(&self.cached_interior_complement(inner_open)))
+            }
+            OpenExpr::Boundary(inner_expr) => {
+                let inner_open = self.eval_open_expr(inner_expr, assignment)?;
+                let interior = self.interior(&inner_open);
+                let closure = S::full(self.n).difference(&self.cached_interior_complement(inner_open));
+                Some(closure.difference(&interior))
+            }
+            OpenExpr::Union(left_expr, right_expr) => {
+                // Only a family member if both sides are, since the family is union-closed
+                let left = self.eval_open_expr(left_expr, assignment)?;
+                let right = self.eval_open_expr(right_expr, assignment)?;
+                Some(left.union(&right))
+            }
+            OpenExpr::Inter(left_expr, right_expr) => {
+                let left = self.eval_open_expr(left_expr, assignment)?;
+                let right = self.eval_open_expr(right_expr, assignment)?;
+                Some(left.intersection(&right))
+            }
+            OpenExpr::Lit(points) => {
+                // Like IC, the raw set of points, whether or not it is in the family;
+                // a point outside 1..=n denotes nothing, as for point literals
+                let mut open = S::empty();
+                for &point in points {
+                    if point == 0 || point > self.n {
+                        return None;
+                    }
+                    open.insert(point - 1);
                 }
+                Some(open)
             }
+            OpenExpr::Univ => Some(S::full(self.n)),
+            OpenExpr::Empty => Some(S::empty()),
         }
     }
 
+    /// Evaluate an open expression as a witness recording how its value was obtained
+    pub fn open_witness(&mut self, open_expr: &OpenExpr, assignment: &Assignment<S>) -> Option<Witness<S>> {
+        let open = self.eval_open_expr(open_expr, assignment)?;
+        let origin = match open_expr {
+            OpenExpr::Var(_) if self.family.contains(&open) => OpenOrigin::Family,
+            OpenExpr::Var(_) => OpenOrigin::Subset,
+            OpenExpr::Community(point_var) => OpenOrigin::Community(self.eval_point(point_var, assignment)?),
+            OpenExpr::InteriorComplement(_) => OpenOrigin::InteriorComplement,
+            OpenExpr::Closure(_) => OpenOrigin::Closure,
+            OpenExpr::Boundary(_) => OpenOrigin::Boundary,
+            OpenExpr::Union(..) | OpenExpr::Inter(..) | OpenExpr::Lit(_) => OpenOrigin::Subset,
+            OpenExpr::MinimalNeighbourhood(_) | OpenExpr::Univ | OpenExpr::Empty => OpenOrigin::Subset,
+        };
+        Some(Witness::Open(open, origin))
+    }
+
     /// Evaluate an atomic proposition under an assignment
-    fn eval_atom(&mut self, atom: &Atom, assignment: &Assignment) -> bool {
+    fn eval_atom(&mut self, atom: &Atom, assignment: &Assignment<S>) -> bool {
+        self.stats.atom_evaluations += 1;
         match atom {
             Atom::PointInOpen(point_var, open_expr) => {
-                if let Some(&point) = assignment.points.get(point_var) {
+                if let Some(point) = self.eval_point(point_var, assignment) {
                     if let Some(open) = self.eval_open_expr(open_expr, assignment) {
-                        self.point_in_open(point, open)
+                        self.point_in_open(point, &open)
                     } else {
                         false
                     }
@@ -280,22 +1164,22 @@ impl ModelChecker {
                     self.eval_open_expr(open_expr1, assignment),
                     self.eval_open_expr(open_expr2, assignment)
                 ) {
-                    self.opens_intersect(open1, open2)
+                    self.opens_intersect(&open1, &open2)
                 } else {
                     false
                 }
             }
             Atom::OpenNonempty(open_expr) => {
                 if let Some(open) = self.eval_open_expr(open_expr, assignment) {
-                    self.open_is_nonempty(open)
+                    self.open_is_nonempty(&open)
                 } else {
                     false
                 }
             }
             Atom::PointNotEqual(point_var1, point_var2) => {
-                if let (Some(&point1), Some(&point2)) = (
-                    assignment.points.get(point_var1),
-                    assignment.points.get(point_var2)
+                if let (Some(point1), Some(point2)) = (
+                    self.eval_point(point_var1, assignment),
+                    self.eval_point(point_var2, assignment)
                 ) {
                     point1 != point2
                 } else {
@@ -313,9 +1197,9 @@ impl ModelChecker {
                 }
             }
             Atom::PointEqual(point_var1, point_var2) => {
-                if let (Some(&point1), Some(&point2)) = (
-                    assignment.points.get(point_var1),
-                    assignment.points.get(point_var2)
+                if let (Some(point1), Some(point2)) = (
+                    self.eval_point(point_var1, assignment),
+                    self.eval_point(point_var2, assignment)
                 ) {
                     point1 == point2
                 } else {
@@ -332,12 +1216,32 @@ impl ModelChecker {
                     false
                 }
             }
+            Atom::Subset(open_expr1, open_expr2) => {
+                if let (Some(open1), Some(open2)) = (
+                    self.eval_open_expr(open_expr1, assignment),
+                    self.eval_open_expr(open_expr2, assignment)
+                ) {
+                    open1.is_subset(&open2)
+                } else {
+                    false
+                }
+            }
+            Atom::IsTopen(open_expr) => {
+                if let Some(open) = self.eval_open_expr(open_expr, assignment) {
+                    self.is_topen(&open)
+                } else {
+                    false
+                }
+            }
         }
     }
     
     /// Evaluate a formula under an assignment, returning witnesses for existential quantifiers
-    pub fn eval_formula(&mut self, formula: &Formula, assignment: &Assignment) -> ModelCheckResult {
+    /// and counterexamples for universal ones
+    pub fn eval_formula(&mut self, formula: &Formula, assignment: &Assignment<S>) -> ModelCheckResult<S> {
         match formula {
+            Formula::True => ModelCheckResult::true_result(),
+            Formula::False => ModelCheckResult::false_result(),
             Formula::Atom(atom) => {
                 if self.eval_atom(atom, assignment) {
                     ModelCheckResult::true_result()
@@ -345,13 +1249,7 @@ impl ModelChecker {
                     ModelCheckResult::false_result()
                 }
             }
-            Formula::Not(f) => {
-                let result = self.eval_formula(f, assignment);
-                ModelCheckResult {
-                    satisfied: !result.satisfied,
-                    witnesses: result.witnesses,
-                }
-            }
+            Formula::Not(f) => self.eval_formula(f, assignment).negated(),
             Formula::And(f1, f2) => {
                 let result1 = self.eval_formula(f1, assignment);
                 if !result1.satisfied {
@@ -362,12 +1260,9 @@ impl ModelChecker {
                     return result2;
                 }
                 // Combine witnesses from both subformulas
-                let mut combined_witnesses = result1.witnesses;
-                combined_witnesses.extend(result2.witnesses);
-                ModelCheckResult {
-                    satisfied: true,
-                    witnesses: combined_witnesses,
-                }
+                let mut combined = result1;
+                combined.witnesses.extend(result2.witnesses);
+                combined
             }
             Formula::Or(f1, f2) => {
                 let result1 = self.eval_formula(f1, assignment);
@@ -378,14 +1273,24 @@ impl ModelChecker {
                 if result2.satisfied {
                     return result2;
                 }
-                ModelCheckResult::false_result()
+                // Both disjuncts failed, so both reasons are reported
+                let mut combined = result1;
+                combined.counterexamples.extend(result2.counterexamples);
+                combined
             }
             Formula::Implies(f1, f2) => {
                 let result1 = self.eval_formula(f1, assignment);
                 if !result1.satisfied {
-                    return ModelCheckResult::true_result();
+                    return result1.negated();
                 }
-                self.eval_formula(f2, assignment)
+                let result2 = self.eval_formula(f2, assignment);
+                if result2.satisfied {
+                    return result2;
+                }
+                // The premise held and the conclusion failed
+                let mut combined = result1.negated();
+                combined.counterexamples.extend(result2.counterexamples);
+                combined
             }
             Formula::Iff(f1, f2) => {
                 let result1 = self.eval_formula(f1, assignment);
@@ -397,18 +1302,36 @@ impl ModelChecker {
                     ModelCheckResult::false_result()
                 }
             }
+            Formula::Xor(f1, f2) => {
+                let result1 = self.eval_formula(f1, assignment);
+                let result2 = self.eval_formula(f2, assignment);
+                // A ^^ B is true iff exactly one of A and B holds
+                if result1.satisfied != result2.satisfied {
+                    ModelCheckResult::true_result()
+                } else {
+                    ModelCheckResult::false_result()
+                }
+            }
             Formula::ForAllPoints(var, f) => {
                 for point in 1..=self.n {
+                    if self.past_deadline() {
+                        return ModelCheckResult::false_result();
+                    }
+                    self.stats.quantifier_expansions += 1;
                     let new_assignment = assignment.clone_with_point(var.clone(), point);
                     let result = self.eval_formula(f, &new_assignment);
                     if !result.satisfied {
-                        return ModelCheckResult::false_result();
+                        return result.with_counterexample(var.clone(), Witness::Point(point));
                     }
                 }
                 ModelCheckResult::true_result()
             }
             Formula::ExistsPoints(var, f) => {
                 for point in 1..=self.n {
+                    if self.past_deadline() {
+                        return ModelCheckResult::false_result();
+                    }
+                    self.stats.quantifier_expansions += 1;
                     let new_assignment = assignment.clone_with_point(var.clone(), point);
                     let result = self.eval_formula(f, &new_assignment);
                     if result.satisfied {
@@ -418,23 +1341,59 @@ impl ModelChecker {
                 ModelCheckResult::false_result()
             }
             Formula::ForAllOpens(var, f) => {
-                let family_vec: Vec<u32> = self.family.iter().cloned().collect();
-                for open in family_vec {
-                    let new_assignment = assignment.clone_with_open(var.clone(), open);
+                for open in self.quantified_opens() {
+                    if self.past_deadline() {
+                        return ModelCheckResult::false_result();
+                    }
+                    self.stats.quantifier_expansions += 1;
+                    let new_assignment = assignment.clone_with_open(var.clone(), open.clone());
                     let result = self.eval_formula(f, &new_assignment);
                     if !result.satisfied {
-                        return ModelCheckResult::false_result();
+                        let origin = self.quantified_origin(&open);
+                        return result.with_counterexample(var.clone(), Witness::Open(open, origin));
                     }
                 }
                 ModelCheckResult::true_result()
             }
             Formula::ExistsOpens(var, f) => {
-                let family_vec: Vec<u32> = self.family.iter().cloned().collect();
-                for open in family_vec {
-                    let new_assignment = assignment.clone_with_open(var.clone(), open);
+                for open in self.quantified_opens() {
+                    if self.past_deadline() {
+                        return ModelCheckResult::false_result();
+                    }
+                    self.stats.quantifier_expansions += 1;
+                    let new_assignment = assignment.clone_with_open(var.clone(), open.clone());
+                    let result = self.eval_formula(f, &new_assignment);
+                    if result.satisfied {
+                        let origin = self.quantified_origin(&open);
+                        return result.with_witness(var.clone(), Witness::Open(open, origin));
+                    }
+                }
+                ModelCheckResult::false_result()
+            }
+            Formula::ForAllTopens(var, f) => {
+                for topen in self.topens().clone() {
+                    if self.past_deadline() {
+                        return ModelCheckResult::false_result();
+                    }
+                    self.stats.quantifier_expansions += 1;
+                    let new_assignment = assignment.clone_with_open(var.clone(), topen.clone());
+                    let result = self.eval_formula(f, &new_assignment);
+                    if !result.satisfied {
+                        return result.with_counterexample(var.clone(), Witness::Open(topen, OpenOrigin::Family));
+                    }
+                }
+                ModelCheckResult::true_result()
+            }
+            Formula::ExistsTopens(var, f) => {
+                for topen in self.topens().clone() {
+                    if self.past_deadline() {
+                        return ModelCheckResult::false_result();
+                    }
+                    self.stats.quantifier_expansions += 1;
+                    let new_assignment = assignment.clone_with_open(var.clone(), topen.clone());
                     let result = self.eval_formula(f, &new_assignment);
                     if result.satisfied {
-                        return result.with_witness(var.clone(), Witness::Open(open));
+                        return result.with_witness(var.clone(), Witness::Open(topen, OpenOrigin::Family));
                     }
                 }
                 ModelCheckResult::false_result()
@@ -442,26 +1401,225 @@ impl ModelChecker {
         }
     }
     
+    /// Whether the deadline, if any, has passed; once it has, every enclosing quantifier
+    /// loop unwinds without visiting further bindings
+    fn past_deadline(&mut self) -> bool {
+        if let Some(deadline) = self.deadline {
+            if !self.timed_out && Instant::now() >= deadline {
+                self.timed_out = true;
+            }
+        }
+        self.timed_out
+    }
+    
     /// Check if a formula is satisfied by the semitopology
-    pub fn check(&mut self, formula: &Formula) -> ModelCheckResult {
-        let assignment = Assignment::new();
-        self.eval_formula(formula, &assignment)
+    pub fn check(&mut self, formula: &Formula) -> ModelCheckResult<S> {
+        self.check_with(formula, &Assignment::new())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::BTreeSet;
-
-    #[test]
-    fn test_build_antipodes_basic() {
-        // Example from documentation comment
-        let mut family = BTreeSet::new();
-        family.insert(0b00); // {}
-        family.insert(0b01); // {1}
-        family.insert(0b10); // {2}
-        family.insert(0b11); // {1,2}
+    
+    /// Check a formula, evaluating the branches of a top-level quantifier in parallel
+    ///
+    /// The antipode table is built up front so the per-thread checkers only read it.
+    /// Results, including witnesses and counterexamples, are the same as for `check`.
+    pub fn check_parallel(&mut self, formula: &Formula, assignment: &Assignment<S>) -> ModelCheckResult<S> {
+        self.ensure_antipodes();
+        let mut result = self.eval_quantifier_parallel(formula, assignment);
+        result.warnings = self.validate(formula);
+        result
+    }
+    
+    /// Split the domain of a top-level quantifier across threads; other formulas are
+    /// evaluated sequentially
+    fn eval_quantifier_parallel(&self, formula: &Formula, assignment: &Assignment<S>) -> ModelCheckResult<S> {
+        let points: Vec<usize> = (1..=self.n).collect();
+        let opens = self.quantified_opens();
+        match formula {
+            Formula::ForAllPoints(var, f) => points.par_iter()
+                .map_init(|| self.clone(), |checker, &point| {
+                    (point, checker.eval_formula(f, &assignment.clone_with_point(var.clone(), point)))
+                })
+                .find_map_first(|(point, result)| {
+                    (!result.satisfied).then(|| result.with_counterexample(var.clone(), Witness::Point(point)))
+                })
+                .unwrap_or_else(ModelCheckResult::true_result),
+            Formula::ExistsPoints(var, f) => points.par_iter()
+                .map_init(|| self.clone(), |checker, &point| {
+                    (point, checker.eval_formula(f, &assignment.clone_with_point(var.clone(), point)))
+                })
+                .find_map_first(|(point, result)| {
+                    result.satisfied.then(|| result.with_witness(var.clone(), Witness::Point(point)))
+                })
+                .unwrap_or_else(ModelCheckResult::false_result),
+            Formula::ForAllOpens(var, f) => opens.par_iter()
+                .map_init(|| self.clone(), |checker, open| {
+                    (open, checker.eval_formula(f, &assignment.clone_with_open(var.clone(), open.clone())))
+                })
+                .find_map_first(|(open, result)| {
+                    (!result.satisfied).then(|| result.with_counterexample(var.clone(), Witness::Open(open.clone(), self.quantified_origin(open))))
+                })
+                .unwrap_or_else(ModelCheckResult::true_result),
+            Formula::ExistsOpens(var, f) => opens.par_iter()
+                .map_init(|| self.clone(), |checker, open| {
+                    (open, checker.eval_formula(f, &assignment.clone_with_open(var.clone(), open.clone())))
+                })
+                .find_map_first(|(open, result)| {
+                    result.satisfied.then(|| result.with_witness(var.clone(), Witness::Open(open.clone(), self.quantified_origin(open))))
+                })
+                .unwrap_or_else(ModelCheckResult::false_result),
+            _ => self.clone().eval_formula(formula, assignment),
+        }
+    }
+    
+    /// Check a formula whose free variables are (partially) bound by `assignment`
+    pub fn check_with(&mut self, formula: &Formula, assignment: &Assignment<S>) -> ModelCheckResult<S> {
+        let mut result = self.eval_formula(formula, assignment);
+        result.warnings = self.validate(formula);
+        result
+    }
+    
+    /// Check each formula of `text`, one per line (blank lines and lines starting with
+    /// `#` are skipped), returning every formula with whether it holds
+    ///
+    /// All lines are parsed before any is checked, so a bad line fails the whole suite,
+    /// naming its line number.
+    pub fn check_lines(&mut self, text: &str, assignment: &Assignment<S>) -> Result<Vec<(String, bool)>, String> {
+        let mut formulas = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let formula = parse_formula(line)
+                .and_then(|formula| check_bound(&formula, assignment).map(|_| formula))
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+            formulas.push((line.to_string(), formula));
+        }
+        Ok(formulas.into_iter()
+            .map(|(line, formula)| {
+                let satisfied = self.check_with(&formula, assignment).satisfied;
+                (line, satisfied)
+            })
+            .collect())
+    }
+    
+    /// As `check_with`, giving up with an error once `timeout` has elapsed
+    ///
+    /// The deadline is only consulted at quantifier bindings, so a check without
+    /// quantifiers always completes.
+    pub fn check_with_deadline(&mut self, formula: &Formula, assignment: &Assignment<S>, timeout: Duration) -> Result<ModelCheckResult<S>, String> {
+        self.deadline = Some(Instant::now() + timeout);
+        let result = self.check_with(formula, assignment);
+        self.deadline = None;
+        if std::mem::take(&mut self.timed_out) {
+            Err(format!("timed out after {:?}", timeout))
+        } else {
+            Ok(result)
+        }
+    }
+    
+    /// As `check_with`, also returning the work done by this evaluation
+    ///
+    /// Caches persist across checks, so a repeated check reports cache hits only.
+    pub fn check_with_stats(&mut self, formula: &Formula, assignment: &Assignment<S>) -> (ModelCheckResult<S>, ModelCheckStats) {
+        self.stats = ModelCheckStats::default();
+        let result = self.check_with(formula, assignment);
+        (result, std::mem::take(&mut self.stats))
+    }
+    
+    /// Every binding of the formula's leading existential quantifiers (EP, EO) that
+    /// satisfies the rest of the formula, extending `assignment`
+    ///
+    /// Bindings are enumerated in order, stopping after `max_models` if given. A formula
+    /// without leading existentials has the single model `assignment` if it holds.
+    pub fn all_models(&mut self, formula: &Formula, assignment: &Assignment<S>, max_models: Option<usize>) -> Vec<Assignment<S>> {
+        let mut models = Vec::new();
+        self.collect_models(formula, assignment, max_models.unwrap_or(usize::MAX), &mut models);
+        models
+    }
+
+    fn collect_models(&mut self, formula: &Formula, assignment: &Assignment<S>, max_models: usize, models: &mut Vec<Assignment<S>>) {
+        match formula {
+            Formula::ExistsPoints(var, f) => {
+                for point in 1..=self.n {
+                    if models.len() >= max_models {
+                        return;
+                    }
+                    self.collect_models(f, &assignment.clone_with_point(var.clone(), point), max_models, models);
+                }
+            }
+            Formula::ExistsOpens(var, f) => {
+                for open in self.quantified_opens() {
+                    if models.len() >= max_models {
+                        return;
+                    }
+                    self.collect_models(f, &assignment.clone_with_open(var.clone(), open), max_models, models);
+                }
+            }
+            _ => {
+                if models.len() < max_models && self.eval_formula(formula, assignment).satisfied {
+                    models.push(assignment.clone());
+                }
+            }
+        }
+    }
+    
+    /// Diagnose models on which the formula's communities are degenerate
+    fn validate(&self, formula: &Formula) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if formula_uses_community(formula) {
+            if self.family.is_empty() {
+                warnings.push("formula uses communities (K p) but the family is empty, so every community is empty".to_string());
+            } else if !has_empty(&self.family) {
+                warnings.push("formula uses communities (K p) but the family does not contain the empty set, so it is not a semitopology".to_string());
+            }
+        }
+        warnings
+    }
+
+    /// Parse a formula string and check it against the semitopology
+    pub fn check_str(&mut self, formula_str: &str) -> Result<ModelCheckResult<S>, String> {
+        let formula = parse_formula(formula_str)?;
+        Ok(self.check(&formula))
+    }
+
+    /// Evaluate every space predicate, every point predicate at each point, and list the
+    /// topens
+    pub fn describe(&mut self) -> Description<S> {
+        let space = SPACE_PREDICATES.iter()
+            .map(|&name| {
+                let formula = parse_formula(name).expect("space predicates parse");
+                (name, self.check(&formula).satisfied)
+            })
+            .collect();
+        let points = POINT_PREDICATES.iter()
+            .map(|&name| {
+                let formula = parse_formula(&format!("{} p", name)).expect("point predicates parse");
+                let holding = (1..=self.n).filter(|&p| {
+                    let mut assignment = Assignment::new();
+                    assignment.assign_point("p".to_string(), p);
+                    self.check_with(&formula, &assignment).satisfied
+                }).collect();
+                (name, holding)
+            })
+            .collect();
+        Description { space, points, topens: self.topens().clone() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canon::full_set;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_build_antipodes_basic() {
+        // Example from documentation comment
+        let mut family = BTreeSet::new();
+        family.insert(0b00); // {}
+        family.insert(0b01); // {1}
+        family.insert(0b10); // {2}
+        family.insert(0b11); // {1,2}
         
         let checker = ModelChecker::new(2, family.clone());
         let anti = checker.build_antipodes();
@@ -587,6 +1745,22 @@ mod tests {
         assert_eq!(k3, 0b100); // {3}
     }
 
+    #[test]
+    fn test_minimal_open_neighbourhood() {
+        // The family of test_community_disconnected_case: every point has a singleton open
+        let mut checker = ModelChecker::from_str("{{}, {1}, {2}, {3}, {1, 2}}", None).unwrap();
+        for p in 1..=3 {
+            assert!(checker.check_str(&format!("MON {} = {{{}}}", p, p)).unwrap().satisfied);
+        }
+        let mut checker = ModelChecker::from_str("{{}, {1, 2}, {2, 3}, {1, 2, 3}}", Some(4)).unwrap();
+        assert!(checker.check_str("MON 1 = {1, 2} && MON 3 = {2, 3}").unwrap().satisfied);
+        // {2} is not an open of the family
+        assert!(checker.check_str("MON 2 = {2} && (AO X. X != MON 2)").unwrap().satisfied);
+        // No open contains 4
+        assert!(checker.check_str("MON 4 = {}").unwrap().satisfied);
+        assert!(checker.check_str("AP x. x in MON x <=> x != 4").unwrap().satisfied);
+    }
+
     #[test]
     fn test_community_degenerate_cases() {
         // Test empty family
@@ -687,38 +1861,51 @@ mod tests {
 
     #[test]
     fn test_community_max_size() {
-        // Test with n = 32 (highest encodable in u32) to exercise universe = u32::MAX path
+        // Test with n = 64 (highest encodable in Elem) to exercise universe = Elem::MAX path
         let mut family = BTreeSet::new();
         family.insert(0); // {}
         family.insert(1); // {1}
-        family.insert(u32::MAX); // {1,2,...,32}
+        family.insert(Elem::MAX); // {1,2,...,64}
         
-        let checker = ModelChecker::new(32, family.clone());
+        let checker = ModelChecker::new(64, family.clone());
         let anti = checker.build_antipodes();
         
-        // Verify the universe = u32::MAX path is exercised
+        // Verify the universe = Elem::MAX path is exercised
         let k1 = checker.community_with_cache(1, &anti);
         
-        // In this case, point 1 is in {1} and {1,2,...,32}
+        // In this case, point 1 is in {1} and {1,2,...,64}
         // anti[{1}] includes everything disjoint from {1} = {}
-        // anti[{1,2,...,32}] = {} (nothing is disjoint from the full set)
+        // anti[{1,2,...,64}] = {} (nothing is disjoint from the full set)
         // So separable = {}, class = all points, community = entire family union
-        assert_eq!(k1, u32::MAX); // Should be the full set
+        assert_eq!(k1, Elem::MAX); // Should be the full set
+    }
+
+    #[test]
+    fn test_community_beyond_32_points() {
+        // The indiscrete topology on 40 points: every community is the whole universe
+        let checker = ModelChecker::from_str("n=40 {{}, {1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40}}", None).unwrap();
+        assert_eq!(checker.family(), &[0, full_set(40)].into_iter().collect::<Family>());
+        let anti = checker.build_antipodes();
+        assert_eq!(checker.community_with_cache(1, &anti), full_set(40));
+        assert_eq!(checker.community_with_cache(40, &anti), full_set(40));
+
+        let mut checker = checker;
+        assert!(checker.check_str("AP p. AP q. q in K p").unwrap().satisfied);
     }
 
     #[test] 
     fn test_community_reference_comparison() {
         // Compare against a reference implementation for a small random case
-        fn reference_community(p: usize, n: usize, family: &Family) -> u32 {
+        fn reference_community(p: usize, n: usize, family: &Family) -> Elem {
             if p == 0 || p > n || family.is_empty() {
                 return 0;
             }
             
-            let universe = if n == 32 { u32::MAX } else { (1u32 << n) - 1 };
-            let p_bit = 1u32 << (p - 1);
+            let universe = full_set(n);
+            let p_bit: Elem = 1 << (p - 1);
             
             // Find all sets separable from p (slow O(|τ|²) method)
-            let mut separable = 0u32;
+            let mut separable: Elem = 0;
             for &o in family {
                 if o & p_bit != 0 { // o contains p
                     for &q in family {
@@ -732,7 +1919,7 @@ mod tests {
             let class = universe & !separable;
             
             // Find interior of class
-            let mut community = 0u32;
+            let mut community: Elem = 0;
             for &o in family {
                 if o & !class == 0 { // o ⊆ class
                     community |= o;
@@ -760,4 +1947,960 @@ mod tests {
             assert_eq!(fast_result, ref_result, "Mismatch for point {}", p);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_from_str_infers_size() {
+        let checker = ModelChecker::from_str("{{}, {1, 2}, {1, 3}, {1, 2, 3}}", None).unwrap();
+        assert_eq!(checker.n(), 3);
+        assert_eq!(checker.family().len(), 4);
+
+        // An explicit size overrides inference
+        let checker = ModelChecker::from_str("{{}, {1}}", Some(4)).unwrap();
+        assert_eq!(checker.n(), 4);
+
+        assert!(ModelChecker::from_str("{{}}", None).is_err());
+        assert!(ModelChecker::from_str("{{1, 5}}", Some(3)).is_err());
+    }
+
+    #[test]
+    fn test_check_str() {
+        let mut checker = ModelChecker::from_str("{{}, {1, 2}, {1, 3}, {1, 2, 3}}", None).unwrap();
+        assert!(checker.check_str("EP x. AO X. nonempty X => x in X").unwrap().satisfied);
+        assert!(!checker.check_str("AO X. nonempty X").unwrap().satisfied);
+        assert!(checker.check_str("AP x x in X").is_err());
+    }
+
+    #[test]
+    fn test_community_warning_on_empty_family() {
+        let mut checker = ModelChecker::new(2, BTreeSet::new());
+        let result = checker.check_str("AP p. quasiregular p").unwrap();
+        assert!(!result.satisfied);
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].contains("family is empty"));
+
+        // No communities, no warning
+        let result = checker.check_str("AP p. p = p").unwrap();
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_community_warning_without_empty_set() {
+        let mut checker = ModelChecker::from_str("{{1}, {1, 2}}", None).unwrap();
+        let result = checker.check_str("EP p. quasiregular p").unwrap();
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].contains("empty set"));
+
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}}", None).unwrap();
+        let result = checker.check_str("EP p. quasiregular p").unwrap();
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_parse_assignment() {
+        let assignment: Assignment = Assignment::parse("X={1,2}, p=3,Y={}", 3).unwrap();
+        assert_eq!(assignment.opens["X"], 0b011);
+        assert_eq!(assignment.opens["Y"], 0);
+        assert_eq!(assignment.points["p"], 3);
+
+        assert!(Assignment::<Elem>::parse("p=4", 3).is_err());
+        assert!(Assignment::<Elem>::parse("X={4}", 3).is_err());
+        assert!(Assignment::<Elem>::parse("X", 3).is_err());
+        assert!(Assignment::<Elem>::parse("1={1}", 3).is_err());
+    }
+
+    #[test]
+    fn test_check_with_partial_assignment() {
+        let mut checker = ModelChecker::from_str("{{}, {1, 2}, {1, 3}, {1, 2, 3}}", None).unwrap();
+        let formula = parse_formula("AP x. x in X").unwrap();
+
+        let full = Assignment::parse("X={1,2,3}", 3).unwrap();
+        assert!(checker.check_with(&formula, &full).satisfied);
+        let partial = Assignment::parse("X={1,2}", 3).unwrap();
+        assert!(!checker.check_with(&formula, &partial).satisfied);
+
+        // Point bindings are visible to nested quantifiers
+        let formula = parse_formula("EO X. p in X && !(q in X)").unwrap();
+        assert!(checker.check_with(&formula, &Assignment::parse("p=2,q=3", 3).unwrap()).satisfied);
+        assert!(!checker.check_with(&formula, &Assignment::parse("p=3,q=1", 3).unwrap()).satisfied);
+    }
+
+    #[test]
+    fn test_witness_origin() {
+        let mut checker = ModelChecker::from_str("{{}, {1, 2}, {1, 3}, {1, 2, 3}}", None).unwrap();
+
+        // EO witnesses range over the family
+        let result = checker.check_str("EO X. nonempty X").unwrap();
+        assert!(matches!(result.witnesses["X"], Witness::Open(_, OpenOrigin::Family)));
+
+        // A community value is tagged with its point rather than as a family member
+        let assignment = Assignment::parse("p=2", 3).unwrap();
+        let witness = checker.open_witness(&OpenExpr::Community("p".to_string()), &assignment);
+        assert!(matches!(witness, Some(Witness::Open(0b111, OpenOrigin::Community(2)))));
+
+        let witness = checker.open_witness(
+            &OpenExpr::InteriorComplement(Box::new(OpenExpr::Var("X".to_string()))),
+            &Assignment::parse("X={1,2}", 3).unwrap(),
+        );
+        assert!(matches!(witness, Some(Witness::Open(0, OpenOrigin::InteriorComplement))));
+
+        // Pre-assigned opens are only tagged as family members if they are
+        let var = OpenExpr::Var("X".to_string());
+        let witness = checker.open_witness(&var, &Assignment::parse("X={2,3}", 3).unwrap());
+        assert!(matches!(witness, Some(Witness::Open(0b110, OpenOrigin::Subset))));
+        let witness = checker.open_witness(&var, &Assignment::parse("X={1,3}", 3).unwrap());
+        assert!(matches!(witness, Some(Witness::Open(0b101, OpenOrigin::Family))));
+    }
+
+    #[test]
+    fn test_num_components() {
+        // Connected: the only clopens are the empty and full sets
+        let checker = ModelChecker::from_str("{{}, {1}, {1, 2}, {1, 2, 3}}", None).unwrap();
+        assert_eq!(checker.num_components(), 1);
+
+        // {1, 2} and {3} are complementary opens
+        let checker = ModelChecker::from_str("{{}, {1}, {3}, {1, 2}, {1, 3}, {1, 2, 3}}", None).unwrap();
+        assert_eq!(checker.num_components(), 2);
+
+        // Discrete on three points
+        let checker = ModelChecker::from_str("{{}, {1}, {2}, {3}, {1, 2}, {1, 3}, {2, 3}, {1, 2, 3}}", None).unwrap();
+        assert_eq!(checker.num_components(), 3);
+
+        // A disjoint union has at least as many components as its parts combined
+        let a = ModelChecker::from_str("{{}, {1}, {1, 2}}", None).unwrap();
+        let (union, n) = crate::canon::disjoint_union(a.family(), 2, a.family(), 2).unwrap();
+        assert_eq!(ModelChecker::new(n, union).num_components(), 2);
+    }
+
+    // In this family point 1 lies in every nonempty open; the first failure of
+    // "q in every nonempty open" is q = 2 at X = {1}
+    const EVERY_OPEN: &str = "AO X. nonempty X => q in X";
+
+    #[test]
+    fn test_counterexamples_and() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}, {1, 2, 3}}", None).unwrap();
+
+        // The first conjunct holds; the guilty second conjunct's counterexample is reported
+        let formula = format!("(EP p. AO X. nonempty X => p in X) && (AP q. {})", EVERY_OPEN);
+        let result = checker.check_str(&formula).unwrap();
+        assert!(!result.satisfied);
+        assert!(result.witnesses.is_empty());
+        assert!(matches!(result.counterexamples["q"], Witness::Point(2)));
+        assert!(matches!(result.counterexamples["X"], Witness::Open(0b001, OpenOrigin::Family)));
+        assert!(!result.counterexamples.contains_key("p"));
+    }
+
+    #[test]
+    fn test_counterexamples_or() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}, {1, 2, 3}}", None).unwrap();
+
+        // Both disjuncts fail, so both counterexamples are reported
+        let formula = format!("(AP q. {}) || (AO Y. nonempty Y)", EVERY_OPEN);
+        let result = checker.check_str(&formula).unwrap();
+        assert!(!result.satisfied);
+        assert!(matches!(result.counterexamples["q"], Witness::Point(2)));
+        assert!(matches!(result.counterexamples["Y"], Witness::Open(0, OpenOrigin::Family)));
+
+        // A true disjunct reports only its own witnesses
+        let formula = format!("(AP q. {}) || (EP r. AO X. nonempty X => r in X)", EVERY_OPEN);
+        let result = checker.check_str(&formula).unwrap();
+        assert!(result.satisfied);
+        assert!(result.counterexamples.is_empty());
+        assert!(matches!(result.witnesses["r"], Witness::Point(1)));
+    }
+
+    #[test]
+    fn test_counterexamples_implies_and_not() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}, {1, 2, 3}}", None).unwrap();
+
+        // The premise's witness and the conclusion's counterexample explain the failure
+        let formula = format!("(EP p. AO Y. nonempty Y => p in Y) => (AP q. {})", EVERY_OPEN);
+        let result = checker.check_str(&formula).unwrap();
+        assert!(!result.satisfied);
+        assert!(matches!(result.counterexamples["p"], Witness::Point(1)));
+        assert!(matches!(result.counterexamples["q"], Witness::Point(2)));
+
+        // A failing universal becomes a witness under negation
+        let result = checker.check_str(&format!("!(AP q. {})", EVERY_OPEN)).unwrap();
+        assert!(result.satisfied);
+        assert!(matches!(result.witnesses["q"], Witness::Point(2)));
+        assert!(result.counterexamples.is_empty());
+    }
+
+    #[test]
+    fn test_check_parallel_matches_sequential() {
+        let family = "{{}, {1}, {2}, {1, 2}, {1, 2, 3}, {1, 2, 3, 4}}";
+        let formulas = [
+            "AP p. regular p",
+            "EP p. irregular p",
+            "AO X. EO Y. X inter Y",
+            "EO X. AP p. p in X",
+            "AO X. nonempty X => (EP p. p in X && K p inter X)",
+            "(AP p. p = p) && regular_space",
+        ];
+        for formula_str in formulas {
+            let formula = parse_formula(formula_str).unwrap();
+            let mut sequential = ModelChecker::from_str(family, None).unwrap();
+            let mut parallel = ModelChecker::from_str(family, None).unwrap();
+            let expected = sequential.check(&formula);
+            let result = parallel.check_parallel(&formula, &Assignment::new());
+            assert_eq!(result.satisfied, expected.satisfied, "{}", formula_str);
+            assert_eq!(result.witnesses, expected.witnesses, "{}", formula_str);
+            assert_eq!(result.counterexamples, expected.counterexamples, "{}", formula_str);
+        }
+    }
+
+    #[test]
+    fn test_check_takes_family_as_given() {
+        // Check does not insert the empty set, so "every open is nonempty" can hold
+        let mut checker = ModelChecker::from_str("{{1}, {1, 2}}", None).unwrap();
+        assert!(checker.check_str("AO X. nonempty X").unwrap().satisfied);
+
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}}", None).unwrap();
+        assert!(!checker.check_str("AO X. nonempty X").unwrap().satisfied);
+    }
+
+    #[test]
+    fn test_join_irreducibles() {
+        // {1, 2} = {1} ∪ {2} and {1, 2, 3} = {1} ∪ {2, 3} are reducible
+        let checker = ModelChecker::from_str("{{}, {1}, {2}, {1, 2}, {2, 3}, {1, 2, 3}}", None).unwrap();
+        assert_eq!(checker.join_irreducibles(), vec![0b001, 0b010, 0b110]);
+
+        // In a chain every nonempty open is irreducible
+        let checker = ModelChecker::from_str("{{}, {1}, {1, 2}, {1, 2, 3}}", None).unwrap();
+        assert_eq!(checker.join_irreducibles(), vec![0b001, 0b011, 0b111]);
+
+        // The generators of a union-closed family are exactly its join-irreducibles
+        let generators: Family = [0b0011, 0b0101, 0b1000].into_iter().collect();
+        let closed = crate::canon::union_closure(&generators, 4);
+        let checker = ModelChecker::new(4, closed);
+        assert_eq!(checker.join_irreducibles(), generators.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_iff_evaluation() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}}", None).unwrap();
+
+        let mut assignment = Assignment::new();
+        assignment.assign_open("X".to_string(), 0b01);
+        let formula = parse_formula("nonempty X <=> nonempty X").unwrap();
+        assert!(checker.check_with(&formula, &assignment).satisfied);
+
+        assert!(checker.check_str("AO X. nonempty X <=> nonempty X").unwrap().satisfied);
+        assert!(!checker.check_str("AO X. nonempty X <=> !(nonempty X)").unwrap().satisfied);
+
+        // Both sides false is a match; biconditionals report no witnesses
+        let result = checker.check_str("(EP p. AO X. p in X) <=> (EO X. AP p. !(p in X) && nonempty X)").unwrap();
+        assert!(result.satisfied);
+        assert!(result.witnesses.is_empty());
+    }
+
+    #[test]
+    fn test_point_equality_atoms() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2, 3}}", None).unwrap();
+        assert!(checker.check_str("AP x. AP y. (x = y) => !(x != y)").unwrap().satisfied);
+        assert!(checker.check_str("AP x. EP y. x != y").unwrap().satisfied);
+        assert!(!checker.check_str("AP x. AP y. x = y").unwrap().satisfied);
+
+        // Unbound point variables make both atoms false
+        let formula = parse_formula("x = y || x != y").unwrap();
+        assert!(!checker.check(&formula).satisfied);
+    }
+
+    #[test]
+    fn test_open_equality_atoms() {
+        // Sierpinski space
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}}", None).unwrap();
+        assert!(checker.check_str("AO X. X = X").unwrap().satisfied);
+        assert!(!checker.check_str("AO X. X != X").unwrap().satisfied);
+        assert!(!checker.check_str("AO X. IC X = X").unwrap().satisfied);
+        assert!(checker.check_str("EO X. IC X != X").unwrap().satisfied);
+
+        // An unbound side makes both atoms false
+        let formula = parse_formula("AO X. X = Y || X != Y").unwrap();
+        assert!(!checker.check(&formula).satisfied);
+    }
+
+    #[test]
+    fn test_universal_counterexamples() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}, {1, 2, 3}}", None).unwrap();
+
+        let result = checker.check_str("AP x. EO X. nonempty X && !(x in X)").unwrap();
+        assert!(!result.satisfied);
+        assert_eq!(result.counterexamples["x"], Witness::Point(1));
+
+        let result = checker.check_str("AO X. EP x. x in X").unwrap();
+        assert!(!result.satisfied);
+        assert_eq!(result.counterexamples["X"], Witness::Open(0, OpenOrigin::Family));
+
+        // Satisfied universals carry no counterexample
+        assert!(checker.check_str("AO X. X = X").unwrap().counterexamples.is_empty());
+    }
+
+    #[test]
+    fn test_negation_does_not_leak_witnesses() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}}", None).unwrap();
+        let assignment = Assignment::parse("X={1}", 2).unwrap();
+        let formula = parse_formula("!(EP x. x in X)").unwrap();
+
+        let result = checker.check_with(&formula, &assignment);
+        assert!(!result.satisfied);
+        assert!(result.witnesses.is_empty());
+        // The inner witness explains why the negation fails
+        assert_eq!(result.counterexamples["x"], Witness::Point(1));
+    }
+
+    #[test]
+    fn test_bitset_checker_matches_elem() {
+        use crate::bitset::BitSet;
+
+        let family_str = "{{}, {1}, {2, 3}, {1, 2, 3}, {3, 4}, {2, 3, 4}, {1, 2, 3, 4}}";
+        let mut small = ModelChecker::from_str(family_str, None).unwrap();
+        let mut wide = ModelChecker::<BitSet>::parse_family(family_str, None).unwrap();
+        for formula in ["AP p. AP q. q in K p", "EP p. p in K p", "AO X. EO Y. X inter Y", "AO X. AP p. p in X => p in K p", "EO X. X = IC X"] {
+            let formula = parse_formula(formula).unwrap();
+            assert_eq!(small.check(&formula).satisfied, wide.check(&formula).satisfied);
+        }
+        assert_eq!(small.num_components(), wide.num_components());
+        assert_eq!(small.join_irreducibles().len(), wide.join_irreducibles().len());
+    }
+
+    #[test]
+    fn test_bitset_checker_beyond_64_points() {
+        use crate::bitset::BitSet;
+
+        // Two indiscrete blocks {1..50} and {51..100}
+        let low: Vec<String> = (1..=50).map(|p| p.to_string()).collect();
+        let high: Vec<String> = (51..=100).map(|p| p.to_string()).collect();
+        let family_str = format!("{{{{}}, {{{}}}, {{{}}}, {{{}, {}}}}}", low.join(", "), high.join(", "), low.join(", "), high.join(", "));
+        let mut checker = ModelChecker::<BitSet>::parse_family(&family_str, None).unwrap();
+
+        assert_eq!(checker.n(), 100);
+        assert_eq!(checker.num_components(), 2);
+        let result = checker.check_str("AP p. AP q. q in K p").unwrap();
+        assert!(!result.satisfied);
+        assert_eq!(result.counterexamples["p"], Witness::Point(1));
+        assert_eq!(result.counterexamples["q"], Witness::Point(51));
+        assert!(checker.check_str("AP p. p in K p").unwrap().satisfied);
+    }
+
+    /// Compare the bitmask and BitSet backends at n=20; run with
+    /// `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_u32_vs_bitset() {
+        use crate::bitset::{BitSet, PointSet};
+        use std::time::Instant;
+
+        fn time<S: PointSet>(family_str: &str) -> std::time::Duration {
+            let mut checker = ModelChecker::<S>::parse_family(family_str, None).unwrap();
+            let formula = parse_formula("AO X. AO Y. EO Z. AP x. (x in X || x in Y) => x in Z && x in K x").unwrap();
+            let start = Instant::now();
+            assert!(checker.check(&formula).satisfied);
+            start.elapsed()
+        }
+
+        // The chain {} ⊂ {1} ⊂ {1, 2} ⊂ ... ⊂ {1..20}
+        let chain: Vec<String> = (0..=20)
+            .map(|k| format!("{{{}}}", (1..=k).map(|p| p.to_string()).collect::<Vec<_>>().join(", ")))
+            .collect();
+        let family_str = format!("{{{}}}", chain.join(", "));
+        println!("u32:    {:?}", time::<u32>(&family_str));
+        println!("BitSet: {:?}", time::<BitSet>(&family_str));
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_community_evaluations() {
+        use std::time::Instant;
+
+        // Every binding evaluates K x and K y, all but the first n of them from the cache
+        let chain: Vec<String> = (0..=20)
+            .map(|k| format!("{{{}}}", (1..=k).map(|p| p.to_string()).collect::<Vec<_>>().join(", ")))
+            .collect();
+        let mut checker = ModelChecker::from_str(&format!("{{{}}}", chain.join(", ")), None).unwrap();
+        let formula = parse_formula("AO X. AP x. AP y. K x inter K y || K x = K y || x in K y").unwrap();
+        let start = Instant::now();
+        let (result, stats) = checker.check_with_stats(&formula, &Assignment::new());
+        let elapsed = start.elapsed();
+        assert!(result.satisfied);
+        let lookups = stats.community_cache_hits + stats.community_cache_misses;
+        println!("{} community lookups in {:?} ({:?} each)", lookups, elapsed, elapsed / lookups as u32);
+    }
+
+    #[test]
+    fn test_community_computed_once_per_point() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {2, 3}, {1, 2, 3}}", None).unwrap();
+        let result = checker.check_str("AP p. AP q. (q in K p) => (K q inter K p) && nonempty K p").unwrap();
+        assert!(result.satisfied);
+        assert_eq!(checker.stats.community_cache_misses, 3);
+
+        // A second check reuses every cached community
+        assert!(checker.check_str("AP p. p in K p").unwrap().satisfied);
+        assert_eq!(checker.stats.community_cache_misses, 3);
+    }
+
+    #[test]
+    fn test_interior_complement_cache() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {2}, {1, 2}, {2, 3}, {1, 2, 3}}", None).unwrap();
+        let ic = OpenExpr::InteriorComplement(Box::new(OpenExpr::Var("X".to_string())));
+        let family: Vec<Elem> = checker.family().iter().copied().collect();
+
+        // Every open is evaluated twice, the second time from the cache
+        for _ in 0..2 {
+            for &open in &family {
+                let assignment = Assignment::new().clone_with_open("X".to_string(), open);
+                assert_eq!(checker.eval_open_expr(&ic, &assignment), Some(checker.interior_complement(&open)));
+            }
+        }
+        assert_eq!(checker.ic_cache.len(), family.len());
+
+        assert!(checker.check_str("AO X. AO Y. (IC X = IC Y) || !(X = Y)").unwrap().satisfied);
+        assert!(checker.check_str("AO X. !(X inter IC X)").unwrap().satisfied);
+        assert!(!checker.check_str("AO X. nonempty IC X").unwrap().satisfied);
+    }
+
+    #[test]
+    fn test_closure_on_sierpinski() {
+        // Sierpinski space: the closed sets are {}, {2} and {1, 2}
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}}", None).unwrap();
+        let closure = OpenExpr::Closure(Box::new(OpenExpr::Var("X".to_string())));
+        for (open, expected) in [(0b00, 0b00), (0b01, 0b11), (0b11, 0b11)] {
+            let assignment = Assignment::new().clone_with_open("X".to_string(), open);
+            assert_eq!(checker.open_witness(&closure, &assignment), Some(Witness::Open(expected, OpenOrigin::Closure)));
+        }
+
+        assert!(checker.check_str("AO X. AP x. x in X => x in Cl X").unwrap().satisfied);
+        assert!(checker.check_str("AO X. Cl (Cl X) = Cl X").unwrap().satisfied);
+        // {1} is open but not closed
+        let result = checker.check_str("AO X. X = Cl X").unwrap();
+        assert!(!result.satisfied);
+        assert_eq!(result.counterexamples["X"], Witness::Open(0b01, OpenOrigin::Family));
+    }
+
+    #[test]
+    fn test_union_of_opens() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {2}, {1, 2}, {1, 2, 3}}", None).unwrap();
+
+        let formula = parse_formula("nonempty (X union Y)").unwrap();
+        let empty = Assignment::parse("X={}, Y={}", 3).unwrap();
+        assert!(!checker.check_with(&formula, &empty).satisfied);
+        let one_side = Assignment::parse("X={}, Y={2}", 3).unwrap();
+        assert!(checker.check_with(&formula, &one_side).satisfied);
+
+        // Unions of family members stay in the family
+        assert!(checker.check_str("AO X. AO Y. EO Z. Z = X union Y").unwrap().satisfied);
+
+        // Cl {1} = {1, 3} is not a family member, and neither is its union with {}
+        let assignment = Assignment::parse("X={1}, Y={}", 3).unwrap();
+        let union = parse_formula("EO Z. Z = Cl X union Y").unwrap();
+        assert!(!checker.check_with(&union, &assignment).satisfied);
+        let expr = OpenExpr::Union(
+            Box::new(OpenExpr::Closure(Box::new(OpenExpr::Var("X".to_string())))),
+            Box::new(OpenExpr::Var("Y".to_string())),
+        );
+        assert_eq!(checker.open_witness(&expr, &assignment), Some(Witness::Open(0b101, OpenOrigin::Subset)));
+    }
+
+    #[test]
+    fn test_intersection_of_opens() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}, {2, 3}, {1, 2, 3}}", None).unwrap();
+        assert!(checker.check_str("AO X. AO Y. AP x. x in (X cap Y) <=> x in X && x in Y").unwrap().satisfied);
+        assert!(checker.check_str("AO X. AO Y. nonempty (X cap Y) <=> X inter Y").unwrap().satisfied);
+
+        // {1, 2} cap {2, 3} = {2} is not a family member
+        let assignment = Assignment::parse("X={1,2}, Y={2,3}", 3).unwrap();
+        assert!(!checker.check_with(&parse_formula("EO Z. Z = X cap Y").unwrap(), &assignment).satisfied);
+        let expr = OpenExpr::Inter(Box::new(OpenExpr::Var("X".to_string())), Box::new(OpenExpr::Var("Y".to_string())));
+        assert_eq!(checker.open_witness(&expr, &assignment), Some(Witness::Open(0b010, OpenOrigin::Subset)));
+    }
+
+    #[test]
+    fn test_subset_atom() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}, {3}, {1, 3}, {1, 2, 3}}", None).unwrap();
+        assert!(checker.check_str("AO X. X subseteq X").unwrap().satisfied);
+        assert!(checker.check_str("AO X. AO Y. X subseteq Y => (X inter Y || !nonempty X)").unwrap().satisfied);
+        assert!(checker.check_str("AO X. AO Y. X subseteq Y && Y subseteq X <=> X = Y").unwrap().satisfied);
+
+        let result = checker.check_str("AO X. AO Y. X subseteq Y || Y subseteq X").unwrap();
+        assert!(!result.satisfied);
+        assert_eq!(result.counterexamples["X"], Witness::Open(0b001, OpenOrigin::Family));
+        assert_eq!(result.counterexamples["Y"], Witness::Open(0b100, OpenOrigin::Family));
+
+        // Unknown variables evaluate to false
+        assert!(!checker.check_str("X subseteq X").unwrap().satisfied);
+    }
+
+    #[test]
+    fn test_powerset_open_domain() {
+        let formula = parse_formula("AO X. !nonempty X || (AP x. x in X)").unwrap();
+
+        let mut checker = ModelChecker::from_str("{{}, {1, 2}}", None).unwrap();
+        assert!(checker.check(&formula).satisfied);
+
+        // Over the powerset X = {1} is a counterexample, reported as a non-member
+        let mut checker = checker.with_open_domain(OpenDomain::Powerset);
+        let result = checker.check(&formula);
+        assert!(!result.satisfied);
+        assert_eq!(result.counterexamples["X"], Witness::Open(0b01, OpenOrigin::Subset));
+        assert_eq!(checker.check_parallel(&formula, &Assignment::new()).counterexamples, result.counterexamples);
+
+        assert_eq!("powerset".parse(), Ok(OpenDomain::Powerset));
+        assert!("opens".parse::<OpenDomain>().is_err());
+    }
+
+    #[test]
+    fn test_all_models() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2, 3}}", None).unwrap();
+        let formula = parse_formula("EP x. EP y. x != y").unwrap();
+        let models = checker.all_models(&formula, &Assignment::new(), None);
+        assert_eq!(models.len(), 6);
+        assert_eq!((models[0].points["x"], models[0].points["y"]), (1, 2));
+        assert!(models.iter().all(|m| m.points["x"] != m.points["y"]));
+
+        assert_eq!(checker.all_models(&formula, &Assignment::new(), Some(4)).len(), 4);
+
+        // Existing bindings are kept, and a closed formula has at most one model
+        let assignment = Assignment::parse("x=2", 3).unwrap();
+        let models = checker.all_models(&parse_formula("EO X. x in X").unwrap(), &assignment, None);
+        assert_eq!(models.iter().map(|m| m.opens["X"]).collect::<Vec<_>>(), vec![0b111]);
+        assert!(models.iter().all(|m| m.points["x"] == 2));
+        assert_eq!(checker.all_models(&parse_formula("AP x. EO X. x in X").unwrap(), &Assignment::new(), None).len(), 1);
+    }
+
+    #[test]
+    fn test_boundary() {
+        // {1} and {2, 3} are clopen; {2} is open but not closed
+        let mut checker = ModelChecker::from_str("{{}, {1}, {2}, {1, 2}, {2, 3}, {1, 2, 3}}", None).unwrap();
+        let boundary = OpenExpr::Boundary(Box::new(OpenExpr::Var("X".to_string())));
+        for (open, expected) in [(0b001, 0b000), (0b110, 0b000), (0b010, 0b100), (0b011, 0b100)] {
+            let assignment = Assignment::new().clone_with_open("X".to_string(), open);
+            assert_eq!(checker.open_witness(&boundary, &assignment), Some(Witness::Open(expected, OpenOrigin::Boundary)));
+        }
+
+        let assignment = Assignment::parse("X={1}", 3).unwrap();
+        assert!(checker.check_with(&parse_formula("!nonempty Bd X").unwrap(), &assignment).satisfied);
+        assert!(checker.check_str("AO X. !(X inter Bd X)").unwrap().satisfied);
+        assert!(checker.check_str("AO X. Bd X subseteq Cl X").unwrap().satisfied);
+    }
+
+    #[test]
+    fn test_to_nnf() {
+        fn is_nnf(formula: &Formula) -> bool {
+            match formula {
+                Formula::True | Formula::False | Formula::Atom(_) => true,
+                Formula::Not(f) => matches!(**f, Formula::Atom(_)),
+                Formula::And(f1, f2) | Formula::Or(f1, f2) => is_nnf(f1) && is_nnf(f2),
+                Formula::Implies(..) | Formula::Iff(..) | Formula::Xor(..) => false,
+                Formula::ForAllPoints(_, f) | Formula::ExistsPoints(_, f)
+                | Formula::ForAllOpens(_, f) | Formula::ExistsOpens(_, f)
+                | Formula::ForAllTopens(_, f) | Formula::ExistsTopens(_, f) => is_nnf(f),
+            }
+        }
+
+        let nnf = to_nnf(&parse_formula("!(AP x. EO X. x in X)").unwrap());
+        assert_eq!(nnf, parse_formula("EP x. AO X. !(x in X)").unwrap());
+        let nnf = to_nnf(&parse_formula("!(A subseteq B => !nonempty A)").unwrap());
+        assert_eq!(nnf, parse_formula("A subseteq B && nonempty A").unwrap());
+
+        let formulas = [
+            "!(AP x. EO X. x in X)",
+            "AO X. AO Y. !(X inter Y => X = Y)",
+            "!(EP p. AP q. q in K p <=> p in K q)",
+            "AO X. !!(nonempty X) <=> !(AP x. !(x in X))",
+            "!(AO X. EO Y. (X inter Y) || !(X subseteq Cl Y))",
+            "regular_space => !conflicted_space",
+            "!(AO X. nonempty X ^^ (EP x. x in K x))",
+        ];
+        let families = ["{{}, {1}, {1, 2}}", "{{}, {1}, {2}, {1, 2}, {2, 3}, {1, 2, 3}}", "{{}, {1, 2}, {2, 3}, {1, 2, 3}}"];
+        for formula in formulas {
+            let formula = parse_formula(formula).unwrap();
+            let nnf = to_nnf(&formula);
+            assert!(is_nnf(&nnf), "{:?}", nnf);
+            for family in families {
+                let mut checker = ModelChecker::from_str(family, None).unwrap();
+                assert_eq!(checker.check(&formula).satisfied, checker.check(&nnf).satisfied, "{} on {}", formula, family);
+            }
+        }
+    }
+
+    #[test]
+    fn test_simplify() {
+        fn size(formula: &Formula) -> usize {
+            match formula {
+                Formula::True | Formula::False | Formula::Atom(_) => 1,
+                Formula::Not(f) => 1 + size(f),
+                Formula::And(f1, f2) | Formula::Or(f1, f2) | Formula::Implies(f1, f2) | Formula::Iff(f1, f2)
+                | Formula::Xor(f1, f2) => {
+                    1 + size(f1) + size(f2)
+                }
+                Formula::ForAllPoints(_, f) | Formula::ExistsPoints(_, f)
+                | Formula::ForAllOpens(_, f) | Formula::ExistsOpens(_, f)
+                | Formula::ForAllTopens(_, f) | Formula::ExistsTopens(_, f) => 1 + size(f),
+            }
+        }
+        let atom = || Formula::Atom(Atom::PointInOpen("x".to_string(), OpenExpr::Var("X".to_string())));
+        let not = |f: Formula| Formula::Not(Box::new(f));
+        let and = |f1: Formula, f2: Formula| Formula::And(Box::new(f1), Box::new(f2));
+        let or = |f1: Formula, f2: Formula| Formula::Or(Box::new(f1), Box::new(f2));
+        let implies = |f1: Formula, f2: Formula| Formula::Implies(Box::new(f1), Box::new(f2));
+        let all_opens = |f: Formula| Formula::ForAllOpens("X".to_string(), Box::new(f));
+        let exists_points = |f: Formula| Formula::ExistsPoints("x".to_string(), Box::new(f));
+
+        assert_eq!(simplify(&and(Formula::True, atom())), atom());
+        assert_eq!(simplify(&or(atom(), Formula::True)), Formula::True);
+        assert_eq!(simplify(&not(not(atom()))), atom());
+        assert_eq!(simplify(&implies(Formula::True, atom())), atom());
+        assert_eq!(simplify(&implies(atom(), Formula::False)), not(atom()));
+        assert_eq!(simplify(&all_opens(or(atom(), not(Formula::False)))), Formula::True);
+        // An existential over a true body still depends on the domain being nonempty
+        assert_eq!(simplify(&exists_points(Formula::True)), exists_points(Formula::True));
+
+        let formulas = [
+            all_opens(exists_points(and(Formula::True, not(not(atom()))))),
+            all_opens(exists_points(implies(Formula::True, or(Formula::False, atom())))),
+            all_opens(or(exists_points(atom()), and(Formula::False, atom()))),
+            not(all_opens(not(exists_points(not(not(atom())))))),
+        ];
+        for family in ["{{}, {1}, {1, 2}}", "{{1, 2}}", "{}"] {
+            let mut checker = ModelChecker::from_str(family, Some(2)).unwrap();
+            for formula in &formulas {
+                let simplified = simplify(formula);
+                assert!(size(&simplified) < size(formula), "{:?}", simplified);
+                assert_eq!(checker.check(formula).satisfied, checker.check(&simplified).satisfied, "{} on {}", formula, family);
+            }
+        }
+
+        // The parser hands the evaluator simplified formulas
+        assert_eq!(parse_formula("!!(x in X)").unwrap(), atom());
+    }
+
+    #[test]
+    fn test_check_with_stats() {
+        let mut checker = ModelChecker::from_str("{{1, 2, 3}}", None).unwrap();
+        let assignment = Assignment::parse("X={1,2,3}", 3).unwrap();
+        let formula = parse_formula("AP x. x in X").unwrap();
+        let (result, stats) = checker.check_with_stats(&formula, &assignment);
+        assert!(result.satisfied);
+        assert_eq!(stats.atom_evaluations, 3);
+        assert_eq!(stats.quantifier_expansions, 3);
+
+        let formula = parse_formula("AP x. AP y. y in K x || IC (K x) inter K y").unwrap();
+        let (_, stats) = checker.check_with_stats(&formula, &Assignment::new());
+        assert_eq!(stats.community_cache_misses, 3);
+        assert_eq!(stats.community_cache_hits + stats.community_cache_misses, 9);
+        // Counters start again from zero on every check
+        let (_, again) = checker.check_with_stats(&formula, &Assignment::new());
+        assert_eq!((again.community_cache_misses, again.community_cache_hits), (0, 9));
+        assert_eq!(again.atom_evaluations, stats.atom_evaluations);
+    }
+
+    #[test]
+    fn test_topen_quantifiers() {
+        let families = [
+            "{{}, {1}, {1, 2}}",
+            "{{}, {1}, {2}, {1, 2}}",
+            "{{}, {1, 2}, {2, 3}, {1, 2, 3}}",
+            "{{}, {1}, {2, 3}, {1, 2, 3}}",
+            "{{}, {1}, {2}, {1, 2}, {2, 3}, {1, 2, 3}}",
+        ];
+        let pairs = [
+            ("EP p. ET T. p in T", "EP p. EO T. topen T && p in T"),
+            ("AP p. ET T. p in T", "AP p. EO T. topen T && p in T"),
+            ("AT T. AT U. T inter U => T = U", "AO T. AO U. topen T && topen U => (T inter U => T = U)"),
+            ("AP p. regular p <=> (ET T. p in T && T subseteq K p)", "AP p. regular p <=> (EO T. topen T && p in T && T subseteq K p)"),
+        ];
+        for family in families {
+            let mut checker = ModelChecker::from_str(family, None).unwrap();
+            for (topen_form, expanded) in pairs {
+                assert_eq!(
+                    checker.check_str(topen_form).unwrap().satisfied,
+                    checker.check_str(expanded).unwrap().satisfied,
+                    "{} on {}", topen_form, family
+                );
+            }
+        }
+
+        // The full set is not a topen here, as {1} and {2, 3} both meet it
+        let mut checker = ModelChecker::from_str("{{}, {1}, {2, 3}, {1, 2, 3}}", None).unwrap();
+        assert_eq!(checker.topens(), &vec![0b001, 0b110]);
+        let result = checker.check_str("AT T. AP p. p in T").unwrap();
+        assert!(!result.satisfied);
+        assert_eq!(result.counterexamples["T"], Witness::Open(0b001, OpenOrigin::Family));
+    }
+
+    #[test]
+    fn test_topens_sierpinski() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}}", None).unwrap();
+        assert_eq!(checker.topens(), &vec![0b01, 0b11]);
+
+        // Agrees with the topen macro on a family with several intertwined opens
+        let mut checker = ModelChecker::from_str("{{}, {1}, {2}, {1, 2}, {2, 3}, {1, 2, 3}, {3, 4}, {2, 3, 4}, {1, 2, 3, 4}}", None).unwrap();
+        let topens = checker.topens().clone();
+        let formula = parse_formula("topen X").unwrap();
+        for open in checker.family().clone() {
+            let assignment = Assignment::new().clone_with_open("X".to_string(), open);
+            assert_eq!(checker.check_with(&formula, &assignment).satisfied, topens.contains(&open), "{:b}", open);
+        }
+    }
+
+    #[test]
+    fn test_is_topen_matches_topen_macro() {
+        let families = [
+            "{{}, {1}, {1, 2}}",
+            "{{}, {1}, {2}, {1, 2}}",
+            "{{}, {1, 2}, {2, 3}, {1, 2, 3}}",
+            "{{}, {1}, {2}, {1, 2}, {2, 3}, {1, 2, 3}, {3, 4}, {2, 3, 4}, {1, 2, 3, 4}}",
+        ];
+        let formula = parse_formula("AO X. is_topen X <=> topen X").unwrap();
+        for family in families {
+            let mut checker = ModelChecker::from_str(family, None).unwrap();
+            assert!(checker.check(&formula).satisfied, "{}", family);
+        }
+
+        // The predicate applies to the resolved set, so unions are tested as well:
+        // {1} and {2} are topens but {1, 2} is not
+        let mut checker = ModelChecker::from_str("{{}, {1}, {2}, {1, 2}}", None).unwrap();
+        assert!(!checker.check_str("EO X. EO Y. X != Y && is_topen (X union Y) && nonempty X && nonempty Y").unwrap().satisfied);
+        assert!(checker.check_str("EO X. is_topen X").unwrap().satisfied);
+    }
+
+    #[test]
+    fn test_check_with_deadline() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {2}, {1, 2}, {3, 4}, {5, 6}, {1, 2, 3, 4, 5, 6}}", None)
+            .unwrap()
+            .with_open_domain(OpenDomain::Powerset);
+        let formula = parse_formula("AO X. AO Y. AO Z. AO W. X cap Y cap Z subseteq W union X").unwrap();
+        let err = checker.check_with_deadline(&formula, &Assignment::new(), Duration::from_millis(1)).unwrap_err();
+        assert!(err.starts_with("timed out after"), "{}", err);
+
+        // The checker is usable again afterwards, and quick checks finish in time
+        let formula = parse_formula("EO X. nonempty X").unwrap();
+        let result = checker.check_with_deadline(&formula, &Assignment::new(), Duration::from_secs(60)).unwrap();
+        assert!(result.satisfied);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_formula_serde_round_trip() {
+        for source in ["AO X. EO Y. AP x. (x in X) || (X inter Y) => !(x in Y)", "regular_space", "ET T. is_topen T"] {
+            let formula = parse_formula(source).unwrap();
+            let json = serde_json::to_string(&formula).unwrap();
+            let back: Formula = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, formula, "{}", json);
+        }
+    }
+
+    #[test]
+    fn test_describe_sierpinski() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}}", None).unwrap();
+        let description = checker.describe();
+        assert_eq!(description.space.len(), SPACE_PREDICATES.len());
+        assert!(description.space.contains(&("regular_space", true)));
+        assert!(description.space.contains(&("conflicted_space", false)));
+        assert!(description.points.contains(&("regular", vec![1, 2])));
+        assert!(description.points.contains(&("conflicted", vec![])));
+        assert_eq!(description.topens, vec![0b01, 0b11]);
+    }
+
+    #[test]
+    fn test_point_literals() {
+        let mut checker = ModelChecker::from_str("{{1, 2}}", None).unwrap();
+        assert!(checker.check_str("EO X. 1 in X && 2 in X").unwrap().satisfied);
+        let mut checker = ModelChecker::from_str("{{}, {1, 2}}", Some(3)).unwrap();
+        assert!(!checker.check_str("EO X. 1 in X && 3 in X").unwrap().satisfied);
+        assert!(checker.check_str("EP x. x = 2 && 1 != x").unwrap().satisfied);
+        assert!(checker.check_str("1 in K 2").unwrap().satisfied);
+        // Literals outside 1..=n denote no point, like unbound variables
+        assert!(!checker.check_str("AO X. nonempty X => 4 in X").unwrap().satisfied);
+        assert!(!checker.check_str("4 = 4").unwrap().satisfied);
+    }
+
+    #[test]
+    fn test_set_literals() {
+        let mut checker = ModelChecker::from_str("{{}, {1, 2}, {1, 2, 3}}", None).unwrap();
+        assert!(checker.check_str("3 in {1, 3}").unwrap().satisfied);
+        assert!(!checker.check_str("2 in {1, 3}").unwrap().satisfied);
+        assert!(checker.check_str("EP x. x in {1,3} && x in {2, 3}").unwrap().satisfied);
+        // Literals need not be opens of the family
+        assert!(checker.check_str("AO X. X != {1, 3}").unwrap().satisfied);
+        assert!(checker.check_str("EO X. X = {1, 2} union {}").unwrap().satisfied);
+        // Points outside 1..=n denote nothing
+        assert!(!checker.check_str("{1, 4} = {1, 4}").unwrap().satisfied);
+    }
+
+    #[test]
+    fn test_truth_constants() {
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}}", None).unwrap();
+        for formula in ["EO X. nonempty X", "AO X. nonempty X", "AP x. EO X. x in X && !(X = {1})"] {
+            let with_true = format!("true => ({})", formula);
+            assert_eq!(
+                checker.check_str(&with_true).unwrap().satisfied,
+                checker.check_str(formula).unwrap().satisfied
+            );
+            assert!(checker.check_str(&format!("false => ({})", formula)).unwrap().satisfied);
+        }
+        // Unsimplified constants evaluate directly
+        assert!(checker.check(&Formula::True).satisfied);
+        assert!(!checker.check(&Formula::Not(Box::new(Formula::True))).satisfied);
+    }
+
+    #[test]
+    fn test_user_definitions() {
+        let formula = parse_formula(
+            "def twice_regular(p) := regular p && regular p; AP x. twice_regular(x) <=> regular x"
+        ).unwrap();
+        for family in ["{{}, {1}, {1, 2}}", "{{}, {1}, {2}, {1, 2}, {2, 3}, {1, 2, 3}}", "{{}, {1, 2}, {2, 3}, {1, 2, 3}}"] {
+            let mut checker = ModelChecker::from_str(family, None).unwrap();
+            assert!(checker.check(&formula).satisfied, "{}", family);
+        }
+        // Point 3 is irregular here, and is found through the definition
+        let mut checker = ModelChecker::from_str("{{}, {1}, {2}, {1, 2}, {1, 2, 3}}", None).unwrap();
+        assert!(checker.check_str("def twice_regular(p) := regular p && regular p; EP x. !twice_regular(x)").unwrap().satisfied);
+    }
+
+    #[test]
+    fn test_xor() {
+        // Two distinct opens, exactly one of them empty
+        let exactly_one = "AO X. AO Y. X != Y => nonempty X ^^ nonempty Y";
+        let mut checker = ModelChecker::from_str("{{}, {1, 2}}", None).unwrap();
+        assert!(checker.check_str(exactly_one).unwrap().satisfied);
+        assert!(checker.check_str("EO X. EO Y. nonempty X ^^ nonempty Y").unwrap().satisfied);
+        let mut checker = ModelChecker::from_str("{{}, {1}, {1, 2}}", None).unwrap();
+        assert!(!checker.check_str(exactly_one).unwrap().satisfied);
+        assert!(checker.check_str("AO X. nonempty X ^^ X = {}").unwrap().satisfied);
+        assert!(!checker.check_str("EO X. nonempty X xor nonempty X").unwrap().satisfied);
+        assert_eq!(simplify(&parse_formula("true ^^ nonempty X").unwrap()), parse_formula("!nonempty X").unwrap());
+    }
+
+    #[test]
+    fn test_univ_and_empty() {
+        for (family, n) in [("{{}, {1}, {1, 2}}", Some(2)), ("{{1, 2}}", Some(3)), ("{{}}", Some(1))] {
+            let mut checker = ModelChecker::from_str(family, n).unwrap();
+            assert!(checker.check_str("nonempty UNIV").unwrap().satisfied, "{}", family);
+            assert!(!checker.check_str("nonempty EMPTY").unwrap().satisfied, "{}", family);
+            assert!(checker.check_str("AO X. EMPTY subseteq X && X subseteq UNIV").unwrap().satisfied);
+        }
+        // Independent of the family: {1, 2, 3} is not an open here, and {} is missing
+        let mut checker = ModelChecker::from_str("{{1, 2}}", Some(3)).unwrap();
+        assert!(checker.check_str("UNIV = {1, 2, 3} && EMPTY = {}").unwrap().satisfied);
+        assert!(checker.check_str("AO X. X != UNIV && X != EMPTY").unwrap().satisfied);
+    }
+
+    #[test]
+    fn test_free_vars() {
+        let set = |vars: &[&str]| vars.iter().map(|v| v.to_string()).collect::<HashSet<_>>();
+        let formula = parse_formula("AP x. (x in X && y in K x) || (EO Y. Y subseteq Z cap MON z)").unwrap();
+        assert_eq!(free_vars(&formula), (set(&["y", "z"]), set(&["X", "Z"])));
+        assert_eq!(free_vars(&parse_formula("hypertransitive_space").unwrap()), (set(&[]), set(&[])));
+
+        let formula = parse_formula("p in X").unwrap();
+        let mut assignment = Assignment::<Elem>::new();
+        assert_eq!(check_bound(&formula, &assignment), Err("unbound variable X".to_string()));
+        assignment.assign_open("X".to_string(), 0b1);
+        assert_eq!(check_bound(&formula, &assignment), Err("unbound variable p".to_string()));
+        assignment.assign_point("p".to_string(), 1);
+        assert_eq!(check_bound(&formula, &assignment), Ok(()));
+    }
+
+    #[test]
+    fn test_formula_to_str() {
+        let explain = |input: &str| formula_to_str(&parse_formula(input).unwrap());
+        assert_eq!(
+            explain("regular p"),
+            "nonempty K p && (AO O_0. AO P_1. O_0 inter K p && K p inter P_1 => O_0 inter P_1)"
+        );
+        assert_eq!(explain("!(x in X)"), "!(x in X)");
+        assert_eq!(explain("(x in X => X inter Y) => y = z"), "(x in X => X inter Y) => y = z");
+
+        // Printing and parsing again gives back the same formula
+        for input in [
+            "AO X. AP x. x in X => (EO Y. x in Y && Y subseteq X)",
+            "(x in X => y in X) => x = y <=> nonempty X",
+            "x = y <=> (x in X <=> (X inter Y))",
+            "x in X ^^ y in X || !(x = y) && X = Y",
+            "!!(x = y) || x != 1",
+            "AT T. EP x. x in T && is_topen (T union IC T cap Cl (X union Y))",
+            "AP x. MON x = K x union {1, 3} || Bd UNIV != EMPTY",
+            "EO X. X inter (X union Y) && (nonempty X || true) && !false",
+            "hypertransitive_space && regular_space",
+        ] {
+            let formula = parse_formula(input).unwrap();
+            let printed = formula_to_str(&formula);
+            assert_eq!(parse_formula(&printed), Ok(formula), "{} printed as {}", input, printed);
+        }
+    }
+
+    #[test]
+    fn test_formula_to_str_round_trips() {
+        // Every connective and quantifier over every pair of smaller formulas, two levels deep;
+        // double negations and constants are left out since parsing simplifies them away
+        let atom = |a: Atom| Formula::Atom(a);
+        let var = |name: &str| OpenExpr::Var(name.to_string());
+        let union = OpenExpr::Union(Box::new(var("X")), Box::new(var("Y")));
+        let leaves = vec![
+            atom(Atom::PointInOpen("x".to_string(), union.clone())),
+            atom(Atom::OpenIntersection(OpenExpr::Inter(Box::new(union), Box::new(var("Y"))), var("X"))),
+        ];
+        let wrap = |formulas: &[Formula]| {
+            let mut wrapped = Vec::new();
+            for f in formulas {
+                let b = || Box::new(f.clone());
+                if !matches!(f, Formula::Not(_)) {
+                    wrapped.push(Formula::Not(b()));
+                }
+                wrapped.push(Formula::ForAllPoints("x".to_string(), b()));
+                wrapped.push(Formula::ExistsOpens("X".to_string(), b()));
+            }
+            for f1 in formulas {
+                for f2 in formulas {
+                    let (b1, b2) = (|| Box::new(f1.clone()), || Box::new(f2.clone()));
+                    wrapped.push(Formula::And(b1(), b2()));
+                    wrapped.push(Formula::Or(b1(), b2()));
+                    wrapped.push(Formula::Implies(b1(), b2()));
+                    wrapped.push(Formula::Iff(b1(), b2()));
+                    wrapped.push(Formula::Xor(b1(), b2()));
+                }
+            }
+            wrapped
+        };
+        let depth1: Vec<Formula> = leaves.iter().cloned().chain(wrap(&leaves)).collect();
+        let depth2 = wrap(&depth1);
+        assert!(depth2.len() > 1000);
+        for formula in depth1.into_iter().chain(depth2) {
+            let printed = formula.to_string();
+            assert_eq!(parse_formula(&printed).as_ref(), Ok(&formula), "{:?} printed as {}", formula, printed);
+        }
+    }
+
+    #[test]
+    fn test_check_lines() {
+        let mut checker = ModelChecker::<Elem>::parse_family("{{}, {1}, {1, 2, 3}}", None).unwrap();
+        let suite = "# a small suite\nEO X. nonempty X\n\nAP x. EO X. x in X\n  AO X. AO Y. X inter Y => X = Y\n";
+        let results = checker.check_lines(suite, &Assignment::new()).unwrap();
+        assert_eq!(results, vec![
+            ("EO X. nonempty X".to_string(), true),
+            ("AP x. EO X. x in X".to_string(), true),
+            ("AO X. AO Y. X inter Y => X = Y".to_string(), false),
+        ]);
+
+        let err = checker.check_lines("true\nEO X. X inter\n", &Assignment::new()).unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+        let err = checker.check_lines("\n\nx in X\n", &Assignment::new()).unwrap_err();
+        assert!(err.starts_with("line 3:") && err.contains("unbound"), "{}", err);
+    }
+
+    #[test]
+    fn test_filter_family_lines() {
+        let formula = parse_formula("EO X. EO Y. nonempty X && nonempty Y && !(X inter Y)").unwrap();
+        let text = "n=2 {{}, {1}, {2}, {1, 2}}\n\nn=2 {{}, {1}, {1, 2}}\n";
+        let matching = filter_family_lines(text, None, &formula, OpenDomain::Family).unwrap();
+        assert_eq!(matching, vec!["n=2 {{}, {1}, {2}, {1, 2}}"]);
+
+        // --size applies to lines without a prefix, which are otherwise inferred
+        let matching = filter_family_lines("{{}, {1}, {2}}\n", Some(3), &formula, OpenDomain::Family).unwrap();
+        assert_eq!(matching.len(), 1);
+
+        let err = filter_family_lines("{{1}}\n{{1} x}\n", None, &formula, OpenDomain::Family).unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+    }
+}
@@ -8,50 +8,180 @@
 //! The parser handles the complete grammar including quantifiers, logical operators,
 //! built-in predicates, and complex macro expansions with proper variable scoping.
 
-use crate::model_checker::Formula;
-use crate::tokens::Lexer;
+use crate::model_checker::{Assignment, Formula, check_bound, simplify};
+use crate::ast::{BinaryProp, LogicProp, Prop, QuantProp, UnaryProp};
+use crate::tokens::{LexError, Lexer, Token};
 use crate::macro_expander::MacroExpander;
-use lalrpop_util::lalrpop_mod;
+use lalrpop_util::{lalrpop_mod, ParseError};
 
 // Include the LALRPOP-generated parser
 lalrpop_mod!(pub grammar);
 
 /// Parse a formula string into the model checker's Formula AST
 ///
-/// This is the main entry point for parsing. It performs three stages:
+/// This is the main entry point for parsing. It performs four stages:
 /// 1. **Lexical analysis**: Tokenize input using Logos DFA lexer
 /// 2. **Syntax analysis**: Parse tokens using LALRPOP LR(1) parser  
-/// 3. **Macro expansion**: Expand all macro constructs with fresh variable generation
+/// 3. **Macro expansion**: Expand all macro constructs with fresh variable generation,
+///    including uses of `def name(p, X) := ...;` definitions given before the formula
+/// 4. **Simplification**: Fold constants and double negations (see `simplify`)
 ///
 /// # Arguments
 /// * `input` - The formula string to parse
 ///
 /// # Returns
 /// * `Ok(Formula)` - Successfully parsed and expanded formula
-/// * `Err(String)` - Parse or expansion error with description
+/// * `Err(String)` - Parse or expansion error with description, or `"empty formula"`
+///   when the input contains no tokens at all. Parse errors give the line and column
+///   and quote the offending line with a caret under the problem
 ///
 /// # Examples
 /// ```
+/// # use semiframes::parse_formula;
 /// let formula = parse_formula("EO X. EP x. x in X")?;
 /// let complex = parse_formula("AO T. transitive T => regular_space")?;
+/// # Ok::<(), String>(())
 /// ```
 pub fn parse_formula(input: &str) -> Result<Formula, String> {
+    parse_formula_with_warnings(input).map(|parsed| parsed.formula)
+}
+
+/// A parsed formula together with warnings about how it was written
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedFormula {
+    pub formula: Formula,
+    /// Legal but suspicious constructs, such as a quantifier shadowing an enclosing one
+    pub warnings: Vec<String>,
+}
+
+/// Parse a formula like `parse_formula`, also reporting warnings about it
+pub fn parse_formula_with_warnings(input: &str) -> Result<ParsedFormula, String> {
+    // Reject input with no tokens up front rather than reporting an unexpected EOF
+    if Lexer::new(input).next().is_none() {
+        return Err("empty formula".to_string());
+    }
+
     // Stage 1: Lexical analysis
     let lexer = Lexer::new(input);
     
     // Stage 2: Syntax analysis  
-    let parser = grammar::PropParser::new();
-    let ast = parser.parse(lexer)
-        .map_err(|e| format!("Parse error: {:?}", e))?;
+    let parser = grammar::ProgramParser::new();
+    let (definitions, ast) = parser.parse(lexer)
+        .map_err(|e| describe_parse_error(input, e))?;
+    
+    let mut warnings = Vec::new();
+    for definition in &definitions {
+        shadowing_warnings(&definition.body, &mut definition.params.clone(), &mut warnings);
+    }
+    shadowing_warnings(&ast, &mut Vec::new(), &mut warnings);
     
     // Stage 3: Macro expansion
-    let mut expander = MacroExpander::new();
-    expander.expand(ast)
+    let mut expander = MacroExpander::with_definitions(definitions)?;
+    let formula = expander.expand(ast)?;
+    
+    // Stage 4: Simplification
+    Ok(ParsedFormula { formula: simplify(&formula), warnings })
+}
+
+/// Warn about quantifiers that rebind a variable already bound around them
+///
+/// Only what the user wrote is checked, before macros add their own (fresh) variables.
+fn shadowing_warnings(prop: &Prop, bound: &mut Vec<String>, warnings: &mut Vec<String>) {
+    let logic = match prop {
+        Prop::Logic(logic) => logic,
+        Prop::Macro(_) => return,
+    };
+    match logic {
+        LogicProp::Quant(quant) => {
+            let (quantifier, var, body) = match quant {
+                QuantProp::AP(var, body) => ("AP", var, body),
+                QuantProp::EP(var, body) => ("EP", var, body),
+                QuantProp::AO(var, body) => ("AO", var, body),
+                QuantProp::EO(var, body) => ("EO", var, body),
+                QuantProp::AT(var, body) => ("AT", var, body),
+                QuantProp::ET(var, body) => ("ET", var, body),
+                QuantProp::APIn(var, _, body) => ("AP", var, body),
+                QuantProp::EPIn(var, _, body) => ("EP", var, body),
+            };
+            if bound.contains(var) {
+                warnings.push(format!("{} {} shadows an enclosing binding of {}", quantifier, var, var));
+            }
+            bound.push(var.clone());
+            shadowing_warnings(body, bound, warnings);
+            bound.pop();
+        }
+        LogicProp::Binary(
+            BinaryProp::And(left, right) | BinaryProp::Or(left, right) | BinaryProp::Implies(left, right)
+            | BinaryProp::Iff(left, right) | BinaryProp::Xor(left, right)
+        ) => {
+            shadowing_warnings(left, bound, warnings);
+            shadowing_warnings(right, bound, warnings);
+        }
+        LogicProp::Unary(UnaryProp::Not(inner)) => shadowing_warnings(inner, bound, warnings),
+        LogicProp::Atomic(_) | LogicProp::Const(_) => {}
+    }
+}
+
+/// Parse a formula that must be closed: every variable bound by a quantifier
+///
+/// This is what `find` and the like need, since nothing else could give the variables
+/// values; a free variable (often a case typo such as `x` for `X`) is reported as
+/// `"unbound variable x"` instead of making its atoms silently false.
+pub fn parse_closed_formula(input: &str) -> Result<ParsedFormula, String> {
+    let parsed = parse_formula_with_warnings(input)?;
+    check_bound(&parsed.formula, &Assignment::<crate::canon::Elem>::new())?;
+    Ok(parsed)
+}
+
+/// Render a parse error with its position in the input and a caret under the offending text
+fn describe_parse_error(input: &str, error: ParseError<usize, Token, LexError>) -> String {
+    let (start, end, message) = match error {
+        ParseError::InvalidToken { location } => (location, location, "invalid token".to_string()),
+        ParseError::User { error: LexError { offset } } => {
+            let found = input[offset..].chars().next().map_or(String::new(), |c| c.to_string());
+            let message = if input[offset..].starts_with("/*") {
+                "unterminated block comment".to_string()
+            } else {
+                format!("unrecognized input `{}`", found)
+            };
+            (offset, offset + found.len(), message)
+        }
+        ParseError::UnrecognizedEof { location, expected } => {
+            (location, location, format!("unexpected end of formula{}", expected_suffix(&expected)))
+        }
+        ParseError::UnrecognizedToken { token: (start, _, end), expected } => {
+            (start, end, format!("unexpected `{}`{}", &input[start..end], expected_suffix(&expected)))
+        }
+        ParseError::ExtraToken { token: (start, _, end) } => {
+            (start, end, format!("unexpected `{}`", &input[start..end]))
+        }
+    };
+
+    let line_start = input[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = input[start..].find('\n').map_or(input.len(), |i| start + i);
+    let line = &input[line_start..line_end];
+    let line_number = input[..start].matches('\n').count() + 1;
+    let column = input[line_start..start].chars().count() + 1;
+    let width = input[start..end.min(line_end)].chars().count().max(1);
+    format!(
+        "Parse error at line {}, column {}: {}\n  {}\n  {}{}",
+        line_number, column, message, line, " ".repeat(column - 1), "^".repeat(width)
+    )
+}
+
+/// ", expected ..." for a short list of expected tokens, and nothing for a long one
+fn expected_suffix(expected: &[String]) -> String {
+    if expected.is_empty() || expected.len() > 6 {
+        String::new()
+    } else {
+        format!(" (expected {})", expected.join(" or "))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::model_checker::{Atom, OpenExpr};
     
     // Core atomic propositions
     #[test]
@@ -175,6 +305,27 @@ mod tests {
         assert!(result.is_ok(), "Formula should parse successfully");
     }
 
+    #[test]
+    fn test_m03a_nary_inter() {
+        // Longer chains are the conjunction of consecutive pairs, extending the triples
+        assert_eq!(
+            parse_formula("A inter B inter C inter D").unwrap(),
+            parse_formula("(A inter B inter C) && C inter D").unwrap()
+        );
+        assert_eq!(
+            parse_formula("A inter B inter C inter D inter E").unwrap(),
+            parse_formula("(A inter B inter C) && C inter D && D inter E").unwrap()
+        );
+        assert_eq!(
+            parse_formula("p inter q inter r inter s").unwrap(),
+            parse_formula("(p inter q inter r) && r inter s").unwrap()
+        );
+        assert_eq!(
+            parse_formula("p inter q inter r inter s inter 1").unwrap(),
+            parse_formula("(p inter q inter r) && r inter s && s inter 1").unwrap()
+        );
+    }
+
     #[test]
     fn test_m03b_triple_inter_in_complex_expression() {
         // Test triple intersections (both point and open) within complex logical expressions
@@ -389,6 +540,68 @@ mod tests {
         assert!(result.is_err(), "Formula should fail to parse (unknown keyword)");
     }
     
+    #[test]
+    fn test_e09_empty_formula() {
+        assert_eq!(parse_formula(""), Err("empty formula".to_string()));
+        assert_eq!(parse_formula("  \t\n "), Err("empty formula".to_string()));
+        assert_eq!(parse_formula("// nothing here\n/* or here */"), Err("empty formula".to_string()));
+    }
+    
+    #[test]
+    fn test_e11_error_positions() {
+        let error = parse_formula("AP x x in X").unwrap_err();
+        assert!(error.starts_with("Parse error at line 1, column 6: unexpected `x`"), "{}", error);
+        assert!(error.ends_with("\n  AP x x in X\n       ^"), "{}", error);
+        
+        let error = parse_formula("AP x.\n  x in X &&\n  y ∈ Y)").unwrap_err();
+        assert!(error.starts_with("Parse error at line 3, column 8: unexpected `)`"), "{}", error);
+        
+        let error = parse_formula("AP x. /* x in X").unwrap_err();
+        assert!(error.contains("column 7: unterminated block comment"), "{}", error);
+    }
+    
+    #[test]
+    fn test_e12_unbound_variables() {
+        assert_eq!(parse_closed_formula("x in X"), Err("unbound variable X".to_string()));
+        assert_eq!(parse_closed_formula("AO X. x in X"), Err("unbound variable x".to_string()));
+        assert_eq!(parse_closed_formula("AP x. x in X"), Err("unbound variable X".to_string()));
+        assert!(parse_closed_formula("AP x. AO X. x in X").is_ok());
+        // A case typo: X is bound, x is not
+        assert_eq!(parse_closed_formula("AO X. nonempty X => (EP y. y in K x)"), Err("unbound variable x".to_string()));
+        // The scope of a quantifier ends with its parentheses
+        assert_eq!(parse_closed_formula("(EP x. regular x) && regular x"), Err("unbound variable x".to_string()));
+        // Literals, constants and macros need no binding
+        assert!(parse_closed_formula("1 in {1, 2} && regular_space && nonempty UNIV && regular 2").is_ok());
+        // parse_formula itself still accepts open formulas
+        assert!(parse_formula("x in X").is_ok());
+    }
+    
+    #[test]
+    fn test_shadowing_warnings() {
+        let warnings = |input: &str| parse_formula_with_warnings(input).unwrap().warnings;
+        assert_eq!(warnings("EP x. EP x. x in X"), vec!["EP x shadows an enclosing binding of x"]);
+        assert!(warnings("EP x. EP y. x in X && y in X").is_empty());
+        // Sibling scopes and macros' own variables are not shadowing
+        assert!(warnings("(EP x. regular x) && (AP x. regular_space)").is_empty());
+        assert_eq!(
+            warnings("AO X. nonempty X => (AO X. EP x in X. X inter K x)"),
+            vec!["AO X shadows an enclosing binding of X"]
+        );
+        assert_eq!(warnings("def f(p) := EP p. regular p; AP x. f(x)"), vec!["EP p shadows an enclosing binding of p"]);
+    }
+    
+    #[test]
+    fn test_e10_comments() {
+        assert!(parse_formula("AP x. // comment\n x in X").is_ok());
+        assert_eq!(
+            parse_formula("AP x. /* a **starred** comment */ x in X"),
+            parse_formula("AP x. x in X")
+        );
+        // Unterminated and nested block comments are lexer errors
+        assert!(parse_formula("AP x. /* x in X").is_err());
+        assert!(parse_formula("AP x. /* a /* b */ c */ x in X").is_err());
+    }
+    
     // Basic functionality tests
     #[test]
     fn test_parse_simple_atomic() {
@@ -414,6 +627,110 @@ mod tests {
         println!("{:?}", formula);
     }
     
+    #[test]
+    fn test_parse_point_literals() {
+        assert_eq!(
+            parse_formula("1 in X").unwrap(),
+            Formula::Atom(Atom::PointInOpen("1".to_string(), OpenExpr::Var("X".to_string())))
+        );
+        assert!(parse_formula("regular 2 && 1 in K 2").is_ok());
+        // Literals cannot be bound
+        assert!(parse_formula("AP 1. 1 in X").is_err());
+    }
+    
+    #[test]
+    fn test_parse_set_literals() {
+        assert_eq!(
+            parse_formula("x in {1, 3}").unwrap(),
+            Formula::Atom(Atom::PointInOpen("x".to_string(), OpenExpr::Lit(vec![1, 3])))
+        );
+        assert!(parse_formula("nonempty ({} union {2,})").is_ok());
+        assert!(parse_formula("x in {1, y}").is_err());
+    }
+    
+    #[test]
+    fn test_parse_unicode_symbols() {
+        assert_eq!(
+            parse_formula("∀x. ∃X. (x ∈ X ∧ nonempty X) → ¬(X = Y) ∨ X ≬ Y").unwrap(),
+            parse_formula("AP x. EO X. (x in X && nonempty X) => !(X = Y) || X inter Y").unwrap()
+        );
+        assert_eq!(
+            parse_formula("∃p. ∀Y. p ≠ q ⇔ X ∩ Y ⊆ X ∪ K p").unwrap(),
+            parse_formula("EP p. AO Y. p != q <=> X cap Y subseteq X union K p").unwrap()
+        );
+        assert_ne!(parse_formula("∀x. x ∈ X").unwrap(), parse_formula("∃x. x ∈ X").unwrap());
+    }
+    
+    #[test]
+    fn test_parse_truth_constants() {
+        assert_eq!(parse_formula("true").unwrap(), Formula::True);
+        assert_eq!(parse_formula("!false").unwrap(), Formula::True);
+        assert_eq!(parse_formula("true => nonempty X").unwrap(), parse_formula("nonempty X").unwrap());
+        assert_eq!(parse_formula("false => x in X").unwrap(), Formula::True);
+        // Kept under the quantifier, which is vacuously true when there are no points
+        assert_eq!(
+            parse_formula("AP x. false && x in X").unwrap(),
+            Formula::ForAllPoints("x".to_string(), Box::new(Formula::False))
+        );
+    }
+    
+    #[test]
+    fn test_parse_bounded_quantifiers() {
+        assert_eq!(
+            parse_formula("AO X. AP x in X. EP y in K x. y != x").unwrap(),
+            parse_formula("AO X. AP x. (x in X) => (EP y. (y in K x) && y != x)").unwrap()
+        );
+        assert_eq!(
+            parse_formula("EP x in X union IC Y. regular x").unwrap(),
+            parse_formula("EP x. (x in X union IC Y) && regular x").unwrap()
+        );
+        assert_eq!(
+            parse_formula("∀x ∈ {1, 2}. ∃y ∈ X. x = y").unwrap(),
+            parse_formula("AP x. x in {1, 2} => (EP y. y in X && x = y)").unwrap()
+        );
+    }
+    
+    #[test]
+    fn test_parse_definitions() {
+        // Parameters are replaced by the arguments, and bound variables renamed apart
+        assert_eq!(
+            parse_formula("def inside(p, X) := p in X; AO Y. inside(1, Y) && inside(q, IC Y)").unwrap(),
+            parse_formula("AO Y. 1 in Y && q in IC Y").unwrap()
+        );
+        assert_eq!(
+            parse_formula("def has_other(p) := EP y. y != p; AP y. has_other(y)").unwrap(),
+            parse_formula("AP y. EP y_0. y_0 != y").unwrap()
+        );
+        // Definitions may use earlier and later ones, and span lines
+        assert!(parse_formula("def a(p) := b(p) || regular p;\ndef b(q) := EO X. q in X;\nAP x. a(x)").is_ok());
+    }
+    
+    #[test]
+    fn test_definition_errors() {
+        let error = |input: &str| parse_formula(input).unwrap_err();
+        assert_eq!(error("AP x. f(x)"), "Unknown definition 'f'");
+        assert_eq!(error("def f(p) := g(p); def g(q) := f(q); AP x. f(x)"), "Definition 'f' is recursive (f -> g -> f)");
+        assert_eq!(error("def f(p) := f(p); AP x. f(x)"), "Definition 'f' is recursive (f -> f)");
+        assert_eq!(error("def f(p, X) := p in X; AP x. f(x)"), "Definition 'f' takes 2 argument(s) but is given 1");
+        assert_eq!(error("def f(X) := nonempty X; AP x. f(x)"), "Argument 1 of 'f' should be an open (parameter 'X')");
+        assert_eq!(error("def f(p) := p in X; AP x. f(x)"), "Definition 'f' uses 'X', which is neither a parameter nor bound in it");
+        assert_eq!(error("def f(p) := regular p; def f(p) := regular p; f(1)"), "Definition 'f' is given twice");
+        assert_eq!(error("def f(p, p) := p = p; f(1, 2)"), "Definition 'f' has two parameters named 'p'");
+    }
+    
+    #[test]
+    fn test_parse_xor_precedence() {
+        // ^^ binds like <=>: looser than && and ||, tighter than =>
+        assert_eq!(
+            parse_formula("nonempty X ^^ x in X && x in Y <=> X = Y").unwrap(),
+            parse_formula("(nonempty X ^^ (x in X && x in Y)) <=> X = Y").unwrap()
+        );
+        assert_eq!(
+            parse_formula("x in X => nonempty X ⊕ nonempty Y").unwrap(),
+            parse_formula("x in X => (nonempty X xor nonempty Y)").unwrap()
+        );
+    }
+    
     #[test]
     fn test_parse_point_intersection() {
         let formula = parse_formula("p inter q").unwrap();
@@ -485,4 +802,49 @@ mod tests {
         let formula = parse_formula("p in (IC X)").unwrap();
         println!("{:?}", formula);
     }
+    
+    #[test]
+    fn test_parse_closure() {
+        let formula = parse_formula("p in Cl (IC X)").unwrap();
+        assert_eq!(
+            formula,
+            Formula::Atom(Atom::PointInOpen(
+                "p".to_string(),
+                OpenExpr::Closure(Box::new(OpenExpr::InteriorComplement(Box::new(OpenExpr::Var("X".to_string()))))),
+            ))
+        );
+    }
+    
+    #[test]
+    fn test_parse_union() {
+        let var = |name: &str| Box::new(OpenExpr::Var(name.to_string()));
+        // Left-associative, with IC binding tighter than union
+        let formula = parse_formula("nonempty IC X union Y union Z").unwrap();
+        assert_eq!(
+            formula,
+            Formula::Atom(Atom::OpenNonempty(OpenExpr::Union(
+                Box::new(OpenExpr::Union(Box::new(OpenExpr::InteriorComplement(var("X"))), var("Y"))),
+                var("Z"),
+            )))
+        );
+        assert!(parse_formula("X union Y inter Z").is_ok());
+        assert!(parse_formula("x in (X union Y)").is_ok());
+    }
+    
+    #[test]
+    fn test_parse_cap() {
+        let var = |name: &str| Box::new(OpenExpr::Var(name.to_string()));
+        // cap binds tighter than union
+        let formula = parse_formula("nonempty X union Y cap Z").unwrap();
+        assert_eq!(
+            formula,
+            Formula::Atom(Atom::OpenNonempty(OpenExpr::Union(var("X"), Box::new(OpenExpr::Inter(var("Y"), var("Z"))))))
+        );
+        // The set operation and the intersection predicate combine without ambiguity
+        let formula = parse_formula("X cap Y inter Z").unwrap();
+        assert_eq!(
+            formula,
+            Formula::Atom(Atom::OpenIntersection(OpenExpr::Inter(var("X"), var("Y")), OpenExpr::Var("Z".to_string())))
+        );
+    }
 }
\ No newline at end of file
@@ -1,11 +1,15 @@
 //! Search algorithm for semiframes and semitopologies.
 
-use crate::canon::{Family, canonicalize, canonical_delete, family_to_str};
-use crate::model_checker::{ModelChecker, Formula};
-use std::collections::{HashMap, BTreeSet};
+use crate::canon::{Elem, Family, SharedCanonCache, full_set, canonicalize_shared, is_union_closed, canonical_delete_shared, canonicalize_once, family_to_json, family_to_str, parse_family_json, parse_family_line};
+use crate::model_checker::{ModelChecker, Formula, OpenDomain, SPACE_PREDICATES};
+use crate::parser::parse_formula;
+use std::collections::{HashSet, BTreeMap, BTreeSet};
 use std::fs::File;
-use std::io::{Write as IoWrite, BufWriter};
+use std::io::{Write as IoWrite, Read, BufRead, BufReader, BufWriter};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use rayon;
+use rayon::prelude::*;
 use std::sync::{
     atomic::{AtomicBool, AtomicUsize, Ordering},
     Arc,
@@ -13,24 +17,426 @@ use std::sync::{
 use crossbeam_channel::{unbounded, Sender};
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Config {
     pub sizes: Vec<usize>,
     pub limit: usize,
     pub output_pattern: String,
     pub search_semiframes: bool,
+    /// Only emit families that are also closed under intersection, i.e. topologies
+    pub topologies: bool,
+    #[cfg_attr(feature = "serde", serde(with = "crate::canon::optional_family"))]
     pub starting_family: Option<Family>,
     pub log_interval: usize,
     pub num_threads: usize,
+    /// Capacity of the canonical form cache shared by the search threads (0 disables it)
+    pub cache_size: usize,
+    pub largest_open_histogram: bool,
+    /// Tally found families by their number of opens
+    pub histogram: bool,
+    pub minimal: bool,
+    pub sort_output: bool,
+    /// Canonical forms (including the empty set) of families not to emit again
+    ///
+    /// Not serialized: the corpus is loaded from its file on each run.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub exclude: Option<HashSet<Family>>,
+    /// What open quantifiers in the formula range over
+    pub open_domain: OpenDomain,
+    /// Save the pending search stack here after every batch (use {n} for the size)
+    pub checkpoint: Option<String>,
+    /// Continue from a checkpoint saved by an earlier run (use {n} for the size)
+    pub resume: Option<String>,
+    /// Number of pending families expanded between checkpoints
+    pub batch_size: usize,
+    /// Keep at most this many pending families in memory, spilling the rest to disk
+    pub max_stack: Option<usize>,
+    /// How families found are written
+    pub format: OutputFormat,
+    /// Order in which the search tree is traversed
+    pub strategy: SearchStrategy,
+    /// Write each family at most once up to relabelling, counting the repeats skipped
+    pub dedup: bool,
+    /// Only emit families satisfying this space predicate (see `parse_property`)
+    pub property: Option<Formula>,
+    /// Stop searching each size after this long, keeping the families found so far
+    pub per_size_timeout: Option<Duration>,
+    /// Write a uniform sample of this many of the families found instead of all of them,
+    /// drawn with the given seed
+    pub sample: Option<(usize, u64)>,
+    /// Add the empty set to each family found, both when checking the formula, minimality
+    /// and exclusion and when writing it. Distinguishedness is unaffected either way, since
+    /// the empty set separates no two points
+    pub include_empty: bool,
+}
+
+/// Parse the name of one of `SPACE_PREDICATES` into its formula
+pub fn parse_property(name: &str) -> Result<Formula, String> {
+    if !SPACE_PREDICATES.contains(&name) {
+        return Err(format!("Unknown property '{}' (expected one of: {})", name, SPACE_PREDICATES.join(", ")));
+    }
+    parse_formula(name)
+}
+
+/// Traversal order of the search tree, in which each child has one more open than
+/// its parent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum SearchStrategy {
+    /// Depth first, from an explicit worklist shared out across the Rayon pool
+    #[default]
+    Dfs,
+    /// Breadth first, one level (number of opens) at a time from an explicit queue
+    Bfs,
+}
+
+impl FromStr for SearchStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dfs" => Ok(SearchStrategy::Dfs),
+            "bfs" => Ok(SearchStrategy::Bfs),
+            _ => Err(format!("Unknown search strategy '{}' (expected 'dfs' or 'bfs')", s)),
+        }
+    }
+}
+
+/// Line format of the families a search writes
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum OutputFormat {
+    /// Brace notation, as produced by `family_to_str`
+    #[default]
+    Text,
+    /// One JSON object per line, as produced by `family_to_json`
+    Json,
+    /// A header, then one row per family: the family in brace notation, its number of
+    /// opens, and whether each named closed formula holds in it (opens ranging over the
+    /// family)
+    Csv(Vec<(String, Formula)>),
+}
+
+impl OutputFormat {
+    /// The line written before any family, if the format has one
+    pub fn header(&self) -> Option<String> {
+        match self {
+            OutputFormat::Csv(columns) => {
+                let names: Vec<&str> = columns.iter().map(|(name, _)| name.as_str()).collect();
+                Some(["family", "opens"].into_iter().chain(names).collect::<Vec<_>>().join(","))
+            }
+            _ => None,
+        }
+    }
+
+    /// Render a family as one output line
+    pub fn render(&self, fam: &Family, n: usize) -> String {
+        match self {
+            OutputFormat::Text => family_to_str(fam, n),
+            OutputFormat::Json => family_to_json(fam, n),
+            OutputFormat::Csv(columns) => {
+                let mut checker = ModelChecker::new(n, fam.clone());
+                let mut fields = vec![format!("\"{}\"", family_to_str(fam, n)), fam.len().to_string()];
+                fields.extend(columns.iter().map(|(_, formula)| checker.check(formula).satisfied.to_string()));
+                fields.join(",")
+            }
+        }
+    }
+
+    /// Parse a line written by `render`
+    pub fn parse(&self, line: &str, n: usize) -> Result<Family, String> {
+        match self {
+            OutputFormat::Text => parse_family_line(line, Some(n)).map(|(fam, _)| fam),
+            OutputFormat::Json => parse_family_json(line).map(|(fam, _)| fam),
+            OutputFormat::Csv(_) => {
+                let quoted = line.strip_prefix('"').and_then(|rest| rest.split_once('"'))
+                    .ok_or_else(|| format!("CSV row must start with a quoted family: {}", line))?;
+                parse_family_line(quoted.0, Some(n)).map(|(fam, _)| fam)
+            }
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    /// Parses the format name; CSV columns are added separately
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv(Vec::new())),
+            _ => Err(format!("Unknown output format '{}' (expected 'text', 'json' or 'csv')", s)),
+        }
+    }
+}
+
+impl Config {
+    /// When a search for one size starting now must stop
+    fn deadline(&self) -> Option<Instant> {
+        self.per_size_timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    /// Name of the structures being searched for, as used in progress messages
+    pub fn search_type(&self) -> &'static str {
+        match (self.topologies, self.search_semiframes) {
+            (true, true) => "T0 topologies",
+            (true, false) => "topologies",
+            (false, true) => "semiframes",
+            (false, false) => "semitopologies",
+        }
+    }
+}
+
+/// Optional statistics tallied over the families written by a search
+#[derive(Debug, Default)]
+pub struct SearchStats {
+    /// Number of families keyed by the cardinality of their largest open
+    pub largest_open: Option<BTreeMap<u32, usize>>,
+    /// Number of families keyed by their number of opens
+    pub opens: Option<BTreeMap<usize, usize>>,
+    /// Canonical forms of the families written so far, when deduplicating
+    seen: Option<HashSet<Family>>,
+    /// Number of families not written because an isomorphic one already was
+    pub duplicates: usize,
+    /// Whether the search stopped at the per-size timeout, so the families are partial
+    pub timed_out: bool,
+    /// Number of families of the search tree visited
+    pub explored: usize,
+}
+
+impl SearchStats {
+    fn new(config: &Config) -> Self {
+        Self {
+            largest_open: config.largest_open_histogram.then(BTreeMap::new),
+            opens: config.histogram.then(BTreeMap::new),
+            seen: config.dedup.then(HashSet::new),
+            duplicates: 0,
+            timed_out: false,
+            explored: 0,
+        }
+    }
+
+    /// Whether a family should be written: always, unless deduplicating and an
+    /// isomorphic family has already been admitted
+    fn admit(&mut self, fam: &Family, n: usize) -> bool {
+        let seen = match self.seen {
+            Some(ref mut seen) => seen,
+            None => return true,
+        };
+        if seen.insert(canonicalize_once(fam, n)) {
+            true
+        } else {
+            self.duplicates += 1;
+            false
+        }
+    }
+
+    /// Tally a family exactly as it is written (i.e. including the empty set)
+    fn record(&mut self, fam: &Family) {
+        if let Some(ref mut hist) = self.largest_open {
+            let largest = fam.iter().map(|m| m.count_ones()).max().unwrap_or(0);
+            *hist.entry(largest).or_insert(0) += 1;
+        }
+        if let Some(ref mut hist) = self.opens {
+            *hist.entry(fam.len()).or_insert(0) += 1;
+        }
+    }
+}
+
+/// Outcome of searching a single size, as recorded in a JSON summary
+#[derive(Debug, Clone)]
+pub struct SizeRun {
+    pub n: usize,
+    pub found: usize,
+    pub secs: f64,
+}
+
+/// Write a u64 in the little-endian layout shared by checkpoints and spilled stack chunks
+fn write_u64(w: &mut impl IoWrite, value: u64) -> std::io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn read_u64(r: &mut impl Read) -> std::io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Write a family as its length followed by its members
+fn write_family(w: &mut impl IoWrite, fam: &Family) -> std::io::Result<()> {
+    write_u64(w, fam.len() as u64)?;
+    fam.iter().try_for_each(|&m| write_u64(w, m))
+}
+
+fn read_family(r: &mut impl Read) -> std::io::Result<Family> {
+    let len = read_u64(r)?;
+    (0..len).map(|_| read_u64(r)).collect()
+}
+
+/// Distinguishes the spill files of stacks alive at the same time in one process
+static NEXT_STACK_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Stack of families still to be explored, keeping at most `max_in_memory` of them in
+/// RAM (if given)
+///
+/// When the stack grows past the bound, its older half is written to a temporary file;
+/// spilled chunks are read back, newest first, once the in-memory part is empty, so pops
+/// come out in exactly the order of an unbounded stack.
+pub struct PendingStack {
+    top: Vec<Family>,
+    max_in_memory: Option<usize>,
+    /// Spilled chunks as (path, number of families), oldest first
+    chunks: Vec<(std::path::PathBuf, usize)>,
+    id: usize,
+    next_chunk: usize,
+    /// Number of chunks written to disk
+    pub spills: usize,
+    /// Number of chunks read back from disk
+    pub reloads: usize,
+}
+
+impl PendingStack {
+    pub fn new(max_in_memory: Option<usize>) -> Self {
+        Self {
+            top: Vec::new(),
+            max_in_memory: max_in_memory.map(|max| max.max(1)),
+            chunks: Vec::new(),
+            id: NEXT_STACK_ID.fetch_add(1, Ordering::Relaxed),
+            next_chunk: 0,
+            spills: 0,
+            reloads: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.top.len() + self.chunks.iter().map(|&(_, len)| len).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.top.is_empty() && self.chunks.is_empty()
+    }
+
+    pub fn push(&mut self, fam: Family) -> std::io::Result<()> {
+        self.top.push(fam);
+        match self.max_in_memory {
+            Some(max) if self.top.len() > max => self.spill(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Pop up to `count` of the most recently pushed families
+    pub fn pop_batch(&mut self, count: usize) -> std::io::Result<Vec<Family>> {
+        if self.top.is_empty() {
+            self.reload()?;
+        }
+        Ok(self.top.split_off(self.top.len().saturating_sub(count)))
+    }
+
+    fn spill(&mut self) -> std::io::Result<()> {
+        let older: Vec<Family> = self.top.drain(..self.top.len().div_ceil(2)).collect();
+        let path = std::env::temp_dir().join(format!(
+            "semiframes_stack_{}_{}_{}.bin", std::process::id(), self.id, self.next_chunk));
+        self.next_chunk += 1;
+        let mut w = BufWriter::new(File::create(&path)?);
+        older.iter().try_for_each(|fam| write_family(&mut w, fam))?;
+        w.flush()?;
+        self.chunks.push((path, older.len()));
+        self.spills += 1;
+        Ok(())
+    }
+
+    fn reload(&mut self) -> std::io::Result<()> {
+        if let Some((path, len)) = self.chunks.pop() {
+            let mut r = BufReader::new(File::open(&path)?);
+            self.top = (0..len).map(|_| read_family(&mut r)).collect::<std::io::Result<_>>()?;
+            std::fs::remove_file(&path)?;
+            self.reloads += 1;
+        }
+        Ok(())
+    }
+
+    /// Write every pending family, oldest first, reading spilled chunks one at a time
+    fn write_all(&self, w: &mut impl IoWrite) -> std::io::Result<()> {
+        for (path, len) in &self.chunks {
+            let mut r = BufReader::new(File::open(path)?);
+            for _ in 0..*len {
+                write_family(w, &read_family(&mut r)?)?;
+            }
+        }
+        self.top.iter().try_for_each(|fam| write_family(w, fam))
+    }
+}
+
+impl Drop for PendingStack {
+    fn drop(&mut self) {
+        for (path, _) in &self.chunks {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
+
+/// State of an interrupted search: the counters so far and the families whose
+/// subtrees are still to be explored
+pub struct Checkpoint {
+    pub n: usize,
+    pub explored: usize,
+    pub found: usize,
+    pub pending: PendingStack,
+}
+
+impl Checkpoint {
+    /// Write the checkpoint as little-endian u64s: n, explored, found, the number of
+    /// pending families, then each family as its length followed by its members
+    ///
+    /// The file is written beside `path` and renamed over it, so a crash mid-write
+    /// leaves the previous checkpoint intact.
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let tmp = format!("{}.tmp", path);
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        for value in [self.n, self.explored, self.found, self.pending.len()] {
+            write_u64(&mut w, value as u64)?;
+        }
+        self.pending.write_all(&mut w)?;
+        w.flush()?;
+        drop(w);
+        std::fs::rename(&tmp, path)
+    }
+
+    /// Read a checkpoint written by `save`, keeping at most `max_in_memory` of its
+    /// pending families in RAM
+    pub fn load(path: &str, max_in_memory: Option<usize>) -> std::io::Result<Self> {
+        let mut r = BufReader::new(File::open(path)?);
+        let n = read_u64(&mut r)? as usize;
+        let explored = read_u64(&mut r)? as usize;
+        let found = read_u64(&mut r)? as usize;
+        let mut pending = PendingStack::new(max_in_memory);
+        for _ in 0..read_u64(&mut r)? {
+            pending.push(read_family(&mut r)?)?;
+        }
+        Ok(Self { n, explored, found, pending })
+    }
+}
+
+/// Render per-size results as `{"runs": [{"n": .., "found": .., "secs": ..}, ...], "total_secs": ..}`
+pub fn summary_json(runs: &[SizeRun], total_secs: f64) -> String {
+    let runs = runs.iter()
+        .map(|r| format!("{{\"n\": {}, \"found\": {}, \"secs\": {:.6}}}", r.n, r.found, r.secs))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{{\"runs\": [{}], \"total_secs\": {:.6}}}", runs, total_secs)
 }
 
 /// Checks if element p is distinguished in the given family
 pub fn is_distinguished(family: &Family, p: usize, n: usize) -> bool {
-    let p_bit = 1u32 << (p - 1);
+    let p_bit: Elem = 1 << (p - 1);
     for q in 1..=n {
         if p == q {
             continue;
         }
-        let q_bit = 1u32 << (q - 1);
+        let q_bit: Elem = 1 << (q - 1);
         let is_separated = family.iter().any(|&s_int| {
             ((s_int & p_bit) != 0) != ((s_int & q_bit) != 0)
         });
@@ -46,76 +452,364 @@ pub fn has_all_distinguished(family: &Family, n: usize) -> bool {
     (1..=n).all(|p| is_distinguished(family, p, n))
 }
 
+/// Checks that the intersection of any two members is again a member (or empty, since
+/// the empty set is only added to the emitted family)
+fn is_intersection_closed(family: &Family) -> bool {
+    family.iter().all(|&a| family.iter().all(|&b| a & b == 0 || family.contains(&(a & b))))
+}
+
+/// Checks if a satisfying family is minimal: removing any open other than the forced
+/// empty and full sets either leaves the search space or breaks the formula
+pub fn is_minimal(fam: &Family, n: usize, search_semiframes: bool, topologies: bool, formula: &Formula, open_domain: OpenDomain) -> bool {
+    let full_set = full_set(n);
+    fam.iter().filter(|&&o| o != 0 && o != full_set).all(|&o| {
+        let mut sub = fam.clone();
+        sub.remove(&o);
+        if !is_union_closed(&sub)
+            || (search_semiframes && !has_all_distinguished(&sub, n))
+            || (topologies && !is_intersection_closed(&sub))
+        {
+            return true;
+        }
+        let mut checker = ModelChecker::new(n, sub).with_open_domain(open_domain);
+        !checker.check(formula).satisfied
+    })
+}
+
+/// Rewrites an output file with its lines sorted, so runs can be diffed regardless of
+/// the order in which the (possibly parallel) search emitted families; a header line,
+/// if the file has one, stays first
+pub fn sort_output_file(path: &str, has_header: bool) -> std::io::Result<()> {
+    let mut lines = BufReader::new(File::open(path)?)
+        .lines()
+        .collect::<std::io::Result<Vec<String>>>()?;
+    let skip = usize::from(has_header).min(lines.len());
+    lines[skip..].sort();
+
+    let mut w = BufWriter::new(File::create(path)?);
+    for line in lines {
+        writeln!(w, "{}", line)?;
+    }
+    w.flush()
+}
+
+/// Print a status line of a search, to stderr instead when the families found are
+/// streamed to stdout
+#[macro_export]
+macro_rules! status {
+    ($to_stderr:expr, $($arg:tt)*) => {
+        if $to_stderr { eprintln!($($arg)*) } else { println!($($arg)*) }
+    };
+}
+
+/// Thread pool of `config.num_threads` threads for one search
+fn build_pool(config: &Config) -> Result<rayon::ThreadPool, String> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(config.num_threads)
+        .build()
+        .map_err(|e| format!("Failed to initialize thread pool: {}", e))
+}
+
+/// Root of the search for size n: the configured starting family, or the full set alone
+fn start_family(config: &Config, n: usize) -> Family {
+    match config.starting_family {
+        Some(ref custom_start) => custom_start.clone(),
+        None => [full_set(n)].into_iter().collect(),
+    }
+}
+
 /// Main function to generate all families for given n with configuration
-pub fn gen_fam(config: &Config, n: usize) -> Result<(usize, String), Box<dyn std::error::Error>> {
+///
+/// An output pattern of "-" streams the families to stdout, with status lines on stderr.
+pub fn gen_fam(config: &Config, n: usize) -> Result<(usize, String, SearchStats), Box<dyn std::error::Error>> {
     let outfile_path = config.output_pattern.replace("{n}", &n.to_string());
-    let search_type = if config.search_semiframes { "semiframes" } else { "semitopologies" };
+    let search_type = config.search_type();
+    let streaming = outfile_path == "-";
     
-    println!("--- Generating {} for n={} (threads: {}). Writing to {} ---", 
-             search_type, n, config.num_threads, outfile_path);
+    status!(streaming, "--- Generating {} for n={} (threads: {}). Writing to {} ---", 
+            search_type, n, config.num_threads, if streaming { "stdout" } else { &outfile_path });
     
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(config.num_threads)
-        .build_global()
-        .map_err(|e| format!("Failed to initialize thread pool: {}", e))?;
+    let pool = build_pool(config)?;
     
     if n == 0 {
-        return Ok((0, outfile_path));
+        return Ok((0, outfile_path, SearchStats::new(config)));
+    }
+
+    let start_family = start_family(config, n);
+    
+    status!(streaming, "  Starting family: {}", family_to_str(&start_family, n));
+
+    if config.checkpoint.is_some() || config.resume.is_some() || config.max_stack.is_some() {
+        if config.sample.is_some() {
+            return Err("--sample writes its families once the search completes, so it does not support checkpoints or --max-stack".into());
+        }
+        if streaming {
+            return Err("Checkpoints and --max-stack need an output file, not stdout".into());
+        }
+        if config.strategy != SearchStrategy::Dfs {
+            return Err("Checkpoints and --max-stack keep their own stack and only support the dfs strategy".into());
+        }
+        return gen_fam_batched(config, n, start_family, outfile_path, &pool);
     }
 
-    let start_family = if let Some(ref custom_start) = config.starting_family {
-        custom_start.clone()
+    let out: Box<dyn IoWrite + Send> = if streaming {
+        Box::new(std::io::stdout())
     } else {
-        let full_set = (1u32 << n) - 1;
-        let mut family = BTreeSet::new();
-        family.insert(full_set);
-        family
+        Box::new(File::create(&outfile_path)?)
     };
-    
-    println!("  Starting family: {}", family_to_str(&start_family, n));
+    let (found, excluded, stats) = search_into(config, n, start_family, out, streaming, &pool, config.property.as_ref(), false)?;
+    let found = found - stats.duplicates;
 
+    if config.sort_output && !streaming {
+        sort_output_file(&outfile_path, config.format.header().is_some())?;
+    }
+    if config.exclude.is_some() {
+        status!(streaming, "\n  Skipped {} {} already in the excluded corpus.", excluded, search_type);
+    }
+    if config.dedup {
+        status!(streaming, "\n  Suppressed {} duplicate {}.", stats.duplicates, search_type);
+    }
+    if let Some((k, _)) = config.sample {
+        status!(streaming, "\n  Wrote a random sample of {} of the {} {} found.", found.min(k), found, search_type);
+    }
+    if stats.timed_out {
+        status!(streaming, "\n  Search stopped: per-size timeout reached, results for n={} are partial.", n);
+    }
+    status!(streaming, "\n  Done. Found {} {}.", found, search_type);
+    Ok((found, outfile_path, stats))
+}
+
+/// Count the families for given n: the search of `gen_fam`, writing to a sink
+pub fn count_fam(config: &Config, n: usize) -> Result<usize, Box<dyn std::error::Error>> {
+    let (found, _) = count_into_sink(config, n, config.property.as_ref(), false)?;
+    Ok(found)
+}
+
+/// Count the families satisfying a formula without printing any of them; returns the
+/// number found and the number explored
+pub fn count_fam_with_formula(config: &Config, n: usize, formula: &Formula) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    let (found, stats) = count_into_sink(config, n, Some(formula), config.minimal)?;
+    Ok((found, stats.explored))
+}
+
+/// Run `search_into` for size n with a writer that discards every family
+fn count_into_sink(config: &Config, n: usize, formula: Option<&Formula>, minimal: bool) -> Result<(usize, SearchStats), Box<dyn std::error::Error>> {
+    let pool = build_pool(config)?;
+    if n == 0 {
+        return Ok((0, SearchStats::new(config)));
+    }
+    let (found, _, stats) = search_into(config, n, start_family(config, n), Box::new(std::io::sink()), false, &pool, formula, minimal)?;
+    Ok((found - stats.duplicates, stats))
+}
+
+/// Run the search below `start_family`, writing each family found that satisfies
+/// `formula` (if any, and minimal for it if `minimal`) to `out` as one line; returns the
+/// number found, the number skipped as excluded, and the statistics
+#[allow(clippy::too_many_arguments)]
+fn search_into(
+    config: &Config,
+    n: usize,
+    start_family: Family,
+    out: Box<dyn IoWrite + Send>,
+    progress_to_stderr: bool,
+    pool: &rayon::ThreadPool,
+    formula: Option<&Formula>,
+    minimal: bool,
+) -> Result<(usize, usize, SearchStats), Box<dyn std::error::Error>> {
     let (tx, rx) = unbounded::<Family>();
     let shared = Arc::new(SharedState {
-        n,
-        search_semiframes: config.search_semiframes,
-        limit: config.limit,
-        log_interval: config.log_interval,
-        found: AtomicUsize::new(0),
-        explored: AtomicUsize::new(0),
-        stop: AtomicBool::new(false),
-        out_tx: tx,
-        formula: None,
+        progress_to_stderr,
+        ..SharedState::from_config(config, n, tx, formula, minimal)
     });
 
     let writer_handle = {
-        let path = outfile_path.clone();
-        std::thread::spawn(move || -> std::io::Result<()> {
-            let mut w = BufWriter::new(File::create(path)?);
+        let mut stats = SearchStats::new(config);
+        let format = config.format.clone();
+        let mut reservoir = config.sample.map(|(k, seed)| Reservoir::new(k, seed));
+        std::thread::spawn(move || -> std::io::Result<SearchStats> {
+            let mut w = BufWriter::new(out);
+            if let Some(header) = format.header() {
+                writeln!(w, "{}", header)?;
+            }
             for fam in rx {
-                writeln!(w, "{}", family_to_str(&fam, n))?;
+                if !stats.admit(&fam, n) {
+                    continue;
+                }
+                match reservoir {
+                    Some(ref mut reservoir) => reservoir.offer(fam),
+                    None => {
+                        stats.record(&fam);
+                        writeln!(w, "{}", format.render(&fam, n))?;
+                    }
+                }
+            }
+            for fam in reservoir.map(Reservoir::into_sample).unwrap_or_default() {
+                stats.record(&fam);
+                writeln!(w, "{}", format.render(&fam, n))?;
             }
-            w.flush()
+            w.flush()?;
+            Ok(stats)
         })
     };
 
-    dfs(start_family, shared.clone());
+    pool.install(|| explore(start_family, shared.clone(), config.strategy));
 
     // read the counters *before* shutting the channel
     let found = shared.found.load(Ordering::Relaxed);
+    let excluded = shared.excluded.load(Ordering::Relaxed);
+    let timed_out = shared.timed_out.load(Ordering::Relaxed);
+    let explored = shared.explored.load(Ordering::Relaxed);
 
     // close the channel: this drops the last Sender
     drop(shared);
 
     // writer thread can now finish
-    writer_handle.join().unwrap()?;
-    println!("\n  Done. Found {} {}.", found, search_type);
-    Ok((found, outfile_path))
+    let mut stats = writer_handle.join().unwrap()?;
+    stats.timed_out = timed_out;
+    stats.explored = explored;
+    Ok((found, excluded, stats))
+}
+
+/// As `gen_fam`, but expanding an explicit stack of pending families in batches of
+/// `config.batch_size`, saving a `Checkpoint` after each batch once its families are on
+/// disk, and keeping at most `config.max_stack` pending families in memory
+///
+/// When resuming, the output file is cut back to the families the checkpoint counts,
+/// dropping any written after it was saved, and the search continues from its stack.
+fn gen_fam_batched(
+    config: &Config,
+    n: usize,
+    start_family: Family,
+    outfile_path: String,
+    pool: &rayon::ThreadPool,
+) -> Result<(usize, String, SearchStats), Box<dyn std::error::Error>> {
+    let search_type = config.search_type();
+    let deadline = config.deadline();
+    let checkpoint_path = config.checkpoint.as_ref().or(config.resume.as_ref())
+        .map(|pattern| pattern.replace("{n}", &n.to_string()));
+    let written = |found: usize| if config.limit == 0 { found } else { found.min(config.limit) };
+
+    let mut stats = SearchStats::new(config);
+    let mut state = match config.resume {
+        Some(ref pattern) => {
+            let path = pattern.replace("{n}", &n.to_string());
+            let state = Checkpoint::load(&path, config.max_stack)
+                .map_err(|e| format!("Error loading checkpoint {}: {}", path, e))?;
+            if state.n != n {
+                return Err(format!("Checkpoint {} is for n={}, not n={}", path, state.n, n).into());
+            }
+            println!("  Resuming from {}: {} explored, {} found, {} pending",
+                     path, state.explored, state.found, state.pending.len());
+            state
+        }
+        None => {
+            let mut pending = PendingStack::new(config.max_stack);
+            pending.push(start_family)?;
+            Checkpoint { n, explored: 0, found: 0, pending }
+        }
+    };
+
+    // Keep exactly the families counted by the checkpoint
+    let kept = if config.resume.is_some() && written(state.found) > 0 {
+        std::fs::read_to_string(&outfile_path)?
+    } else {
+        String::new()
+    };
+    let header = config.format.header();
+    let kept: Vec<&str> = kept.lines().skip(usize::from(header.is_some())).take(written(state.found)).collect();
+    if kept.len() < written(state.found) {
+        return Err(format!("{} holds {} families but the checkpoint counts {}",
+                           outfile_path, kept.len(), written(state.found)).into());
+    }
+    let mut w = BufWriter::new(File::create(&outfile_path)?);
+    if let Some(header) = header {
+        writeln!(w, "{}", header)?;
+    }
+    for line in kept {
+        let fam = config.format.parse(line, n)?;
+        stats.admit(&fam, n);
+        stats.record(&fam);
+        writeln!(w, "{}", line)?;
+    }
+
+    let mut excluded = 0;
+    let mut stopped = false;
+    // One cache for all the batches, which each get fresh counters
+    let canon_cache = Arc::new(SharedCanonCache::new(config.cache_size));
+    while !state.pending.is_empty() && !stopped {
+        let batch = state.pending.pop_batch(config.batch_size.max(1))?;
+
+        let (tx, rx) = unbounded::<Family>();
+        let shared = SharedState {
+            found: AtomicUsize::new(state.found),
+            explored: AtomicUsize::new(state.explored),
+            deadline,
+            canon_cache: canon_cache.clone(),
+            ..SharedState::from_config(config, n, tx, config.property.as_ref(), false)
+        };
+        let visited: Vec<(Family, Option<Vec<Family>>)> = pool.install(|| {
+            batch.into_par_iter().map(|fam| {
+                let children = visit(&fam, &shared);
+                (fam, children)
+            }).collect()
+        });
+
+        // Families skipped after the limit was hit stay pending
+        for (fam, children) in visited {
+            match children {
+                Some(children) => children.into_iter().try_for_each(|child| state.pending.push(child))?,
+                None => state.pending.push(fam)?,
+            }
+        }
+        state.found = written(shared.found.load(Ordering::Relaxed));
+        state.explored = shared.explored.load(Ordering::Relaxed);
+        excluded += shared.excluded.load(Ordering::Relaxed);
+        stopped = shared.stop.load(Ordering::Relaxed);
+        stats.timed_out |= shared.timed_out.load(Ordering::Relaxed);
+        drop(shared);
+
+        // Count only what is written, so a resumed run keeps the right lines
+        let duplicates = stats.duplicates;
+        for fam in rx {
+            if !stats.admit(&fam, n) {
+                continue;
+            }
+            stats.record(&fam);
+            writeln!(w, "{}", config.format.render(&fam, n))?;
+        }
+        state.found -= stats.duplicates - duplicates;
+        w.flush()?;
+        if let Some(ref path) = checkpoint_path {
+            state.save(path).map_err(|e| format!("Error saving checkpoint {}: {}", path, e))?;
+        }
+    }
+    drop(w);
+
+    if config.sort_output {
+        sort_output_file(&outfile_path, config.format.header().is_some())?;
+    }
+    if config.exclude.is_some() {
+        println!("\n  Skipped {} {} already in the excluded corpus.", excluded, search_type);
+    }
+    if config.dedup {
+        println!("\n  Suppressed {} duplicate {}.", stats.duplicates, search_type);
+    }
+    if stats.timed_out {
+        println!("\n  Search stopped: per-size timeout reached, results for n={} are partial.", n);
+    }
+    if config.max_stack.is_some() {
+        println!("\n  Pending stack spilled to disk {} times and reloaded {} times.",
+                 state.pending.spills, state.pending.reloads);
+    }
+    println!("\n  Done. Found {} {}.", state.found, search_type);
+    Ok((state.found, outfile_path, stats))
 }
 
 /// Shared state for parallel execution
 struct SharedState<'a> {
     n: usize,
     search_semiframes: bool,
+    topologies: bool,
     limit: usize,
     log_interval: usize,
     found: AtomicUsize,
@@ -123,86 +817,205 @@ struct SharedState<'a> {
     stop: AtomicBool,
     out_tx: Sender<Family>,
     formula: Option<&'a Formula>,
+    minimal: bool,
+    open_domain: OpenDomain,
+    exclude: Option<&'a HashSet<Family>>,
+    excluded: AtomicUsize,
+    /// Print the progress line on stderr, keeping stdout for the families found
+    progress_to_stderr: bool,
+    /// Stop the search, as if the limit were hit, once this instant has passed
+    deadline: Option<Instant>,
+    /// Set when the search stopped at `deadline`
+    timed_out: AtomicBool,
+    /// Add the empty set to the families accepted (see `Config::include_empty`)
+    include_empty: bool,
+    /// Canonical forms computed while extending families, shared by all the threads
+    canon_cache: Arc<SharedCanonCache>,
 }
 
 impl<'a> SharedState<'a> {
+    /// Fresh counters for a search of size n as `config` describes it, sending the
+    /// families accepted to `out_tx` and keeping only those satisfying `formula`, if
+    /// given (and minimal for it, if `minimal` is set)
+    fn from_config(config: &'a Config, n: usize, out_tx: Sender<Family>, formula: Option<&'a Formula>, minimal: bool) -> Self {
+        Self {
+            n,
+            search_semiframes: config.search_semiframes,
+            topologies: config.topologies,
+            limit: config.limit,
+            log_interval: config.log_interval,
+            found: AtomicUsize::new(0),
+            explored: AtomicUsize::new(0),
+            stop: AtomicBool::new(false),
+            out_tx,
+            formula,
+            minimal,
+            open_domain: config.open_domain,
+            exclude: config.exclude.as_ref(),
+            excluded: AtomicUsize::new(0),
+            progress_to_stderr: false,
+            deadline: config.deadline(),
+            timed_out: AtomicBool::new(false),
+            include_empty: config.include_empty,
+            canon_cache: Arc::new(SharedCanonCache::new(config.cache_size)),
+        }
+    }
+
+    /// Tests one family, sending it on if it is found within the limit. Returns false,
+    /// leaving the family uncounted, if the search stopped before it could be settled:
+    /// either the stop flag was already set, or another thread reached the limit first
     fn try_accept(&self, fam: &Family) -> bool {
         if self.stop.load(Ordering::Relaxed) { return false; }
 
-        let good = if self.search_semiframes {
-            has_all_distinguished(fam, self.n)
-        } else {
-            true
-        };
+        // The search walks all union-closed covers; topologies are filtered here rather
+        // than in `extend_threadsafe`, since the canonical parent of a topology need not
+        // be intersection-closed and pruning there would lose families
+        let good = (!self.search_semiframes || has_all_distinguished(fam, self.n))
+            && (!self.topologies || is_intersection_closed(fam));
 
         if good {
             let mut complete = fam.clone();
-            complete.insert(0);
+            if self.include_empty {
+                complete.insert(0);
+            }
             
             // Check formula if provided
             let formula_ok = if let Some(formula) = self.formula {
-                let mut checker = ModelChecker::new(self.n, complete.clone());
+                let mut checker = ModelChecker::new(self.n, complete.clone()).with_open_domain(self.open_domain);
                 checker.check(formula).satisfied
+                    && (!self.minimal || is_minimal(&complete, self.n, self.search_semiframes, self.topologies, formula, self.open_domain))
             } else {
                 true
             };
             
-            if formula_ok {
+            // Skip families already present in the excluded corpus
+            let is_new = match self.exclude {
+                Some(known) if formula_ok => {
+                    let is_new = !known.contains(&canonicalize_once(&complete, self.n));
+                    if !is_new {
+                        self.excluded.fetch_add(1, Ordering::Relaxed);
+                    }
+                    is_new
+                }
+                _ => true,
+            };
+            
+            if formula_ok && is_new {
                 let new_total = self.found.fetch_add(1, Ordering::Relaxed) + 1;
-                if new_total <= self.limit || self.limit == 0 {
-                    self.out_tx.send(complete).ok();
+                if self.limit != 0 && new_total > self.limit {
+                    // Lost the race for the last place: `found` counts only what was sent
+                    self.found.fetch_sub(1, Ordering::Relaxed);
+                    return false;
                 }
+                self.out_tx.send(complete).ok();
                 if self.limit != 0 && new_total >= self.limit {
                     self.stop.store(true, Ordering::Release);
                 }
             }
         }
-        good
+        true
     }
 }
 
-/// Recursively explores one subtree in the Rayon pool
-fn dfs(fam: Family, shared: Arc<SharedState<'_>>) {
-    if shared.stop.load(Ordering::Acquire) { return; }
+/// Counts and tests one family of the search tree, returning its children, or `None`
+/// without counting it once the search has stopped, so a checkpoint keeps it pending
+fn visit(fam: &Family, shared: &SharedState<'_>) -> Option<Vec<Family>> {
+    if shared.stop.load(Ordering::Acquire) { return None; }
+    if shared.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        shared.timed_out.store(true, Ordering::Relaxed);
+        shared.stop.store(true, Ordering::Release);
+        return None;
+    }
 
     let explored_count = shared.explored.fetch_add(1, Ordering::Relaxed) + 1;
-    shared.try_accept(&fam);
+    if !shared.try_accept(fam) {
+        shared.explored.fetch_sub(1, Ordering::Relaxed);
+        return None;
+    }
 
     // Log progress periodically
     if explored_count % shared.log_interval == 0 {
         let found = shared.found.load(Ordering::Relaxed);
-        print!("\r  Exploring... Total explored: {}. Found so far: {}", explored_count, found);
-        std::io::stdout().flush().ok();
+        if shared.progress_to_stderr {
+            eprint!("\r  Exploring... Total explored: {}. Found so far: {}", explored_count, found);
+        } else {
+            print!("\r  Exploring... Total explored: {}. Found so far: {}", explored_count, found);
+            std::io::stdout().flush().ok();
+        }
     }
 
-    // Produce children inside the current thread, then recurse in parallel
-    let children = extend_threadsafe(&fam, shared.n);
+    Some(extend_threadsafe(fam, shared.n, &shared.canon_cache))
+}
 
-    rayon::scope(|s| {
-        for child in children {
-            let shared = shared.clone();
-            s.spawn(move |_| dfs(child, shared));
+/// Explores the tree below `start` in the given order
+fn explore(start: Family, shared: Arc<SharedState<'_>>, strategy: SearchStrategy) {
+    match strategy {
+        SearchStrategy::Dfs => dfs(start, shared),
+        SearchStrategy::Bfs => bfs(start, &shared),
+    }
+}
+
+/// Explores the tree below `start` level by level, visiting each level in parallel and
+/// reporting the families explored and found at each number of opens
+fn bfs(start: Family, shared: &SharedState<'_>) {
+    let mut level = vec![start];
+    while !level.is_empty() {
+        let opens = level[0].len();
+        let found_before = shared.found.load(Ordering::Relaxed);
+        let next: Vec<Family> = level.par_iter()
+            .filter_map(|fam| visit(fam, shared))
+            .flatten()
+            .collect();
+        let found = shared.found.load(Ordering::Relaxed) - found_before;
+        status!(shared.progress_to_stderr, "\r  Level with {} nonempty opens: {} explored, {} found",
+                opens, level.len(), found);
+        level = next;
+    }
+}
+
+/// Explores one subtree in the Rayon pool using an explicit worklist, so the
+/// depth of the search tree never touches the thread stack
+fn dfs(start: Family, shared: Arc<SharedState<'_>>) {
+    rayon::scope(|s| dfs_worklist(vec![start], &shared, s));
+}
+
+/// Pops families off `stack` depth-first; when other threads are available,
+/// hands the bottom half of the worklist to a new task in the same flat scope
+fn dfs_worklist<'s>(mut stack: Vec<Family>, shared: &'s SharedState<'_>, s: &rayon::Scope<'s>) {
+    let split = rayon::current_num_threads() > 1;
+    while let Some(fam) = stack.pop() {
+        if let Some(children) = visit(&fam, shared) {
+            // Reversed so the first child is the next one popped
+            stack.extend(children.into_iter().rev());
         }
-    });
+        if split && stack.len() > 1 {
+            let rest = stack.split_off(stack.len() / 2);
+            let given = std::mem::replace(&mut stack, rest);
+            s.spawn(move |s| dfs_worklist(given, shared, s));
+        }
+    }
+}
+
+/// Upward-closure test: adding `s` keeps the family closed under unions
+fn can_extend(family: &Family, s: Elem) -> bool {
+    family.iter().all(|&x| family.contains(&(x | s)))
 }
 
-/// Thread-safe version of extend (no cache, matches original logic)
-fn extend_threadsafe(family: &Family, n: usize) -> Vec<Family> {
+/// Thread-safe version of extend, canonicalizing through a cache the threads share
+fn extend_threadsafe(family: &Family, n: usize, cache: &SharedCanonCache) -> Vec<Family> {
     let mut extended = BTreeSet::new();  // Use BTreeSet like the original
 
-    for s_to_add in 1..(1u32 << n) {
+    for s_to_add in 1..=full_set(n) {
         if family.contains(&s_to_add) {
             continue;
         }
 
-        // upward-closure test (as before)
-        if family.iter().all(|&x| family.contains(&(x | s_to_add))) {
+        if can_extend(family, s_to_add) {
             let mut new_family = family.clone();
             new_family.insert(s_to_add);
 
-            let mut cache = HashMap::new();
-            let c_new = canonicalize(&new_family, n, &mut cache, 0);
-            if canonical_delete(&c_new, n, &mut cache, 0) == *family {
+            let c_new = canonicalize_shared(&new_family, n, cache);
+            if canonical_delete_shared(&c_new, n, cache) == *family {
                 extended.insert(c_new);  // duplicates silently ignored
             }
         }
@@ -213,125 +1026,74 @@ fn extend_threadsafe(family: &Family, n: usize) -> Vec<Family> {
 /// Main function to generate all families satisfying a formula for given n
 pub fn gen_fam_with_formula(config: &Config, n: usize, formula: &Formula) -> Result<(usize, usize, String), Box<dyn std::error::Error>> {
     let outfile_path = config.output_pattern.replace("{n}", &n.to_string());
-    let search_type = if config.search_semiframes { "semiframes" } else { "semitopologies" };
+    let search_type = config.search_type();
     
     println!("--- Generating {} satisfying formula for n={} (threads: {}). Writing to {} ---", 
              search_type, n, config.num_threads, outfile_path);
     
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(config.num_threads)
-        .build_global()
-        .map_err(|e| format!("Failed to initialize thread pool: {}", e))?;
+    let pool = build_pool(config)?;
     
     if n == 0 {
         return Ok((0, 0, outfile_path));
     }
 
-    let start_family = if let Some(ref custom_start) = config.starting_family {
-        custom_start.clone()
-    } else {
-        let full_set = (1u32 << n) - 1;
-        let mut family = BTreeSet::new();
-        family.insert(full_set);
-        family
-    };
+    let start_family = start_family(config, n);
     
     println!("  Starting family: {}", family_to_str(&start_family, n));
 
-    let (tx, rx) = unbounded::<Family>();
-    let shared = Arc::new(SharedState {
-        n,
-        search_semiframes: config.search_semiframes,
-        limit: config.limit,
-        log_interval: config.log_interval,
-        found: AtomicUsize::new(0),
-        explored: AtomicUsize::new(0),
-        stop: AtomicBool::new(false),
-        out_tx: tx,
-        formula: Some(formula),
-    });
-
-    let writer_handle = {
-        let path = outfile_path.clone();
-        std::thread::spawn(move || -> std::io::Result<()> {
-            let mut w = BufWriter::new(File::create(path)?);
-            for fam in rx {
-                writeln!(w, "{}", family_to_str(&fam, n))?;
-            }
-            w.flush()
-        })
-    };
-
-    dfs(start_family, shared.clone());
-
-    // read the counters *before* shutting the channel
-    let found = shared.found.load(Ordering::Relaxed);
-    let explored = shared.explored.load(Ordering::Relaxed);
+    let out = Box::new(File::create(&outfile_path)?);
+    let (found, _, stats) = search_into(config, n, start_family, out, false, &pool, Some(formula), config.minimal)?;
 
-    // close the channel: this drops the last Sender
-    drop(shared);
-
-    // writer thread can now finish
-    writer_handle.join().unwrap()?;
+    if config.sort_output {
+        sort_output_file(&outfile_path, config.format.header().is_some())?;
+    }
+    if stats.timed_out {
+        println!("\n  Search stopped: per-size timeout reached, results for n={} are partial.", n);
+    }
     println!("\n  Done. Found {} {} satisfying formula.", found, search_type);
-    Ok((found, explored, outfile_path))
+    Ok((found, stats.explored, outfile_path))
 }
 
 
 
 /// Main function to generate all families satisfying a formula for given n (console output)
 pub fn gen_fam_with_formula_console(config: &Config, n: usize, formula: &Formula, quiet: bool) -> Result<(usize, usize, String), Box<dyn std::error::Error>> {
-    let search_type = if config.search_semiframes { "semiframes" } else { "semitopologies" };
+    let search_type = config.search_type();
     
     println!("--- Streaming {} satisfying formula for n={} (threads: {}) ---", search_type, n, config.num_threads);
     
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(config.num_threads)
-        .build_global()
-        .map_err(|e| format!("Failed to initialize thread pool: {}", e))?;
+    let pool = build_pool(config)?;
     
     if n == 0 {
         return Ok((0, 0, "console".to_string()));
     }
 
-    let start_family = if let Some(ref custom_start) = config.starting_family {
-        custom_start.clone()
-    } else {
-        let full_set = (1u32 << n) - 1;
-        let mut family = BTreeSet::new();
-        family.insert(full_set);
-        family
-    };
+    let start_family = start_family(config, n);
     
     println!("  Starting family: {}", family_to_str(&start_family, n));
     println!();
 
     let (tx, rx) = unbounded::<Family>();
-    let shared = Arc::new(SharedState {
-        n,
-        search_semiframes: config.search_semiframes,
-        limit: config.limit,
-        log_interval: config.log_interval,
-        found: AtomicUsize::new(0),
-        explored: AtomicUsize::new(0),
-        stop: AtomicBool::new(false),
-        out_tx: tx,
-        formula: Some(formula),
-    });
+    let shared = Arc::new(SharedState::from_config(config, n, tx, Some(formula), config.minimal));
 
+    let format = config.format.clone();
+    if let (false, Some(header)) = (quiet, format.header()) {
+        println!("{}", header);
+    }
     let writer_handle = std::thread::spawn(move || {
         for fam in rx {
             if !quiet {
-                println!("{}", family_to_str(&fam, n));
+                println!("{}", format.render(&fam, n));
             }
         }
     });
 
-    dfs(start_family, shared.clone());
+    pool.install(|| explore(start_family, shared.clone(), config.strategy));
 
     // read the counters *before* shutting the channel
     let found = shared.found.load(Ordering::Relaxed);
     let explored = shared.explored.load(Ordering::Relaxed);
+    let timed_out = shared.timed_out.load(Ordering::Relaxed);
 
     // close the channel: this drops the last Sender
     drop(shared);
@@ -339,7 +1101,9 @@ pub fn gen_fam_with_formula_console(config: &Config, n: usize, formula: &Formula
     // writer thread can now finish
     writer_handle.join().unwrap();
     
-    if config.limit != 0 && found >= config.limit {
+    if timed_out {
+        println!("\n  Search stopped: per-size timeout reached, results for n={} are partial.", n);
+    } else if config.limit != 0 && found >= config.limit {
         println!("\n  Search stopped: reached limit of {} families.", config.limit);
     } else {
         println!("\n  Search complete.");
@@ -347,4 +1111,976 @@ pub fn gen_fam_with_formula_console(config: &Config, n: usize, formula: &Formula
     println!("  Done.");
     
     Ok((found, explored, "console".to_string()))
-}
\ No newline at end of file
+}
+
+/// Search for one family satisfying a formula, stopping every thread as soon as it is
+/// found; returns it (with the empty set) and the number of families explored
+pub fn find_first(config: &Config, n: usize, formula: &Formula) -> Result<(Option<Family>, usize), Box<dyn std::error::Error>> {
+    let pool = build_pool(config)?;
+
+    if n == 0 {
+        return Ok((None, 0));
+    }
+
+    // With a limit of 1 only the first family accepted is sent, and it sets `stop`
+    let (tx, rx) = unbounded::<Family>();
+    let shared = Arc::new(SharedState {
+        limit: 1,
+        ..SharedState::from_config(config, n, tx, Some(formula), config.minimal)
+    });
+
+    pool.install(|| explore(start_family(config, n), shared.clone(), config.strategy));
+    let explored = shared.explored.load(Ordering::Relaxed);
+    drop(shared);
+    Ok((rx.try_recv().ok(), explored))
+}
+
+/// Largest n accepted by `random_families` (every step scans the 2^n candidate sets)
+pub const RANDOM_MAX_N: usize = 16;
+
+/// SplitMix64, a small seedable generator so random families need no extra dependency
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound` (the modulo bias is negligible for the small bounds used here)
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+/// Uniform random sample of at most `capacity` families from a stream of unknown length
+/// (reservoir sampling, Algorithm R)
+struct Reservoir {
+    capacity: usize,
+    seen: usize,
+    rng: SplitMix64,
+    families: Vec<Family>,
+}
+
+impl Reservoir {
+    fn new(capacity: usize, seed: u64) -> Self {
+        Self { capacity, seen: 0, rng: SplitMix64(seed), families: Vec::new() }
+    }
+
+    /// The i-th family offered replaces a random sampled one with probability capacity / i
+    fn offer(&mut self, fam: Family) {
+        self.seen += 1;
+        if self.families.len() < self.capacity {
+            self.families.push(fam);
+        } else {
+            let slot = self.rng.below(self.seen);
+            if slot < self.capacity {
+                self.families[slot] = fam;
+            }
+        }
+    }
+
+    /// The sampled families, sorted so the output does not depend on replacement order
+    fn into_sample(mut self) -> Vec<Family> {
+        self.families.sort();
+        self.families
+    }
+}
+
+/// Generate `count` random semitopologies on n points, canonicalized and including `{}`
+///
+/// Each starts from the full set and adds between 1 and 2n sets, each chosen uniformly
+/// among those that keep the family closed under unions (the same test the search
+/// extends by), stopping early if none is left. The result depends only on the seed.
+pub fn random_families(n: usize, count: usize, seed: u64) -> Result<Vec<Family>, String> {
+    if n == 0 || n > RANDOM_MAX_N {
+        return Err(format!("random families need 1 <= n <= {} (got n={})", RANDOM_MAX_N, n));
+    }
+    let mut rng = SplitMix64(seed);
+    let families = (0..count).map(|_| {
+        let mut family = Family::from([full_set(n)]);
+        let target = 1 + rng.below(2 * n);
+        while family.len() < target {
+            let candidates: Vec<Elem> = (1..full_set(n))
+                .filter(|&s| !family.contains(&s) && can_extend(&family, s))
+                .collect();
+            if candidates.is_empty() {
+                break;
+            }
+            family.insert(candidates[rng.below(candidates.len())]);
+        }
+        family.insert(0);
+        canonicalize_once(&family, n)
+    }).collect();
+    Ok(families)
+}
+
+/// Largest n accepted by the brute-force cross-check (2^(2^n - 2) candidate families)
+pub const BRUTE_VERIFY_MAX_N: usize = 4;
+
+/// Collect every family the orderly search finds satisfying a formula, canonicalized
+/// with the empty set included so the result is comparable to `brute_force_families`
+pub fn collect_fam_with_formula(config: &Config, n: usize, formula: &Formula) -> Result<(BTreeSet<Family>, usize), Box<dyn std::error::Error>> {
+    let pool = build_pool(config)?;
+
+    if n == 0 {
+        return Ok((BTreeSet::new(), 0));
+    }
+
+    let (tx, rx) = unbounded::<Family>();
+    let shared = Arc::new(SharedState {
+        limit: 0,
+        log_interval: usize::MAX,
+        ..SharedState::from_config(config, n, tx, Some(formula), config.minimal)
+    });
+
+    pool.install(|| explore(start_family(config, n), shared.clone(), config.strategy));
+
+    let explored = shared.explored.load(Ordering::Relaxed);
+    drop(shared);
+
+    let found = rx.iter().map(|fam| canonicalize_once(&fam, n)).collect();
+    Ok((found, explored))
+}
+
+/// Turn a formula into a file-name-safe name: runs of characters other than ASCII
+/// letters and digits become a single `_`
+pub fn sanitize_formula_name(formula: &str) -> String {
+    let mut name = String::new();
+    for c in formula.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c);
+        } else if !name.is_empty() && !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    let name = name.trim_end_matches('_');
+    if name.is_empty() { "formula".to_string() } else { name.to_string() }
+}
+
+/// Run the search once for size n and check every family against each formula, writing
+/// the satisfying families for formula `name` to the output pattern with `{formula}`
+/// replaced by `name` (and `{n}` by n)
+///
+/// Families are enumerated, and hence canonicalized, only once however many formulas
+/// are given; the enumeration is held in memory while the formulas are checked. Each
+/// output file is sorted, and `limit` (if nonzero) keeps its first `limit` lines.
+/// Returns the number of satisfying families per formula and the number explored.
+pub fn gen_fam_with_formulas(config: &Config, n: usize, formulas: &[(String, Formula)]) -> Result<(Vec<usize>, usize), Box<dyn std::error::Error>> {
+    let search_type = config.search_type();
+    println!("--- Generating {} for {} formulas at n={} (threads: {}) ---",
+             search_type, formulas.len(), n, config.num_threads);
+
+    let pool = build_pool(config)?;
+
+    if n == 0 {
+        return Ok((vec![0; formulas.len()], 0));
+    }
+
+    // The limit applies to each formula's file, once the whole search is done
+    let (tx, rx) = unbounded::<Family>();
+    let shared = Arc::new(SharedState {
+        limit: 0,
+        ..SharedState::from_config(config, n, tx, None, false)
+    });
+
+    pool.install(|| explore(start_family(config, n), shared.clone(), config.strategy));
+
+    let explored = shared.explored.load(Ordering::Relaxed);
+    drop(shared);
+    let families: Vec<Family> = rx.iter().collect();
+
+    let mut counts = Vec::with_capacity(formulas.len());
+    for (name, formula) in formulas {
+        let mut lines: Vec<String> = pool.install(|| {
+            families.par_iter()
+                .filter(|fam| {
+                    let mut checker = ModelChecker::new(n, (*fam).clone()).with_open_domain(config.open_domain);
+                    checker.check(formula).satisfied
+                        && (!config.minimal || is_minimal(fam, n, config.search_semiframes, config.topologies, formula, config.open_domain))
+                })
+                .map(|fam| config.format.render(fam, n))
+                .collect()
+        });
+        lines.sort();
+        counts.push(lines.len());
+        if config.limit != 0 {
+            lines.truncate(config.limit);
+        }
+
+        let outfile_path = config.output_pattern
+            .replace("{formula}", name)
+            .replace("{n}", &n.to_string());
+        let mut w = BufWriter::new(File::create(&outfile_path)?);
+        if let Some(header) = config.format.header() {
+            writeln!(w, "{}", header)?;
+        }
+        for line in &lines {
+            writeln!(w, "{}", line)?;
+        }
+        w.flush()?;
+        println!("  {}: {} {} satisfying formula. Writing to {}", name, counts.last().unwrap(), search_type, outfile_path);
+    }
+
+    println!("\n  Done. Explored {} {}.", explored, search_type);
+    Ok((counts, explored))
+}
+
+/// Independently enumerate every union-closed cover of {1..n} by brute force over the
+/// powerset of nonempty subsets, keeping the canonical forms of those satisfying `formula`
+/// (and, if `minimal` is set, only those that are minimal in the sense of `is_minimal`)
+///
+/// Candidates are checked in parallel on the current rayon pool, each with its own
+/// `ModelChecker`; the result is the same set whatever the number of threads.
+pub fn brute_force_families(n: usize, search_semiframes: bool, topologies: bool, minimal: bool, formula: &Formula, open_domain: OpenDomain) -> BTreeSet<Family> {
+    if n == 0 {
+        return BTreeSet::new();
+    }
+
+    let full_set = full_set(n);
+    // Every cover contains the full set; choose freely among the remaining nonempty subsets
+    let optional: Vec<Elem> = (1..full_set).collect();
+
+    (0u64..(1u64 << optional.len())).into_par_iter().filter_map(|choice| {
+        let mut fam: Family = BTreeSet::new();
+        fam.insert(full_set);
+        for (i, &s) in optional.iter().enumerate() {
+            if (choice >> i) & 1 == 1 {
+                fam.insert(s);
+            }
+        }
+
+        if !is_union_closed(&fam) {
+            return None;
+        }
+        if search_semiframes && !has_all_distinguished(&fam, n) {
+            return None;
+        }
+        if topologies && !is_intersection_closed(&fam) {
+            return None;
+        }
+
+        fam.insert(0);
+        let mut checker = ModelChecker::new(n, fam.clone()).with_open_domain(open_domain);
+        let keep = checker.check(formula).satisfied
+            && (!minimal || is_minimal(&fam, n, search_semiframes, topologies, formula, open_domain));
+        keep.then(|| canonicalize_once(&fam, n))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config(search_semiframes: bool) -> Config {
+        Config {
+            sizes: vec![],
+            limit: 0,
+            output_pattern: String::new(),
+            search_semiframes,
+            topologies: false,
+            starting_family: None,
+            log_interval: usize::MAX,
+            num_threads: 1,
+            cache_size: 10000,
+            largest_open_histogram: false,
+            histogram: false,
+            minimal: false,
+            sort_output: false,
+            exclude: None,
+            open_domain: OpenDomain::Family,
+            checkpoint: None,
+            resume: None,
+            batch_size: 100000,
+            max_stack: None,
+            format: OutputFormat::Text,
+            strategy: SearchStrategy::Dfs,
+            dedup: false,
+            property: None,
+            per_size_timeout: None,
+            sample: None,
+            include_empty: true,
+        }
+    }
+
+    #[test]
+    fn test_brute_force_counts() {
+        // Known counts of semitopologies / semiframes up to isomorphism
+        let formula = parse_formula("AP p. p = p").unwrap();
+        let expected = [(1, 1, 1), (2, 3, 2), (3, 14, 10), (4, 165, 138)];
+        for (n, semitopologies, semiframes) in expected {
+            assert_eq!(brute_force_families(n, false, false, false, &formula, OpenDomain::Family).len(), semitopologies, "n={}", n);
+            assert_eq!(brute_force_families(n, true, false, false, &formula, OpenDomain::Family).len(), semiframes, "n={}", n);
+        }
+    }
+
+    #[test]
+    fn test_topology_counts() {
+        // Known counts of topologies up to homeomorphism (OEIS A001930)
+        let formula = parse_formula("AP p. p = p").unwrap();
+        let mut config = test_config(false);
+        config.topologies = true;
+        for (n, topologies) in [(1, 1), (2, 3), (3, 9), (4, 33)] {
+            let (found, _) = collect_fam_with_formula(&config, n, &formula).unwrap();
+            assert_eq!(found.len(), topologies, "n={}", n);
+            assert_eq!(found, brute_force_families(n, false, true, false, &formula, OpenDomain::Family), "n={}", n);
+        }
+    }
+
+    #[test]
+    fn test_search_matches_brute_force() {
+        for formula_str in ["regular_space", "EP p. irregular p", "EO X. AP p. p in X"] {
+            let formula = parse_formula(formula_str).unwrap();
+            for semiframes in [false, true] {
+                let config = test_config(semiframes);
+                for n in 1..=3 {
+                    let (found, _) = collect_fam_with_formula(&config, n, &formula).unwrap();
+                    let brute = brute_force_families(n, semiframes, false, false, &formula, OpenDomain::Family);
+                    assert_eq!(found, brute, "{} at n={}", formula_str, n);
+                }
+            }
+        }
+
+        // One larger case at the brute-force limit
+        let formula = parse_formula("EP p. irregular p").unwrap();
+        let (found, _) = collect_fam_with_formula(&test_config(false), BRUTE_VERIFY_MAX_N, &formula).unwrap();
+        assert_eq!(found, brute_force_families(BRUTE_VERIFY_MAX_N, false, false, false, &formula, OpenDomain::Family));
+    }
+
+    #[test]
+    fn test_emitted_families_contain_empty_set() {
+        // Search writes families with the empty set added
+        let path = std::env::temp_dir().join(format!("semiframes_empty_test_{}.txt", std::process::id()));
+        let mut config = test_config(false);
+        config.output_pattern = path.to_str().unwrap().to_string();
+        gen_fam(&config, 3).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for line in text.lines() {
+            let (family, _): (Family, _) = crate::canon::parse_family_line(line, Some(3)).unwrap();
+            assert!(crate::canon::has_empty(&family), "{}", line);
+        }
+
+        // Find checks formulas with the empty set present: no family has only nonempty opens
+        let formula = parse_formula("AO X. nonempty X").unwrap();
+        let (found, _) = collect_fam_with_formula(&config, 3, &formula).unwrap();
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn test_sanitize_formula_name() {
+        assert_eq!(sanitize_formula_name("EP p. irregular p"), "EP_p_irregular_p");
+        assert_eq!(sanitize_formula_name("  AO X. (X inter Y)  "), "AO_X_X_inter_Y");
+        assert_eq!(sanitize_formula_name("regular_space"), "regular_space");
+        assert_eq!(sanitize_formula_name("!!"), "formula");
+    }
+
+    #[test]
+    fn test_gen_fam_with_formulas_matches_single_searches() {
+        let dir = std::env::temp_dir();
+        let mut config = test_config(false);
+        config.output_pattern = dir
+            .join(format!("semiframes_formulas_{}_{{formula}}_{{n}}.txt", std::process::id()))
+            .to_str().unwrap().to_string();
+
+        let formulas: Vec<(String, Formula)> = ["regular_space", "EP p. irregular p"]
+            .into_iter()
+            .map(|f| (sanitize_formula_name(f), parse_formula(f).unwrap()))
+            .collect();
+        let (counts, explored) = gen_fam_with_formulas(&config, 3, &formulas).unwrap();
+        assert_eq!(explored, 14);
+
+        for ((name, formula), count) in formulas.iter().zip(counts) {
+            let (expected, _) = collect_fam_with_formula(&config, 3, formula).unwrap();
+            assert_eq!(count, expected.len(), "{}", name);
+
+            let path = config.output_pattern.replace("{formula}", name).replace("{n}", "3");
+            let written = crate::canon::load_canonical_families(&path).unwrap();
+            assert_eq!(written, expected.into_iter().collect::<HashSet<_>>(), "{}", name);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_minimal_families() {
+        // "Some point is outside some nonempty open" is upward-closed; at n=2 it holds
+        // in {{}, {1}, {1, 2}} and {{}, {1}, {2}, {1, 2}}, but only the former is minimal
+        let formula = parse_formula("EP p. EO X. nonempty X && !(p in X)").unwrap();
+        let mut config = test_config(false);
+        let (all, _) = collect_fam_with_formula(&config, 2, &formula).unwrap();
+        assert_eq!(all.len(), 2);
+
+        config.minimal = true;
+        let (minimal, _) = collect_fam_with_formula(&config, 2, &formula).unwrap();
+        let expected: Family = [0b00, 0b01, 0b11].into_iter().collect();
+        assert_eq!(minimal.iter().cloned().collect::<Vec<_>>(), vec![expected]);
+        assert_eq!(minimal, brute_force_families(2, false, false, true, &formula, OpenDomain::Family));
+    }
+
+    #[test]
+    fn test_summary_json() {
+        let runs = [
+            SizeRun { n: 1, found: 1, secs: 0.5 },
+            SizeRun { n: 2, found: 3, secs: 0.25 },
+        ];
+        assert_eq!(
+            summary_json(&runs, 0.75),
+            r#"{"runs": [{"n": 1, "found": 1, "secs": 0.500000}, {"n": 2, "found": 3, "secs": 0.250000}], "total_secs": 0.750000}"#
+        );
+        assert_eq!(summary_json(&[], 0.0), r#"{"runs": [], "total_secs": 0.000000}"#);
+    }
+
+    #[test]
+    fn test_sort_output_is_deterministic() {
+        let dir = std::env::temp_dir();
+        let mut config = test_config(false);
+        config.num_threads = 4;
+        config.sort_output = true;
+
+        let mut contents = Vec::new();
+        for run in 0..2 {
+            let path = dir.join(format!("semiframes_sort_test_{}_{}.txt", std::process::id(), run));
+            config.output_pattern = path.to_str().unwrap().to_string();
+            let (count, _, _) = gen_fam(&config, 4).unwrap();
+            let text = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            let lines: Vec<&str> = text.lines().collect();
+            assert_eq!(lines.len(), count);
+            assert!(lines.windows(2).all(|w| w[0] <= w[1]));
+            contents.push(text);
+        }
+        assert_eq!(contents[0], contents[1]);
+    }
+
+    #[test]
+    fn test_gen_fam_count_independent_of_threads() {
+        // The DFS already spreads subtrees over the pool; the total must not depend on its size
+        let dir = std::env::temp_dir();
+        let mut config = test_config(false);
+        for num_threads in [1, 2, 4, 8] {
+            let path = dir.join(format!("semiframes_threads_test_{}_{}.txt", std::process::id(), num_threads));
+            config.num_threads = num_threads;
+            config.output_pattern = path.to_str().unwrap().to_string();
+            let (count, _, _) = gen_fam(&config, 4).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(count, 165, "threads={}", num_threads);
+        }
+    }
+
+    #[test]
+    fn test_exclude_known_families() {
+        let dir = std::env::temp_dir();
+        let corpus = dir.join(format!("semiframes_exclude_corpus_{}.txt", std::process::id()));
+        let output = dir.join(format!("semiframes_exclude_out_{}.txt", std::process::id()));
+
+        // Build a corpus from the first 5 semitopologies at n=3
+        let mut config = test_config(false);
+        config.limit = 5;
+        config.output_pattern = corpus.to_str().unwrap().to_string();
+        gen_fam(&config, 3).unwrap();
+
+        config.limit = 0;
+        config.output_pattern = output.to_str().unwrap().to_string();
+        config.exclude = Some(crate::canon::load_canonical_families(corpus.to_str().unwrap()).unwrap());
+        let (found, _, _) = gen_fam(&config, 3).unwrap();
+        assert_eq!(found, 14 - 5);
+
+        // Nothing emitted is in the corpus
+        let emitted = crate::canon::load_canonical_families(output.to_str().unwrap()).unwrap();
+        assert!(emitted.is_disjoint(config.exclude.as_ref().unwrap()));
+
+        std::fs::remove_file(&corpus).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_resume_matches_uninterrupted_run() {
+        let dir = std::env::temp_dir();
+        let path = |name: &str| dir.join(format!("semiframes_checkpoint_{}_{}", name, std::process::id())).to_str().unwrap().to_string();
+        let (checkpoint, resumed, uninterrupted) = (path("state.bin"), path("resumed.txt"), path("full.txt"));
+
+        // Stop partway through with a limit, leaving work on the checkpoint's stack
+        let mut config = test_config(false);
+        config.output_pattern = resumed.clone();
+        config.checkpoint = Some(checkpoint.clone());
+        config.batch_size = 5;
+        config.limit = 40;
+        let (found, _, _) = gen_fam(&config, 4).unwrap();
+        assert_eq!(found, 40);
+        let saved = Checkpoint::load(&checkpoint, None).unwrap();
+        assert_eq!((saved.n, saved.found), (4, 40));
+        assert!(!saved.pending.is_empty());
+
+        config.limit = 0;
+        config.resume = Some(checkpoint.clone());
+        let (found, _, _) = gen_fam(&config, 4).unwrap();
+        assert_eq!(found, 165);
+        assert!(Checkpoint::load(&checkpoint, None).unwrap().pending.is_empty());
+
+        let mut config = test_config(false);
+        config.output_pattern = uninterrupted.clone();
+        gen_fam(&config, 4).unwrap();
+
+        let lines = |p: &str| {
+            let mut lines: Vec<String> = std::fs::read_to_string(p).unwrap().lines().map(String::from).collect();
+            lines.sort();
+            lines
+        };
+        assert_eq!(lines(&resumed).len(), 165);
+        assert_eq!(lines(&resumed), lines(&uninterrupted));
+
+        for p in [checkpoint, resumed, uninterrupted] {
+            std::fs::remove_file(p).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_limit_with_threads() {
+        // A family that loses the race for the last place is neither counted nor lost
+        let config = test_config(false);
+        let (tx, rx) = unbounded::<Family>();
+        let shared = SharedState {
+            limit: 1,
+            found: AtomicUsize::new(1),
+            explored: AtomicUsize::new(5),
+            ..SharedState::from_config(&config, 3, tx, None, false)
+        };
+        let fam: Family = [full_set(3)].into_iter().collect();
+        assert_eq!(visit(&fam, &shared), None);
+        assert_eq!(shared.found.load(Ordering::Relaxed), 1);
+        assert_eq!(shared.explored.load(Ordering::Relaxed), 5);
+        drop(shared);
+        assert_eq!(rx.iter().count(), 0);
+
+        let dir = std::env::temp_dir();
+        let path = |name: &str| dir.join(format!("semiframes_checkpoint_threads_{}_{}", name, std::process::id())).to_str().unwrap().to_string();
+        let (checkpoint, output) = (path("state.bin"), path("out.txt"));
+        let mut config = test_config(false);
+        config.output_pattern = output.clone();
+        config.num_threads = 8;
+        config.batch_size = 2000;
+        config.property = Some(parse_property("hypertransitive_space").unwrap());
+        let total = count_fam(&config, 4).unwrap();
+        for round in 0..5 {
+            config.checkpoint = Some(checkpoint.clone());
+            config.resume = None;
+            config.limit = 7;
+            let (found, _, _) = gen_fam(&config, 4).unwrap();
+            let saved = Checkpoint::load(&checkpoint, None).unwrap();
+            let written = std::fs::read_to_string(&output).unwrap().lines().count();
+            assert_eq!((found, saved.found, written), (7, 7, 7), "round {}", round);
+
+            config.limit = 0;
+            config.resume = Some(checkpoint.clone());
+            let (found, _, _) = gen_fam(&config, 4).unwrap();
+            let lines: HashSet<String> = std::fs::read_to_string(&output).unwrap().lines().map(String::from).collect();
+            assert_eq!((found, lines.len()), (total, total), "round {}", round);
+        }
+        for p in [checkpoint, output] {
+            std::fs::remove_file(p).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_pending_stack_spills_in_stack_order() {
+        let fam = |m: Elem| -> Family { [m].into_iter().collect() };
+        let mut stack = PendingStack::new(Some(4));
+        for m in 1..=20 {
+            stack.push(fam(m)).unwrap();
+        }
+        assert_eq!(stack.len(), 20);
+        assert!(stack.spills > 0);
+
+        let mut popped = Vec::new();
+        while !stack.is_empty() {
+            popped.extend(stack.pop_batch(3).unwrap().into_iter().rev());
+        }
+        assert_eq!(popped, (1..=20).rev().map(fam).collect::<Vec<_>>());
+        assert_eq!(stack.reloads, stack.spills);
+    }
+
+    #[test]
+    fn test_max_stack_matches_in_memory_run() {
+        let dir = std::env::temp_dir();
+        let path = |name: &str| dir.join(format!("semiframes_max_stack_{}_{}.txt", name, std::process::id())).to_str().unwrap().to_string();
+
+        let mut config = test_config(false);
+        config.output_pattern = path("memory");
+        let (in_memory, _, _) = gen_fam(&config, 5).unwrap();
+
+        config.output_pattern = path("spilled");
+        config.max_stack = Some(16);
+        config.batch_size = 8;
+        let (spilled, _, _) = gen_fam(&config, 5).unwrap();
+        assert_eq!(spilled, in_memory);
+
+        std::fs::remove_file(path("memory")).unwrap();
+        std::fs::remove_file(path("spilled")).unwrap();
+    }
+
+    #[test]
+    fn test_streamed_lines_match_count() {
+        // Collects what the search writes, standing in for stdout
+        #[derive(Clone, Default)]
+        struct Captured(Arc<std::sync::Mutex<Vec<u8>>>);
+        impl IoWrite for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let config = test_config(true);
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+        let captured = Captured::default();
+        let start: Family = [full_set(4)].into_iter().collect();
+        let (found, _, _) = search_into(&config, 4, start, Box::new(captured.clone()), true, &pool, None, false).unwrap();
+
+        let text = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(found, 138);
+        assert_eq!(text.lines().count(), found);
+        assert!(text.lines().all(|line| parse_family_line::<Elem>(line, Some(4)).is_ok()));
+    }
+
+    #[test]
+    fn test_csv_format() {
+        let columns = ["regular_space", "conflicted_space"]
+            .map(|name| (name.to_string(), parse_formula(name).unwrap()))
+            .to_vec();
+        let format = OutputFormat::Csv(columns);
+        assert_eq!(format.header().unwrap(), "family,opens,regular_space,conflicted_space");
+
+        // Two disjoint points: regular, and no point is conflicted
+        let (fam, n) = parse_family_line::<Elem>("{{}, {1}, {2}, {1, 2}}", None).unwrap();
+        let row = format.render(&fam, n);
+        assert_eq!(row, "\"{{}, {1}, {2}, {1, 2}}\",4,true,false");
+        assert_eq!(format.parse(&row, n).unwrap(), fam);
+        assert_eq!(OutputFormat::Csv(Vec::new()).render(&fam, n), "\"{{}, {1}, {2}, {1, 2}}\",4");
+    }
+
+    #[test]
+    fn test_count_matches_search() {
+        let dir = std::env::temp_dir();
+        for semiframes in [false, true] {
+            let mut config = test_config(semiframes);
+            let path = dir.join(format!("semiframes_count_test_{}_{}.txt", std::process::id(), semiframes));
+            config.output_pattern = path.to_str().unwrap().to_string();
+            let (searched, _, _) = gen_fam(&config, 3).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(count_fam(&config, 3).unwrap(), searched);
+        }
+        assert_eq!(count_fam(&test_config(false), 3).unwrap(), 14);
+    }
+
+    #[test]
+    fn test_bfs_matches_dfs() {
+        for semiframes in [false, true] {
+            let mut config = test_config(semiframes);
+            let dfs_count = count_fam(&config, 4).unwrap();
+            config.strategy = SearchStrategy::Bfs;
+            assert_eq!(count_fam(&config, 4).unwrap(), dfs_count);
+        }
+
+        // The same families, not just the same number
+        let formula = parse_formula("AP p. p = p").unwrap();
+        let mut config = test_config(false);
+        let (dfs_families, _) = collect_fam_with_formula(&config, 4, &formula).unwrap();
+        config.strategy = SearchStrategy::Bfs;
+        let (bfs_families, _) = collect_fam_with_formula(&config, 4, &formula).unwrap();
+        assert_eq!(bfs_families.len(), 165);
+        assert_eq!(bfs_families, dfs_families);
+    }
+
+    #[test]
+    fn test_iterative_dfs_survives_small_stack() {
+        assert_eq!(count_fam(&test_config(false), 5).unwrap(), 14480);
+
+        // The recursive version overflowed a 64 KiB stack at n=5
+        for threads in [1, 4] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .stack_size(64 * 1024)
+                .build()
+                .unwrap();
+            let config = test_config(false);
+            let (tx, rx) = unbounded::<Family>();
+            drop(rx);
+            let shared = Arc::new(SharedState::from_config(&config, 5, tx, None, false));
+            let start: Family = [full_set(5)].into_iter().collect();
+            pool.install(|| dfs(start, shared.clone()));
+            assert_eq!(shared.found.load(Ordering::Relaxed), 14480);
+        }
+    }
+
+    #[test]
+    fn test_dedup_suppresses_isomorphic_families() {
+        let mut config = test_config(false);
+        config.dedup = true;
+
+        // {1} and {2} are relabellings of each other; {1, 2} is not
+        let mut stats = SearchStats::new(&config);
+        let first: Family = [0b001, 0b111].into_iter().collect();
+        let relabelled: Family = [0b010, 0b111].into_iter().collect();
+        let other: Family = [0b011, 0b111].into_iter().collect();
+        assert!(stats.admit(&first, 3));
+        assert!(!stats.admit(&relabelled, 3));
+        assert!(!stats.admit(&first, 3));
+        assert!(stats.admit(&other, 3));
+        assert_eq!(stats.duplicates, 2);
+
+        // Without --dedup nothing is filtered
+        let mut stats = SearchStats::new(&test_config(false));
+        assert!(stats.admit(&first, 3) && stats.admit(&relabelled, 3));
+
+        // The search itself already emits each family once
+        let path = std::env::temp_dir().join(format!("semiframes_dedup_test_{}.txt", std::process::id()));
+        config.output_pattern = path.to_str().unwrap().to_string();
+        let (found, _, stats) = gen_fam(&config, 4).unwrap();
+        let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((found, lines, stats.duplicates), (165, 165, 0));
+    }
+
+    #[test]
+    fn test_histogram_sums_to_count() {
+        let mut config = test_config(false);
+        config.histogram = true;
+        let path = std::env::temp_dir().join(format!("semiframes_histogram_test_{}.txt", std::process::id()));
+        config.output_pattern = path.to_str().unwrap().to_string();
+        let (found, _, stats) = gen_fam(&config, 4).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let hist = stats.opens.unwrap();
+        assert_eq!(found, 165);
+        assert_eq!(hist.values().sum::<usize>(), found);
+        // Only the empty and full sets: {{}, {1, 2, 3, 4}}
+        assert_eq!(hist.first_key_value(), Some((&2, &1)));
+    }
+
+    #[test]
+    fn test_property_matches_formula_search() {
+        let mut config = test_config(false);
+        config.property = Some(parse_property("regular_space").unwrap());
+        let path = std::env::temp_dir().join(format!("semiframes_property_test_{}.txt", std::process::id()));
+        config.output_pattern = path.to_str().unwrap().to_string();
+        let (found, _, _) = gen_fam(&config, 3).unwrap();
+        let searched: BTreeSet<Family> = std::fs::read_to_string(&path).unwrap().lines()
+            .map(|line| parse_family_line(line, Some(3)).unwrap().0)
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+
+        config.property = None;
+        let formula = parse_formula("regular_space").unwrap();
+        let (expected, _) = collect_fam_with_formula(&config, 3, &formula).unwrap();
+        assert_eq!(found, expected.len());
+        assert_eq!(searched, expected);
+        assert!(found > 0 && found < 14);
+
+        assert!(parse_property("regular").unwrap_err().contains("Unknown property 'regular'"));
+    }
+
+    #[test]
+    fn test_per_size_timeout_keeps_partial_results() {
+        let mut config = test_config(false);
+        config.per_size_timeout = Some(Duration::from_millis(20));
+        let path = std::env::temp_dir().join(format!("semiframes_timeout_test_{}.txt", std::process::id()));
+        config.output_pattern = path.to_str().unwrap().to_string();
+
+        // n=6 has over four million semitopologies, far more than 20ms of search
+        let (found, _, stats) = gen_fam(&config, 6).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(stats.timed_out);
+        assert!(found > 0 && found < 1_000_000);
+        assert_eq!(text.lines().count(), found);
+        for line in text.lines() {
+            parse_family_line::<Elem>(line, Some(6)).unwrap();
+        }
+
+        // A size that finishes within its budget is not marked partial
+        config.per_size_timeout = Some(Duration::from_secs(60));
+        let (found, _, stats) = gen_fam(&config, 3).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!stats.timed_out);
+        assert_eq!(found, 14);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_config_serde_round_trip() {
+        let mut config = test_config(true);
+        config.starting_family = Some([0b001, 0b111].into_iter().collect());
+        config.property = Some(parse_property("regular_space").unwrap());
+        config.strategy = SearchStrategy::Bfs;
+        config.per_size_timeout = Some(Duration::from_secs(5));
+        config.exclude = Some(HashSet::new());
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.contains("\"starting_family\":[[1],[1,2,3]]"), "{}", json);
+        assert!(json.contains("\"strategy\":\"bfs\""), "{}", json);
+        let back: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.starting_family, config.starting_family);
+        assert_eq!(back.property, config.property);
+        assert_eq!(back.strategy, SearchStrategy::Bfs);
+        assert_eq!(back.per_size_timeout, config.per_size_timeout);
+        // The excluded corpus is reloaded from its file rather than serialized
+        assert!(back.exclude.is_none());
+    }
+
+    #[test]
+    fn test_random_families() {
+        let families = random_families(5, 20, 42).unwrap();
+        assert_eq!(families.len(), 20);
+        assert_eq!(families, random_families(5, 20, 42).unwrap());
+        assert_ne!(families, random_families(5, 20, 43).unwrap());
+        for family in &families {
+            assert!(family.contains(&0) && family.contains(&full_set(5)));
+            assert!(is_union_closed(family));
+            assert_eq!(*family, canonicalize_once(family, 5));
+        }
+        assert!(random_families(0, 1, 42).is_err());
+        assert!(random_families(RANDOM_MAX_N + 1, 1, 42).is_err());
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut config = test_config(false);
+        config.sample = Some((10, 7));
+        let path = std::env::temp_dir().join(format!("semiframes_sample_test_{}.txt", std::process::id()));
+        config.output_pattern = path.to_str().unwrap().to_string();
+        let sample = |config: &Config| {
+            let (found, _, _) = gen_fam(config, 4).unwrap();
+            (found, std::fs::read_to_string(&path).unwrap())
+        };
+
+        // All 165 families are still counted, but only 10 distinct ones are written
+        let (found, written) = sample(&config);
+        let lines: HashSet<&str> = written.lines().collect();
+        assert_eq!((found, written.lines().count(), lines.len()), (165, 10, 10));
+        assert_eq!(sample(&config).1, written);
+
+        config.sample = Some((200, 7));
+        assert_eq!(sample(&config).1.lines().count(), 165);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut reservoir = Reservoir::new(2, 1);
+        (1..=1000).for_each(|i| reservoir.offer(Family::from([i])));
+        let kept = reservoir.into_sample();
+        assert_eq!(kept.len(), 2);
+        assert!(kept[1] > Family::from([2]), "later families are sampled too");
+    }
+
+    #[test]
+    fn test_find_first() {
+        let config = test_config(false);
+        let formula = parse_formula("regular_space").unwrap();
+        let (family, explored) = find_first(&config, 3, &formula).unwrap();
+        let family = family.expect("a regular semitopology on 3 points");
+        assert!(ModelChecker::new(3, family).check(&formula).satisfied);
+        assert!(explored > 0);
+
+        // Nothing satisfies false, so the whole space is explored
+        let (family, explored) = find_first(&config, 3, &parse_formula("false").unwrap()).unwrap();
+        assert_eq!((family, explored), (None, 14));
+    }
+
+    #[test]
+    fn test_count_fam_with_formula() {
+        let mut config = test_config(false);
+        let formula = parse_formula("regular_space").unwrap();
+        let (found, explored, _) = gen_fam_with_formula_console(&config, 4, &formula, true).unwrap();
+        assert_eq!(count_fam_with_formula(&config, 4, &formula).unwrap(), (found, explored));
+        assert!(found > 1);
+
+        config.minimal = true;
+        let (found, explored, _) = gen_fam_with_formula_console(&config, 4, &formula, true).unwrap();
+        assert_eq!(count_fam_with_formula(&config, 4, &formula).unwrap(), (found, explored));
+    }
+
+    #[test]
+    fn test_negated_formula_finds_violations() {
+        // find --negate searches for !formula
+        let config = test_config(false);
+        let regular = parse_formula("regular_space").unwrap();
+        let (violating, _) = collect_fam_with_formula(&config, 3, &Formula::Not(Box::new(regular.clone()))).unwrap();
+        let (satisfying, _) = collect_fam_with_formula(&config, 3, &regular).unwrap();
+        let (all, _) = collect_fam_with_formula(&config, 3, &Formula::True).unwrap();
+        assert_eq!(violating.len(), 2);
+        assert_eq!(violating, all.difference(&satisfying).cloned().collect());
+
+        // Not the same as irregular_space, which asks for every point to be irregular
+        let (irregular, _) = collect_fam_with_formula(&config, 3, &parse_formula("irregular_space").unwrap()).unwrap();
+        assert!(irregular.is_empty());
+    }
+
+    #[test]
+    fn test_include_empty() {
+        let mut config = test_config(true);
+        let path = std::env::temp_dir().join(format!("semiframes_include_empty_test_{}.txt", std::process::id()));
+        config.output_pattern = path.to_str().unwrap().to_string();
+        let written = |config: &Config| {
+            let (found, _, _) = gen_fam(config, 3).unwrap();
+            let text = std::fs::read_to_string(&path).unwrap();
+            let families: HashSet<Family> = text.lines()
+                .map(|line| crate::canon::parse_family_line(line, Some(3)).unwrap().0)
+                .collect();
+            (found, families)
+        };
+
+        let (found_with, with_empty) = written(&config);
+        config.include_empty = false;
+        let (found_without, without_empty) = written(&config);
+        std::fs::remove_file(&path).unwrap();
+
+        // The same semiframes are found either way, differing only by {}
+        assert_eq!(found_with, found_without);
+        assert!(with_empty.iter().all(|fam| fam.contains(&0)));
+        assert!(without_empty.iter().all(|fam| !fam.contains(&0)));
+        let stripped: HashSet<Family> = with_empty.into_iter()
+            .map(|mut fam| { fam.remove(&0); fam })
+            .collect();
+        assert_eq!(stripped, without_empty);
+    }
+
+    #[test]
+    fn test_brute_force_families_parallel_matches_sequential() {
+        let formula = parse_formula("regular_space").unwrap();
+        let on_threads = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| brute_force_families(4, false, false, false, &formula, OpenDomain::Family))
+        };
+        let sequential = on_threads(1);
+        assert!(!sequential.is_empty());
+        assert_eq!(on_threads(4), sequential);
+    }
+
+    #[test]
+    fn test_extend_through_shared_canon_cache() {
+        // Two levels of the tree for n=4, extended through a shared cache and without one
+        let n = 4;
+        let cache = SharedCanonCache::new(10000);
+        let uncached = SharedCanonCache::new(0);
+        let root: Family = [full_set(n)].into_iter().collect();
+        let children = extend_threadsafe(&root, n, &cache);
+        assert_eq!(children, extend_threadsafe(&root, n, &uncached));
+        for child in &children {
+            assert_eq!(extend_threadsafe(child, n, &cache), extend_threadsafe(child, n, &uncached));
+        }
+        assert!(cache.hits() > 0);
+        assert!(uncached.is_empty());
+
+        // Counts do not depend on the cache, however many threads share it
+        let mut config = test_config(false);
+        config.num_threads = 4;
+        let with_cache = count_fam(&config, n).unwrap();
+        config.cache_size = 0;
+        assert_eq!(count_fam(&config, n).unwrap(), with_cache);
+        assert_eq!(with_cache, 165);
+    }
+}
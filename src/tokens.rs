@@ -1,10 +1,13 @@
 //! Lexical tokens for semitopology formulas using Logos
 //!
 //! This module defines all tokens recognized by the lexer, including:
-//! - Logical operators with proper precedence
+//! - Logical operators with proper precedence, in ASCII or Unicode (`∧`, `→`, ...)
 //! - Quantifiers for points and opens  
 //! - Built-in predicates and macros
 //! - Variables distinguished by case (lowercase=points, uppercase=opens)
+//! - Point literals naming concrete points by number
+//! - `def` for user definitions
+//! - `//` line comments and `/* ... */` block comments, skipped like whitespace
 
 use logos::Logos;
 
@@ -16,26 +19,39 @@ use logos::Logos;
 pub enum Token {
     // Logical operators
     #[token("&&")]
+    #[token("∧")]
     And,
     
     #[token("||")]
+    #[token("∨")]
     Or,
     
     #[token("=>")]
+    #[token("→")]
+    #[token("⇒")]
     Implies,
     
     #[token("!")]
+    #[token("¬")]
     Not,
     
     #[token("!=")]
+    #[token("≠")]
     NotEqual,
     
     #[token("=")]
     Equal,
     
     #[token("<=>")]
+    #[token("↔")]
+    #[token("⇔")]
     Iff,
     
+    #[token("^^")]
+    #[token("xor")]
+    #[token("⊕")]
+    Xor,
+    
     // Quantifiers
     #[token("AP")]
     AP,
@@ -49,22 +65,74 @@ pub enum Token {
     #[token("EO")]
     EO,
     
+    #[token("AT")]
+    AT,
+    
+    #[token("ET")]
+    ET,
+    
+    // Unicode quantifiers: AP/AO (resp. EP/EO) by the case of the bound variable
+    #[token("∀")]
+    ForAll,
+    
+    #[token("∃")]
+    Exists,
+    
+    // Truth constants
+    #[token("true")]
+    True,
+    
+    #[token("false")]
+    False,
+    
     // Primitives
     #[token("in")]
+    #[token("∈")]
     In,
     
     #[token("inter")]
+    #[token("≬")]
     Inter,
     
+    #[token("union")]
+    #[token("∪")]
+    Union,
+    
+    #[token("cap")]
+    #[token("∩")]
+    Cap,
+    
+    #[token("subseteq")]
+    #[token("⊆")]
+    Subseteq,
+    
     #[token("nonempty")]
     Nonempty,
     
+    #[token("is_topen")]
+    IsTopen,
+    
     #[token("K", priority = 2)]
     K,
     
     #[token("IC")]
     IC,
     
+    #[token("Cl")]
+    Cl,
+    
+    #[token("Bd")]
+    Bd,
+    
+    #[token("MON")]
+    Mon,
+    
+    #[token("UNIV")]
+    Univ,
+    
+    #[token("EMPTY")]
+    Empty,
+    
     // Built-in macro keywords
     #[token("transitive")]
     Transitive,
@@ -130,6 +198,20 @@ pub enum Token {
     #[regex(r"[A-Z][a-zA-Z0-9_]*", priority = 1, callback = |lex| lex.slice().to_owned())]
     OpenVar(String),
     
+    // Point literals: a concrete point by its 1-based number (1, 2, ...)
+    #[regex(r"[0-9]+", |lex| lex.slice().parse::<usize>().ok())]
+    PointLit(usize),
+    
+    // User definitions: def name(p, X) := ...;
+    #[token("def")]
+    Def,
+    
+    #[token(":=")]
+    Define,
+    
+    #[token(";")]
+    Semicolon,
+    
     // Punctuation
     #[token(".")]
     Dot,
@@ -140,13 +222,43 @@ pub enum Token {
     #[token(")")]
     RightParen,
     
-    // Whitespace is skipped during lexing
+    #[token("{")]
+    LeftBrace,
+    
+    #[token("}")]
+    RightBrace,
+    
+    #[token(",")]
+    Comma,
+    
+    // Whitespace and comments are skipped during lexing. Block comments do not nest:
+    // one ends at the first `*/`, so the rest of a nested one is lexed (and rejected),
+    // and an unterminated `/*` is a lexer error rather than swallowing the input.
     #[regex(r"[ \t\r\n]+", logos::skip)]
+    #[regex(r"//[^\n]*", logos::skip)]
+    #[token("/*", block_comment)]
     Error,
 }
 
+/// Skip the rest of a block comment after its `/*`, failing if it is never closed
+fn block_comment(lex: &mut logos::Lexer<Token>) -> logos::FilterResult<(), ()> {
+    match lex.remainder().find("*/") {
+        Some(end) => {
+            lex.bump(end + 2);
+            logos::FilterResult::Skip
+        }
+        None => logos::FilterResult::Error(()),
+    }
+}
+
 pub type Spanned<Tok, Loc, Error> = Result<(Loc, Tok, Loc), Error>;
 
+/// A lexer error: no token starts at this byte offset of the input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LexError {
+    pub offset: usize,
+}
+
 pub struct Lexer<'input> {
     token_stream: logos::SpannedIter<'input, Token>,
 }
@@ -160,13 +272,13 @@ impl<'input> Lexer<'input> {
 }
 
 impl<'input> Iterator for Lexer<'input> {
-    type Item = Spanned<Token, usize, &'static str>;
+    type Item = Spanned<Token, usize, LexError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.token_stream.next().map(|(token, span)| {
             match token {
                 Ok(token) => Ok((span.start, token, span.end)),
-                Err(()) => Err("Lexer error"),
+                Err(()) => Err(LexError { offset: span.start }),
             }
         })
     }
@@ -0,0 +1,22 @@
+//! Exit codes of the `check` command, which scripts rely on
+
+use std::process::Command;
+
+fn check(args: &[&str]) -> Option<i32> {
+    Command::new(env!("CARGO_BIN_EXE_semiframes"))
+        .arg("check")
+        .args(args)
+        .output()
+        .expect("failed to run semiframes")
+        .status
+        .code()
+}
+
+#[test]
+fn check_exit_codes() {
+    let family = "{{}, {1}, {1, 2}}";
+    assert_eq!(check(&["-f", "EO X. nonempty X", "-s", family]), Some(0));
+    assert_eq!(check(&["-f", "AO X. nonempty X", "-s", family]), Some(1));
+    assert_eq!(check(&["-f", "EO X. nonempty", "-s", family]), Some(2));
+    assert_eq!(check(&["-f", "true", "-s", "{{1} x}"]), Some(2));
+}